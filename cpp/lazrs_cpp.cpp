@@ -52,6 +52,11 @@ void LasZipDecompressor::decompress_many(uint8_t *out, size_t len, Lazrs_Result
     result = lazrs_decompressor_decompress_many(m_decompressor.get(), out, len);
 }
 
+bool LasZipDecompressor::is_parallel() const
+{
+    return lazrs_decompressor_is_parallel(m_decompressor.get());
+}
+
 void LasZipCompressor::compress_one(uint8_t *in, size_t len, Lazrs_Result &result)
 {
     result = lazrs_compressor_compress_one(m_compressor.get(), in, len);
@@ -78,4 +83,9 @@ void LasZipCompressor::compress_many(uint8_t *in, size_t len)
     }
 }
 
+bool LasZipCompressor::is_parallel() const
+{
+    return lazrs_compressor_is_parallel(m_compressor.get());
+}
+
 } // namespace lazrs
\ No newline at end of file
@@ -19,6 +19,8 @@ class LasZipDecompressor
     void decompress_many(uint8_t *out, size_t len, Lazrs_Result &result);
     void decompress_many(uint8_t *out, size_t len);
 
+    bool is_parallel() const;
+
   private:
     using Lazrs_LasZipDecompressorPtr =
         std::unique_ptr<Lazrs_LasZipDecompressor, decltype(&lazrs_decompressor_delete)>;
@@ -33,6 +35,8 @@ class LasZipCompressor
     void compress_many(uint8_t *out, size_t len, Lazrs_Result &result);
     void compress_many(uint8_t *out, size_t len);
 
+    bool is_parallel() const;
+
   private:
     using Lazrs_LasZipCompressorPtr =
         std::unique_ptr<Lazrs_LasZipCompressor, void (*)(Lazrs_LasZipCompressor *)>;
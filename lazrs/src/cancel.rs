@@ -0,0 +1,300 @@
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::Lazrs_Result;
+
+/// Number of points processed between two checks of the token
+/// when the chunk size is not fixed.
+pub(crate) const DEFAULT_POINTS_PER_STEP: usize = 50_000;
+
+/// Token used to cancel an operation that is in progress.
+///
+/// The token can be attached to one or more decompressors / compressors,
+/// they check it between each chunk they process.
+pub struct Lazrs_CancelToken(pub(crate) Arc<AtomicBool>);
+
+impl Lazrs_CancelToken {
+    pub(crate) fn flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.0)
+    }
+}
+
+/// Returns the number of points to process between two checks of a token.
+///
+/// `parallel` tells if the points are processed by a multi-threaded (de)compressor,
+/// in which case we give it enough chunks to keep all the threads busy.
+pub(crate) fn points_per_step(vlr: &laz::LazVlr, parallel: bool) -> usize {
+    let chunk_size = if vlr.uses_variable_size_chunks() {
+        DEFAULT_POINTS_PER_STEP
+    } else {
+        vlr.chunk_size() as usize
+    };
+
+    #[cfg(feature = "parallel")]
+    if parallel {
+        return chunk_size * rayon::current_num_threads();
+    }
+    let _ = parallel;
+    chunk_size
+}
+
+/// Calls `f` with successive ranges of at most `step` elements covering `0..len`.
+///
+/// The token (if any) is checked before each call, `LAZRS_CANCELLED` is returned
+/// as soon as it is set. Stops at the first call that does not return `LAZRS_OK`.
+pub(crate) fn run_in_steps<F>(
+    len: usize,
+    step: usize,
+    token: Option<&Arc<AtomicBool>>,
+    mut f: F,
+) -> Lazrs_Result
+where
+    F: FnMut(Range<usize>) -> Lazrs_Result,
+{
+    let step = step.max(1);
+    let mut start = 0;
+    while start < len {
+        if token.is_some_and(|token| token.load(Ordering::Relaxed)) {
+            return Lazrs_Result::LAZRS_CANCELLED;
+        }
+        let end = (start + step).min(len);
+        let result = f(start..end);
+        if result != Lazrs_Result::LAZRS_OK {
+            return result;
+        }
+        start = end;
+    }
+    Lazrs_Result::LAZRS_OK
+}
+
+/// Creates a new token, initially not cancelled.
+///
+/// The token must be freed with `lazrs_cancel_token_delete`.
+#[no_mangle]
+pub extern "C" fn lazrs_cancel_token_new() -> *mut Lazrs_CancelToken {
+    Box::into_raw(Box::new(Lazrs_CancelToken(Arc::new(AtomicBool::new(
+        false,
+    )))))
+}
+
+/// Requests the cancellation of the operations running with this token.
+///
+/// This can be called from any thread.
+///
+/// @token: must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_cancel_token_cancel(token: *const Lazrs_CancelToken) {
+    debug_assert!(!token.is_null());
+    (*token).0.store(true, Ordering::Relaxed);
+}
+
+/// Returns whether the cancellation was requested.
+///
+/// @token: must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_cancel_token_is_cancelled(token: *const Lazrs_CancelToken) -> bool {
+    debug_assert!(!token.is_null());
+    (*token).0.load(Ordering::Relaxed)
+}
+
+/// Puts the token back in its non-cancelled state, so that it can be reused.
+///
+/// @token: must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_cancel_token_reset(token: *const Lazrs_CancelToken) {
+    debug_assert!(!token.is_null());
+    (*token).0.store(false, Ordering::Relaxed);
+}
+
+/// Frees the token.
+///
+/// (De)compressors the token was attached to keep working and
+/// are simply no longer cancellable through it.
+///
+/// @token can be NULL (no-op)
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_cancel_token_delete(token: *mut Lazrs_CancelToken) {
+    if !token.is_null() {
+        let _ = Box::from_raw(token);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::c_void;
+    use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+    use crate::chunks::test_data::{compressed, points, vlr, POINT_SIZE};
+    use crate::io::{CDest, CFile, CSource, CustomSource};
+    use crate::{
+        lazrs_compressor_compress_many, lazrs_compressor_done, lazrs_compressor_position,
+        lazrs_compressor_set_cancel_token, lazrs_decompressor_decompress_many,
+        lazrs_decompressor_position, lazrs_decompressor_set_cancel_token, Compressor, Decompressor,
+        Lazrs_LasZipCompressor, Lazrs_LasZipDecompressor,
+    };
+
+    const HEADER: &[u8] = b"LASF";
+
+    /// Reads from memory, and cancels the token on the first read once armed
+    struct CancellingSource {
+        data: Cursor<Vec<u8>>,
+        token: Arc<AtomicBool>,
+        armed: bool,
+    }
+
+    unsafe extern "C" fn read_fn(user_data: *mut c_void, n: u64, out: *mut u8) -> u64 {
+        let source = &mut *(user_data as *mut CancellingSource);
+        if source.armed {
+            source.token.store(true, Ordering::Relaxed);
+        }
+        let out = std::slice::from_raw_parts_mut(out, n as usize);
+        source.data.read(out).unwrap() as u64
+    }
+
+    unsafe extern "C" fn seek_fn(
+        user_data: *mut c_void,
+        pos: i64,
+        whence: libc::c_int,
+    ) -> libc::c_int {
+        let source = &mut *(user_data as *mut CancellingSource);
+        let pos = match whence {
+            libc::SEEK_SET => SeekFrom::Start(pos as u64),
+            libc::SEEK_CUR => SeekFrom::Current(pos),
+            _ => SeekFrom::End(pos),
+        };
+        match source.data.seek(pos) {
+            Ok(_) => 0,
+            Err(_) => -1,
+        }
+    }
+
+    unsafe extern "C" fn tell_fn(user_data: *mut c_void) -> u64 {
+        (*(user_data as *mut CancellingSource)).data.position()
+    }
+
+    #[test]
+    fn steps_stop_once_cancelled() {
+        let token = Arc::new(AtomicBool::new(false));
+        let mut ranges = vec![];
+        let result = run_in_steps(10, 4, Some(&token), |range| {
+            ranges.push(range);
+            token.store(true, Ordering::Relaxed);
+            Lazrs_Result::LAZRS_OK
+        });
+        assert_eq!(result, Lazrs_Result::LAZRS_CANCELLED);
+        assert_eq!(ranges, vec![0..4]);
+
+        token.store(false, Ordering::Relaxed);
+        ranges.clear();
+        let result = run_in_steps(10, 4, Some(&token), |range| {
+            ranges.push(range);
+            Lazrs_Result::LAZRS_OK
+        });
+        assert_eq!(result, Lazrs_Result::LAZRS_OK);
+        assert_eq!(ranges, vec![0..4, 4..8, 8..10]);
+
+        // The steps are the same without a token
+        ranges.clear();
+        let result = run_in_steps(10, 4, None, |range| {
+            ranges.push(range);
+            Lazrs_Result::LAZRS_OK
+        });
+        assert_eq!(result, Lazrs_Result::LAZRS_OK);
+        assert_eq!(ranges, vec![0..4, 4..8, 8..10]);
+    }
+
+    #[test]
+    fn cancelled_decompression_tells_how_many_points_were_decompressed() {
+        let points = points(25);
+
+        let token = Lazrs_CancelToken(Arc::new(AtomicBool::new(false)));
+        let mut source = Box::new(CancellingSource {
+            data: Cursor::new(compressed(&vlr(), 25)),
+            token: token.flag(),
+            armed: false,
+        });
+        let custom = CustomSource {
+            user_data: &mut *source as *mut CancellingSource as *mut c_void,
+            read_fn,
+            seek_fn,
+            tell_fn,
+        };
+        let decompressor = laz::LasZipDecompressor::new(CSource::Custom(custom), vlr()).unwrap();
+        let mut decompressor =
+            Lazrs_LasZipDecompressor::new(Decompressor::sequential(decompressor), vlr());
+
+        let mut out = vec![0u8; points.len()];
+        unsafe {
+            lazrs_decompressor_set_cancel_token(&mut decompressor, &token);
+            // The token gets cancelled while the first chunk is decompressed
+            source.armed = true;
+            let result =
+                lazrs_decompressor_decompress_many(&mut decompressor, out.as_mut_ptr(), out.len());
+            assert_eq!(result, Lazrs_Result::LAZRS_CANCELLED);
+            assert_eq!(lazrs_decompressor_position(&decompressor), 10);
+        }
+        assert_eq!(out[..10 * POINT_SIZE], points[..10 * POINT_SIZE]);
+
+        // The decompression goes on from where it stopped
+        source.armed = false;
+        token.0.store(false, Ordering::Relaxed);
+        unsafe {
+            let rest = &mut out[10 * POINT_SIZE..];
+            let result = lazrs_decompressor_decompress_many(
+                &mut decompressor,
+                rest.as_mut_ptr(),
+                rest.len(),
+            );
+            assert_eq!(result, Lazrs_Result::LAZRS_OK);
+            assert_eq!(lazrs_decompressor_position(&decompressor), 25);
+        }
+        assert_eq!(out, points);
+    }
+
+    #[test]
+    fn cancelled_compression_tells_how_many_points_were_compressed() {
+        let points = points(25);
+        let fh = unsafe { libc::tmpfile() };
+        assert!(!fh.is_null());
+        let mut file = unsafe { CFile::new_unchecked(fh) };
+        // Stands in for the LAS header, the points do not start at the beginning of the file
+        file.write_all(HEADER).unwrap();
+        let compressor = laz::LasZipCompressor::new(CDest::CFile(file), vlr()).unwrap();
+        let mut compressor = Lazrs_LasZipCompressor::new(Compressor::sequential(compressor));
+
+        let token = Lazrs_CancelToken(Arc::new(AtomicBool::new(false)));
+        unsafe {
+            lazrs_compressor_set_cancel_token(&mut compressor, &token);
+            let first = &points[..15 * POINT_SIZE];
+            let result =
+                lazrs_compressor_compress_many(&mut compressor, first.as_ptr(), first.len());
+            assert_eq!(result, Lazrs_Result::LAZRS_OK);
+
+            token.0.store(true, Ordering::Relaxed);
+            let rest = &points[15 * POINT_SIZE..];
+            let result = lazrs_compressor_compress_many(&mut compressor, rest.as_ptr(), rest.len());
+            assert_eq!(result, Lazrs_Result::LAZRS_CANCELLED);
+            assert_eq!(lazrs_compressor_position(&compressor), 15);
+            assert_eq!(
+                lazrs_compressor_done(&mut compressor),
+                Lazrs_Result::LAZRS_OK
+            );
+        }
+
+        // The data only has the points compressed before the cancellation
+        unsafe { libc::rewind(fh) };
+        let mut file = unsafe { CFile::new_unchecked(fh) };
+        let mut laz_data = vec![];
+        file.read_to_end(&mut laz_data).unwrap();
+        unsafe { libc::fclose(fh) };
+        let mut out = vec![0u8; 15 * POINT_SIZE];
+        assert_eq!(laz_data[..HEADER.len()], *HEADER);
+        let mut source = Cursor::new(laz_data);
+        source.seek(SeekFrom::Start(HEADER.len() as u64)).unwrap();
+        let mut decompressor = laz::LasZipDecompressor::new(source, vlr()).unwrap();
+        decompressor.decompress_many(&mut out).unwrap();
+        assert_eq!(out, points[..15 * POINT_SIZE]);
+    }
+}
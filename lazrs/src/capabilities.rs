@@ -0,0 +1,167 @@
+//! Functions to query what this build of lazrs supports.
+
+/// Type codes of the LAZ items, as stored in the LASzip VLR
+pub const LAZRS_ITEM_BYTE: u16 = 0;
+pub const LAZRS_ITEM_POINT10: u16 = 6;
+pub const LAZRS_ITEM_GPS_TIME: u16 = 7;
+pub const LAZRS_ITEM_RGB12: u16 = 8;
+pub const LAZRS_ITEM_POINT14: u16 = 10;
+pub const LAZRS_ITEM_RGB14: u16 = 11;
+pub const LAZRS_ITEM_RGBNIR14: u16 = 12;
+pub const LAZRS_ITEM_BYTE14: u16 = 14;
+
+/// Range of compression versions supported for a type of LAZ item
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Lazrs_ItemVersions {
+    /// One of the LAZRS_ITEM_* codes
+    item_type: u16,
+    pub(crate) min_version: u16,
+    pub(crate) max_version: u16,
+}
+
+static VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "\0");
+/// The version of laz is pinned in Cargo.toml, they must be kept in sync
+static LAZ_VERSION: &str = "0.7.0\0";
+
+static SUPPORTED_POINT_FORMATS: [u8; 7] = [0, 1, 2, 3, 6, 7, 8];
+
+static SUPPORTED_ITEMS: [Lazrs_ItemVersions; 8] = [
+    Lazrs_ItemVersions {
+        item_type: LAZRS_ITEM_BYTE,
+        min_version: 1,
+        max_version: 2,
+    },
+    Lazrs_ItemVersions {
+        item_type: LAZRS_ITEM_POINT10,
+        min_version: 1,
+        max_version: 2,
+    },
+    Lazrs_ItemVersions {
+        item_type: LAZRS_ITEM_GPS_TIME,
+        min_version: 1,
+        max_version: 2,
+    },
+    Lazrs_ItemVersions {
+        item_type: LAZRS_ITEM_RGB12,
+        min_version: 1,
+        max_version: 2,
+    },
+    Lazrs_ItemVersions {
+        item_type: LAZRS_ITEM_POINT14,
+        min_version: 3,
+        max_version: 3,
+    },
+    Lazrs_ItemVersions {
+        item_type: LAZRS_ITEM_RGB14,
+        min_version: 3,
+        max_version: 3,
+    },
+    Lazrs_ItemVersions {
+        item_type: LAZRS_ITEM_RGBNIR14,
+        min_version: 3,
+        max_version: 3,
+    },
+    Lazrs_ItemVersions {
+        item_type: LAZRS_ITEM_BYTE14,
+        min_version: 3,
+        max_version: 3,
+    },
+];
+
+/// Returns the version of lazrs, as a NUL-terminated string (e.g "0.1.0")
+#[no_mangle]
+pub extern "C" fn lazrs_version() -> *const libc::c_char {
+    VERSION.as_ptr() as *const libc::c_char
+}
+
+/// Returns the version of the laz crate lazrs was built with,
+/// as a NUL-terminated string (e.g "0.7.0")
+#[no_mangle]
+pub extern "C" fn lazrs_laz_version() -> *const libc::c_char {
+    LAZ_VERSION.as_ptr() as *const libc::c_char
+}
+
+/// Returns whether lazrs was built with the `parallel` feature,
+/// that is, if the multi-threaded decompressors / compressors are available
+#[no_mangle]
+pub extern "C" fn lazrs_has_parallel() -> bool {
+    cfg!(feature = "parallel")
+}
+
+/// Returns the list of point format ids that can be compressed with a default
+/// LASzip VLR (`lazrs_compressor_new_for_point_format`)
+///
+/// @count: will receive the number of elements in the list
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_supported_point_formats(count: *mut usize) -> *const u8 {
+    debug_assert!(!count.is_null());
+    *count = SUPPORTED_POINT_FORMATS.len();
+    SUPPORTED_POINT_FORMATS.as_ptr()
+}
+
+/// Returns the list of LAZ items types and the compression versions supported for each of them
+///
+/// @count: will receive the number of elements in the list
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_supported_items(count: *mut usize) -> *const Lazrs_ItemVersions {
+    debug_assert!(!count.is_null());
+    *count = SUPPORTED_ITEMS.len();
+    SUPPORTED_ITEMS.as_ptr()
+}
+
+/// Returns the range of versions supported for the item type, if it is known
+pub(crate) fn item_versions(item_type: u16) -> Option<&'static Lazrs_ItemVersions> {
+    SUPPORTED_ITEMS
+        .iter()
+        .find(|item| item.item_type == item_type)
+}
+
+/// Returns whether the given compression version of the given item type is supported
+#[no_mangle]
+pub extern "C" fn lazrs_is_item_supported(item_type: u16, version: u16) -> bool {
+    item_versions(item_type)
+        .is_some_and(|item| item.min_version <= version && version <= item.max_version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn laz_version_is_the_pinned_one() {
+        let version = unsafe { CStr::from_ptr(lazrs_laz_version()) }
+            .to_str()
+            .unwrap();
+        let pin = format!("laz = {{ version = \"={}\" }}", version);
+        assert!(include_str!("../Cargo.toml").contains(&pin), "{}", pin);
+    }
+
+    #[test]
+    fn default_items_of_supported_formats_are_supported() {
+        let mut count = 0;
+        let formats =
+            unsafe { std::slice::from_raw_parts(lazrs_supported_point_formats(&mut count), count) };
+        for &format in formats {
+            let items = laz::LazItemRecordBuilder::default_for_point_format_id(format, 2).unwrap();
+            for item in items {
+                assert!(
+                    lazrs_is_item_supported(item.item_type().into(), item.version()),
+                    "{:?} of format {}",
+                    item,
+                    format
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn item_versions_are_checked() {
+        assert!(lazrs_is_item_supported(LAZRS_ITEM_POINT10, 2));
+        assert!(!lazrs_is_item_supported(LAZRS_ITEM_POINT10, 3));
+        assert!(lazrs_is_item_supported(LAZRS_ITEM_POINT14, 3));
+        assert!(!lazrs_is_item_supported(LAZRS_ITEM_POINT14, 2));
+        assert!(!lazrs_is_item_supported(42, 1));
+    }
+}
@@ -0,0 +1,480 @@
+//! Extents of the chunks, computed while compressing,
+//! and their storage in a VLR to build spatial indexes without decompressing.
+
+use std::convert::TryInto;
+
+use crate::point::{Lazrs_Point, PointFormat};
+use crate::{Lazrs_LasZipCompressor, Lazrs_Result};
+
+/// User id of the VLR (or EVLR) holding the chunk bounds
+pub(crate) const CHUNK_BOUNDS_USER_ID: &str = "lazrs";
+/// Record id of the VLR (or EVLR) holding the chunk bounds,
+/// its user id is "lazrs"
+pub const LAZRS_CHUNK_BOUNDS_RECORD_ID: u16 = 100;
+
+/// Version of the format of the VLR data
+const VLR_DATA_VERSION: u16 = 1;
+const VLR_HEADER_SIZE: usize = 2 + 8;
+const VLR_ENTRY_SIZE: usize = 8 + 6 * 4 + 2 * 8;
+
+/// Extents of the points of a chunk, or of all the points in `Lazrs_PointStats`
+///
+/// The coordinates are the ones stored (before applying the scales
+/// and offsets of the header). Everything is 0 for empty chunks.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Lazrs_ChunkBounds {
+    pub(crate) point_count: u64,
+    pub(crate) min_x: i32,
+    pub(crate) min_y: i32,
+    pub(crate) min_z: i32,
+    pub(crate) max_x: i32,
+    pub(crate) max_y: i32,
+    pub(crate) max_z: i32,
+    /// 0 if the point format has no GPS time
+    pub(crate) min_gps_time: f64,
+    pub(crate) max_gps_time: f64,
+}
+
+impl Lazrs_ChunkBounds {
+    pub(crate) fn add(&mut self, point: &Lazrs_Point) {
+        if self.point_count == 0 {
+            self.min_x = point.x;
+            self.min_y = point.y;
+            self.min_z = point.z;
+            self.max_x = point.x;
+            self.max_y = point.y;
+            self.max_z = point.z;
+            self.min_gps_time = point.gps_time;
+            self.max_gps_time = point.gps_time;
+        } else {
+            self.min_x = self.min_x.min(point.x);
+            self.min_y = self.min_y.min(point.y);
+            self.min_z = self.min_z.min(point.z);
+            self.max_x = self.max_x.max(point.x);
+            self.max_y = self.max_y.max(point.y);
+            self.max_z = self.max_z.max(point.z);
+            self.min_gps_time = self.min_gps_time.min(point.gps_time);
+            self.max_gps_time = self.max_gps_time.max(point.gps_time);
+        }
+        self.point_count += 1;
+    }
+
+    fn write_to(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.point_count.to_le_bytes());
+        for value in [
+            self.min_x, self.min_y, self.min_z, self.max_x, self.max_y, self.max_z,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&self.min_gps_time.to_le_bytes());
+        data.extend_from_slice(&self.max_gps_time.to_le_bytes());
+    }
+
+    fn read_from(data: &[u8]) -> Self {
+        let i32_at =
+            |offset: usize| i32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+        Self {
+            point_count: u64::from_le_bytes(data[0..8].try_into().unwrap()),
+            min_x: i32_at(8),
+            min_y: i32_at(12),
+            min_z: i32_at(16),
+            max_x: i32_at(20),
+            max_y: i32_at(24),
+            max_z: i32_at(28),
+            min_gps_time: f64::from_le_bytes(data[32..40].try_into().unwrap()),
+            max_gps_time: f64::from_le_bytes(data[40..48].try_into().unwrap()),
+        }
+    }
+}
+
+/// Follows the points given to a compressor to compute the bounds of its chunks
+pub(crate) struct ChunkBoundsTracker {
+    format: PointFormat,
+    point_size: usize,
+    /// `None` for variable-size chunks, which are ended explicitly
+    chunk_size: Option<u64>,
+    current: Lazrs_ChunkBounds,
+    chunks: Vec<Lazrs_ChunkBounds>,
+}
+
+impl ChunkBoundsTracker {
+    pub(crate) fn add_points(&mut self, records: &[u8]) {
+        for record in records.chunks_exact(self.point_size) {
+            self.current
+                .add(&Lazrs_Point::read_from(&self.format, record));
+            if Some(self.current.point_count) == self.chunk_size {
+                self.finish_chunk();
+            }
+        }
+    }
+
+    pub(crate) fn finish_chunk(&mut self) {
+        self.chunks.push(std::mem::take(&mut self.current));
+    }
+
+    /// Ends the last chunk, like the compressor's `done`
+    pub(crate) fn done(&mut self) {
+        // With variable-size chunks the compressor always ends the current chunk,
+        // even when it is empty
+        if self.chunk_size.is_none() || self.current.point_count > 0 {
+            self.finish_chunk();
+        }
+    }
+}
+
+/// The bounds of the chunks of some LAZ data
+pub struct Lazrs_ChunkBoundsTable {
+    pub(crate) chunks: Vec<Lazrs_ChunkBounds>,
+}
+
+impl Lazrs_ChunkBoundsTable {
+    /// Reads the bounds from the data of their VLR, `None` if it is not valid
+    pub(crate) fn from_vlr_data(data: &[u8]) -> Option<Self> {
+        if data.len() < VLR_HEADER_SIZE || data[0..2] != VLR_DATA_VERSION.to_le_bytes() {
+            return None;
+        }
+        let num_chunks = u64::from_le_bytes(data[2..10].try_into().unwrap());
+        let entries = &data[VLR_HEADER_SIZE..];
+        if entries.len() as u64 != num_chunks.saturating_mul(VLR_ENTRY_SIZE as u64) {
+            return None;
+        }
+        let chunks = entries
+            .chunks_exact(VLR_ENTRY_SIZE)
+            .map(Lazrs_ChunkBounds::read_from)
+            .collect();
+        Some(Self { chunks })
+    }
+}
+
+/// Makes the compressor compute the bounds of the chunks it compresses
+///
+/// This must be called before any point is compressed.
+/// The bounds are available through `lazrs_compressor_chunk_bounds`
+/// once `lazrs_compressor_done` has been called.
+///
+/// @compressor: the compressor, must not be NULL
+/// @point_format_id: the point format of the points, LAZRS_OTHER is returned
+///                   if the compressor was not created for this format
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_compressor_track_chunk_bounds(
+    compressor: *mut Lazrs_LasZipCompressor,
+    point_format_id: u8,
+) -> Lazrs_Result {
+    debug_assert!(!compressor.is_null());
+    let compressor = &mut *compressor;
+    let vlr = compressor.compressor.vlr();
+    let (format, point_size) = match PointFormat::of_vlr(point_format_id, vlr) {
+        Ok(format) => format,
+        Err(result) => return result,
+    };
+    compressor.chunk_bounds = Some(ChunkBoundsTracker {
+        format,
+        point_size,
+        chunk_size: if vlr.uses_variable_size_chunks() {
+            None
+        } else {
+            Some(u64::from(vlr.chunk_size()))
+        },
+        current: Lazrs_ChunkBounds::default(),
+        chunks: Vec::new(),
+    });
+    Lazrs_Result::LAZRS_OK
+}
+
+/// Gives a copy of the bounds of the chunks written by the compressor
+///
+/// LAZRS_OTHER is returned if `lazrs_compressor_track_chunk_bounds` was not called.
+///
+/// @compressor: the compressor, must not be NULL, `lazrs_compressor_done` must have been called
+/// @table: will receive the bounds, to be freed with `lazrs_chunk_bounds_table_delete`
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_compressor_chunk_bounds(
+    compressor: *const Lazrs_LasZipCompressor,
+    table: *mut *mut Lazrs_ChunkBoundsTable,
+) -> Lazrs_Result {
+    debug_assert!(!compressor.is_null());
+    debug_assert!(!table.is_null());
+    match &(*compressor).chunk_bounds {
+        Some(tracker) => {
+            *table = Box::into_raw(Box::new(Lazrs_ChunkBoundsTable {
+                chunks: tracker.chunks.clone(),
+            }));
+            Lazrs_Result::LAZRS_OK
+        }
+        None => {
+            *table = std::ptr::null_mut();
+            Lazrs_Result::LAZRS_OTHER
+        }
+    }
+}
+
+/// Frees the table
+///
+/// @table can be NULL (no-op)
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_chunk_bounds_table_delete(table: *mut Lazrs_ChunkBoundsTable) {
+    if !table.is_null() {
+        let _ = Box::from_raw(table);
+    }
+}
+
+/// Returns the number of chunks
+///
+/// @table: must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_chunk_bounds_table_len(
+    table: *const Lazrs_ChunkBoundsTable,
+) -> usize {
+    debug_assert!(!table.is_null());
+    (*table).chunks.len()
+}
+
+/// Gets the bounds of a chunk
+///
+/// @table: must not be NULL
+/// @index: index of the chunk, LAZRS_OTHER is returned if it is out of bounds
+/// @bounds: will receive the bounds
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_chunk_bounds_table_entry(
+    table: *const Lazrs_ChunkBoundsTable,
+    index: usize,
+    bounds: *mut Lazrs_ChunkBounds,
+) -> Lazrs_Result {
+    debug_assert!(!table.is_null());
+    debug_assert!(!bounds.is_null());
+    let table = &*table;
+    match table.chunks.get(index) {
+        Some(b) => {
+            *bounds = *b;
+            Lazrs_Result::LAZRS_OK
+        }
+        None => Lazrs_Result::LAZRS_OTHER,
+    }
+}
+
+fn vlr_data(table: &Lazrs_ChunkBoundsTable) -> Vec<u8> {
+    let mut data = Vec::with_capacity(VLR_HEADER_SIZE + table.chunks.len() * VLR_ENTRY_SIZE);
+    data.extend_from_slice(&VLR_DATA_VERSION.to_le_bytes());
+    data.extend_from_slice(&(table.chunks.len() as u64).to_le_bytes());
+    for chunk in &table.chunks {
+        chunk.write_to(&mut data);
+    }
+    data
+}
+
+/// Returns the size of the data of the VLR holding the bounds
+///
+/// Data bigger than 65535 bytes (more than 1365 chunks)
+/// does not fit in a VLR and has to be stored in an EVLR.
+///
+/// @table: must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_chunk_bounds_table_vlr_data_size(
+    table: *const Lazrs_ChunkBoundsTable,
+) -> usize {
+    debug_assert!(!table.is_null());
+    VLR_HEADER_SIZE + (*table).chunks.len() * VLR_ENTRY_SIZE
+}
+
+/// Writes the data of the VLR holding the bounds,
+/// to be stored with the user id "lazrs" and the record id `LAZRS_CHUNK_BOUNDS_RECORD_ID`
+///
+/// @table: must not be NULL
+/// @data: buffer that will receive the data
+/// @size: size of the buffer, must be `lazrs_chunk_bounds_table_vlr_data_size`
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_chunk_bounds_table_vlr_data(
+    table: *const Lazrs_ChunkBoundsTable,
+    data: *mut u8,
+    size: usize,
+) -> Lazrs_Result {
+    debug_assert!(!table.is_null());
+    debug_assert!(!data.is_null());
+    let tmp = vlr_data(&*table);
+    if tmp.len() != size {
+        return Lazrs_Result::LAZRS_OTHER;
+    }
+    std::slice::from_raw_parts_mut(data, size).copy_from_slice(&tmp);
+    Lazrs_Result::LAZRS_OK
+}
+
+/// Reads the bounds from the data of their VLR
+///
+/// @data: the data of the VLR, must not be NULL
+/// @size: size of the data
+/// @table: will receive the bounds, or NULL if the data is not valid (LAZRS_OTHER)
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_chunk_bounds_table_from_vlr_data(
+    data: *const u8,
+    size: usize,
+    table: *mut *mut Lazrs_ChunkBoundsTable,
+) -> Lazrs_Result {
+    debug_assert!(!data.is_null());
+    debug_assert!(!table.is_null());
+    let data = std::slice::from_raw_parts(data, size);
+    match Lazrs_ChunkBoundsTable::from_vlr_data(data) {
+        Some(bounds) => {
+            *table = Box::into_raw(Box::new(bounds));
+            Lazrs_Result::LAZRS_OK
+        }
+        None => {
+            *table = std::ptr::null_mut();
+            Lazrs_Result::LAZRS_OTHER
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POINT_SIZE: usize = 28 + 1;
+
+    /// Records of format 1 with an extra byte
+    fn records(coordinates: &[(i32, i32, i32, f64)]) -> Vec<u8> {
+        let format = PointFormat::new(1).unwrap();
+        let mut records = vec![0u8; coordinates.len() * POINT_SIZE];
+        for (record, &(x, y, z, gps_time)) in records.chunks_exact_mut(POINT_SIZE).zip(coordinates)
+        {
+            let point = Lazrs_Point {
+                x,
+                y,
+                z,
+                gps_time,
+                ..Default::default()
+            };
+            point.write_to(&format, record);
+        }
+        records
+    }
+
+    fn tracker(chunk_size: Option<u64>) -> ChunkBoundsTracker {
+        ChunkBoundsTracker {
+            format: PointFormat::new(1).unwrap(),
+            point_size: POINT_SIZE,
+            chunk_size,
+            current: Lazrs_ChunkBounds::default(),
+            chunks: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn fixed_size_chunks_end_by_themselves() {
+        let mut tracker = tracker(Some(2));
+        tracker.add_points(&records(&[
+            (1, -1, 5, 2.0),
+            (3, -4, 0, 1.0),
+            (7, 7, 7, 9.0),
+        ]));
+        tracker.add_points(&records(&[(-2, 0, 1, 0.5)]));
+        tracker.add_points(&records(&[(8, 8, 8, 8.0)]));
+        tracker.done();
+
+        assert_eq!(
+            tracker.chunks,
+            vec![
+                Lazrs_ChunkBounds {
+                    point_count: 2,
+                    min_x: 1,
+                    min_y: -4,
+                    min_z: 0,
+                    max_x: 3,
+                    max_y: -1,
+                    max_z: 5,
+                    min_gps_time: 1.0,
+                    max_gps_time: 2.0,
+                },
+                Lazrs_ChunkBounds {
+                    point_count: 2,
+                    min_x: -2,
+                    min_y: 0,
+                    min_z: 1,
+                    max_x: 7,
+                    max_y: 7,
+                    max_z: 7,
+                    min_gps_time: 0.5,
+                    max_gps_time: 9.0,
+                },
+                Lazrs_ChunkBounds {
+                    point_count: 1,
+                    min_x: 8,
+                    min_y: 8,
+                    min_z: 8,
+                    max_x: 8,
+                    max_y: 8,
+                    max_z: 8,
+                    min_gps_time: 8.0,
+                    max_gps_time: 8.0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn fixed_size_chunks_do_not_end_with_an_empty_chunk() {
+        let mut tracker = tracker(Some(1));
+        tracker.add_points(&records(&[(1, 1, 1, 1.0)]));
+        tracker.done();
+        assert_eq!(tracker.chunks.len(), 1);
+    }
+
+    #[test]
+    fn variable_size_chunks_end_when_asked() {
+        let mut tracker = tracker(None);
+        tracker.add_points(&records(&[(1, 1, 1, 1.0), (2, 2, 2, 2.0), (3, 3, 3, 3.0)]));
+        tracker.finish_chunk();
+        tracker.add_points(&records(&[(4, 4, 4, 4.0)]));
+        tracker.finish_chunk();
+        tracker.done();
+
+        let counts: Vec<u64> = tracker.chunks.iter().map(|c| c.point_count).collect();
+        assert_eq!(counts, vec![3, 1, 0]);
+        assert_eq!(tracker.chunks[2], Lazrs_ChunkBounds::default());
+    }
+
+    /// Reads the bounds back through `lazrs_chunk_bounds_table_from_vlr_data`
+    fn from_vlr_data(data: &[u8]) -> Option<Vec<Lazrs_ChunkBounds>> {
+        let mut table = std::ptr::null_mut();
+        unsafe {
+            match lazrs_chunk_bounds_table_from_vlr_data(data.as_ptr(), data.len(), &mut table) {
+                Lazrs_Result::LAZRS_OK => {
+                    let chunks = (*table).chunks.clone();
+                    lazrs_chunk_bounds_table_delete(table);
+                    Some(chunks)
+                }
+                _ => {
+                    assert!(table.is_null());
+                    None
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn vlr_data_is_read_back() {
+        let mut tracker = tracker(Some(2));
+        tracker.add_points(&records(&[
+            (1, 2, 3, 4.0),
+            (-5, 6, -7, 8.0),
+            (9, 9, 9, 9.0),
+        ]));
+        tracker.done();
+        let table = Lazrs_ChunkBoundsTable {
+            chunks: tracker.chunks,
+        };
+
+        let data = vlr_data(&table);
+        assert_eq!(data.len(), VLR_HEADER_SIZE + 2 * VLR_ENTRY_SIZE);
+        assert_eq!(from_vlr_data(&data), Some(table.chunks));
+
+        assert!(from_vlr_data(&data[..data.len() - 1]).is_none());
+        assert!(from_vlr_data(&data[..VLR_HEADER_SIZE - 1]).is_none());
+        let mut other_version = data.clone();
+        other_version[0] += 1;
+        assert!(from_vlr_data(&other_version).is_none());
+        let mut too_many = data;
+        too_many[2..10].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(from_vlr_data(&too_many).is_none());
+    }
+}
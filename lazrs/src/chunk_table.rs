@@ -0,0 +1,354 @@
+//! Access to the chunk table of LAZ data, and its reconstruction when it is missing.
+
+use std::io::{Seek, SeekFrom, Write};
+
+use laz::laszip::ChunkTable;
+
+use crate::chunks::{chunk_end, is_layered, scan_chunk, ChunkLayout};
+use crate::io::{CDest, CSource};
+use crate::{Lazrs_DecompressorParams, Lazrs_Dest, Lazrs_DestType, Lazrs_Result};
+
+/// One entry of the chunk table
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Lazrs_ChunkTableEntry {
+    /// Number of points in the chunk
+    point_count: u64,
+    /// Number of bytes of the compressed chunk
+    byte_count: u64,
+}
+
+/// The chunk table of some LAZ data
+pub struct Lazrs_ChunkTable {
+    table: ChunkTable,
+    vlr: laz::LazVlr,
+}
+
+/// Creates the source and the vlr described by the params,
+/// the source is positioned at the start of the point data
+unsafe fn open_source(
+    params: &Lazrs_DecompressorParams,
+) -> Result<(CSource<'static>, laz::LazVlr), Lazrs_Result> {
+    let vlr_data = std::slice::from_raw_parts(params.laszip_vlr.data, params.laszip_vlr.len);
+    let vlr = laz::LazVlr::from_buffer(vlr_data).map_err(Lazrs_Result::from)?;
+    let mut csource = CSource::from_c_source(params.source_type, params.source)?;
+    csource
+        .seek(SeekFrom::Start(params.source_offset))
+        .map_err(|_| Lazrs_Result::LAZRS_IO_ERROR)?;
+    Ok((csource, vlr))
+}
+
+/// Finds the chunks by decompressing them one after the other
+fn rebuild<R: std::io::Read + Seek + Send>(
+    mut src: R,
+    vlr: &laz::LazVlr,
+    num_points: u64,
+) -> laz::Result<ChunkTable> {
+    if vlr.uses_variable_size_chunks() && !is_layered(vlr) {
+        // Nothing tells where these chunks end
+        return Err(laz::LasZipError::MissingChunkTable);
+    }
+    src.seek(SeekFrom::Current(ChunkTable::OFFSET_SIZE as i64))?;
+
+    let mut table = ChunkTable::default();
+    let mut remaining = num_points;
+    while remaining > 0 {
+        let max_points = if vlr.uses_variable_size_chunks() {
+            remaining
+        } else {
+            remaining.min(u64::from(vlr.chunk_size()))
+        };
+        let entry = scan_chunk(&mut src, vlr, max_points, |_| {})?;
+        if entry.point_count == 0 || entry.point_count > remaining {
+            return Err(laz::LasZipError::IoError(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "chunk point count does not match the number of points",
+            )));
+        }
+        remaining -= entry.point_count;
+        table.push(entry);
+    }
+    Ok(table)
+}
+
+/// Reads the chunk table of the LAZ data
+///
+/// @params: where the LAZ data is, `source_offset` must be the start of the point data
+/// @table: will receive the table, or NULL if an error occurred
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_chunk_table_read(
+    params: Lazrs_DecompressorParams,
+    table: *mut *mut Lazrs_ChunkTable,
+) -> Lazrs_Result {
+    debug_assert!(!table.is_null());
+    *table = std::ptr::null_mut::<Lazrs_ChunkTable>();
+    let (mut csource, vlr) = match open_source(&params) {
+        Ok(v) => v,
+        Err(result) => return result,
+    };
+    match ChunkLayout::read_from(&mut csource, &vlr) {
+        Ok(layout) => {
+            *table = Box::into_raw(Box::new(Lazrs_ChunkTable {
+                table: layout.table,
+                vlr,
+            }));
+            Lazrs_Result::LAZRS_OK
+        }
+        Err(error) => error.into(),
+    }
+}
+
+/// Reconstructs the chunk table of LAZ data whose table is missing,
+/// by decompressing all the chunks one after the other.
+///
+/// Chunks of variable size can only be found for point formats >= 6,
+/// LAZRS_MISSING_CHUNK_TABLE is returned for the others.
+///
+/// @params: where the LAZ data is, `source_offset` must be the start of the point data
+/// @num_points: number of points of the LAZ data (from the LAS header)
+/// @table: will receive the table, or NULL if an error occurred
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_chunk_table_rebuild(
+    params: Lazrs_DecompressorParams,
+    num_points: u64,
+    table: *mut *mut Lazrs_ChunkTable,
+) -> Lazrs_Result {
+    debug_assert!(!table.is_null());
+    *table = std::ptr::null_mut::<Lazrs_ChunkTable>();
+    let (mut csource, vlr) = match open_source(&params) {
+        Ok(v) => v,
+        Err(result) => return result,
+    };
+    match rebuild(&mut csource, &vlr, num_points) {
+        Ok(chunk_table) => {
+            *table = Box::into_raw(Box::new(Lazrs_ChunkTable {
+                table: chunk_table,
+                vlr,
+            }));
+            Lazrs_Result::LAZRS_OK
+        }
+        Err(error) => error.into(),
+    }
+}
+
+/// Frees the table
+///
+/// @table can be NULL (no-op)
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_chunk_table_delete(table: *mut Lazrs_ChunkTable) {
+    if !table.is_null() {
+        let _ = Box::from_raw(table);
+    }
+}
+
+/// Returns the number of chunks
+///
+/// @table: must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_chunk_table_len(table: *const Lazrs_ChunkTable) -> usize {
+    debug_assert!(!table.is_null());
+    (*table).table.len()
+}
+
+/// Gets the entry of a chunk
+///
+/// For fixed-size chunks read from a table, the point count is the chunk size,
+/// even for the last chunk.
+///
+/// @table: must not be NULL
+/// @index: index of the chunk, LAZRS_OTHER is returned if it is out of bounds
+/// @entry: will receive the entry
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_chunk_table_entry(
+    table: *const Lazrs_ChunkTable,
+    index: usize,
+    entry: *mut Lazrs_ChunkTableEntry,
+) -> Lazrs_Result {
+    debug_assert!(!table.is_null());
+    debug_assert!(!entry.is_null());
+    match (*table).table.as_ref().get(index) {
+        Some(e) => {
+            *entry = Lazrs_ChunkTableEntry {
+                point_count: e.point_count,
+                byte_count: e.byte_count,
+            };
+            Lazrs_Result::LAZRS_OK
+        }
+        None => Lazrs_Result::LAZRS_OTHER,
+    }
+}
+
+/// Writes the table after the chunks, and updates the offset to it
+/// that is at the start of the point data.
+///
+/// This is meant to repair LAZ data in place, so the destination must
+/// already contain the chunks (e.g. a FILE opened with "r+b"),
+/// LAZRS_DEST_FNAME is not accepted as the file would be truncated.
+///
+/// @table: must not be NULL
+/// @dest_type: type of the destination
+/// @dest: the destination
+/// @source_offset: position of the start of the point data in the destination
+///
+/// LAZRS_OTHER is returned if the chunks of the table end past what a file can hold.
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_chunk_table_write(
+    table: *const Lazrs_ChunkTable,
+    dest_type: Lazrs_DestType,
+    dest: Lazrs_Dest,
+    source_offset: u64,
+) -> Lazrs_Result {
+    debug_assert!(!table.is_null());
+    let table = &*table;
+    if let Lazrs_DestType::LAZRS_DEST_FNAME = dest_type {
+        return Lazrs_Result::LAZRS_OTHER;
+    }
+    let mut dest = match CDest::from_c_dest(dest_type, dest) {
+        Ok(dest) => dest,
+        Err(result) => return result,
+    };
+
+    // The byte counts may come from a corrupt table
+    let table_offset = table
+        .table
+        .as_ref()
+        .iter()
+        .try_fold(ChunkTable::OFFSET_SIZE as u64, chunk_end)
+        .and_then(|end| end.checked_add(source_offset))
+        .filter(|&offset| offset <= i64::MAX as u64);
+    let Some(table_offset) = table_offset else {
+        return Lazrs_Result::LAZRS_OTHER;
+    };
+    let result = (|| -> std::io::Result<()> {
+        dest.seek(SeekFrom::Start(table_offset))?;
+        table.table.write_to(&mut dest, &table.vlr)?;
+        dest.seek(SeekFrom::Start(source_offset))?;
+        dest.write_all(&(table_offset as i64).to_le_bytes())?;
+        dest.flush()
+    })();
+    result.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read};
+
+    use crate::chunks::test_data::{compressed, vlr};
+    use crate::io::CFile;
+    use crate::{Lazrs_Buffer, Lazrs_Source, Lazrs_SourceType};
+
+    /// Calls `f` with the params of LAZ data in memory
+    fn with_params<T>(
+        data: &[u8],
+        vlr: &laz::LazVlr,
+        f: impl FnOnce(Lazrs_DecompressorParams) -> T,
+    ) -> T {
+        let mut vlr_data = vec![];
+        vlr.write_to(&mut vlr_data).unwrap();
+        f(Lazrs_DecompressorParams {
+            source_type: Lazrs_SourceType::LAZRS_SOURCE_BUFFER,
+            source: Lazrs_Source {
+                buffer: Lazrs_Buffer {
+                    data: data.as_ptr(),
+                    len: data.len(),
+                },
+            },
+            source_offset: 0,
+            laszip_vlr: Lazrs_Buffer {
+                data: vlr_data.as_ptr(),
+                len: vlr_data.len(),
+            },
+        })
+    }
+
+    unsafe fn entries(table: *const Lazrs_ChunkTable) -> Vec<(u64, u64)> {
+        let mut entry = Lazrs_ChunkTableEntry {
+            point_count: 0,
+            byte_count: 0,
+        };
+        (0..lazrs_chunk_table_len(table))
+            .map(|i| {
+                assert_eq!(
+                    lazrs_chunk_table_entry(table, i, &mut entry),
+                    Lazrs_Result::LAZRS_OK
+                );
+                (entry.point_count, entry.byte_count)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rebuilt_table_is_written_in_place() {
+        let vlr = vlr();
+        let data = compressed(&vlr, 25);
+        let table_offset = ChunkLayout::read_from(Cursor::new(&data), &vlr)
+            .unwrap()
+            .table_offset
+            .unwrap();
+
+        unsafe {
+            let mut read = std::ptr::null_mut();
+            assert_eq!(
+                with_params(&data, &vlr, |params| lazrs_chunk_table_read(
+                    params, &mut read
+                )),
+                Lazrs_Result::LAZRS_OK
+            );
+            let read_entries = entries(read);
+            assert_eq!(read_entries.len(), 3);
+            let out_of_bounds = lazrs_chunk_table_entry(
+                read,
+                3,
+                &mut Lazrs_ChunkTableEntry {
+                    point_count: 0,
+                    byte_count: 0,
+                },
+            );
+            assert_eq!(out_of_bounds, Lazrs_Result::LAZRS_OTHER);
+            lazrs_chunk_table_delete(read);
+
+            // Data whose writer stopped before writing the table
+            let mut damaged = data[..table_offset as usize].to_vec();
+            damaged[..8].fill(0);
+            let mut rebuilt = std::ptr::null_mut();
+            assert_eq!(
+                with_params(&damaged, &vlr, |params| {
+                    lazrs_chunk_table_rebuild(params, 25, &mut rebuilt)
+                }),
+                Lazrs_Result::LAZRS_OK
+            );
+            // A read table gives the chunk size as the point count of the last chunk
+            let rebuilt_entries = entries(rebuilt);
+            assert_eq!(
+                rebuilt_entries.iter().map(|e| e.0).collect::<Vec<_>>(),
+                vec![10, 10, 5]
+            );
+            assert_eq!(
+                rebuilt_entries.iter().map(|e| e.1).collect::<Vec<_>>(),
+                read_entries.iter().map(|e| e.1).collect::<Vec<_>>()
+            );
+
+            let fh = libc::tmpfile();
+            assert!(!fh.is_null());
+            let mut file = CFile::new_unchecked(fh);
+            file.write_all(&damaged).unwrap();
+            assert_eq!(
+                lazrs_chunk_table_write(
+                    rebuilt,
+                    Lazrs_DestType::LAZRS_DEST_CFILE,
+                    Lazrs_Dest { file: fh },
+                    0
+                ),
+                Lazrs_Result::LAZRS_OK
+            );
+            lazrs_chunk_table_delete(rebuilt);
+
+            let mut repaired = vec![];
+            file.seek(SeekFrom::Start(0)).unwrap();
+            file.read_to_end(&mut repaired).unwrap();
+            libc::fclose(fh);
+            assert_eq!(repaired, data);
+        }
+    }
+}
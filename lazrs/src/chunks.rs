@@ -0,0 +1,311 @@
+//! Helpers to work on the chunks of LAZ data one by one,
+//! as laz only gives access to the points as a whole.
+
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+use laz::laszip::{ChunkTable, ChunkTableEntry};
+use laz::record::{
+    LayeredPointRecordCompressor, LayeredPointRecordDecompressor, RecordCompressor,
+    RecordDecompressor, SequentialPointRecordCompressor, SequentialPointRecordDecompressor,
+};
+use laz::{LasZipError, LazVlr};
+
+/// Returns whether the points are compressed in layers (point formats >= 6)
+pub(crate) fn is_layered(vlr: &LazVlr) -> bool {
+    vlr.items().first().is_some_and(|item| item.version() >= 3)
+}
+
+/// Creates the decompressor for the points of one chunk
+pub(crate) fn record_decompressor<'a, R: Read + Seek + Send + 'a>(
+    vlr: &LazVlr,
+    input: R,
+) -> laz::Result<Box<dyn RecordDecompressor<R> + Send + 'a>> {
+    let first_item = vlr
+        .items()
+        .first()
+        .ok_or(LasZipError::UnsupportedPointFormat(0))?;
+    let mut decompressor = match first_item.version() {
+        1 | 2 => Box::new(SequentialPointRecordDecompressor::new(input))
+            as Box<dyn RecordDecompressor<R> + Send>,
+        3 | 4 => Box::new(LayeredPointRecordDecompressor::new(input))
+            as Box<dyn RecordDecompressor<R> + Send>,
+        version => {
+            return Err(LasZipError::UnsupportedLazItemVersion(
+                first_item.item_type(),
+                version,
+            ))
+        }
+    };
+    decompressor.set_fields_from(vlr.items())?;
+    Ok(decompressor)
+}
+
+/// Creates the compressor for the points of one chunk
+pub(crate) fn record_compressor<'a, W: Write + Send + 'a>(
+    vlr: &LazVlr,
+    output: W,
+) -> laz::Result<Box<dyn RecordCompressor<W> + Send + 'a>> {
+    let first_item = vlr
+        .items()
+        .first()
+        .ok_or(LasZipError::UnsupportedPointFormat(0))?;
+    let mut compressor = match first_item.version() {
+        1 | 2 => Box::new(SequentialPointRecordCompressor::new(output))
+            as Box<dyn RecordCompressor<W> + Send>,
+        3 | 4 => Box::new(LayeredPointRecordCompressor::new(output))
+            as Box<dyn RecordCompressor<W> + Send>,
+        version => {
+            return Err(LasZipError::UnsupportedLazItemVersion(
+                first_item.item_type(),
+                version,
+            ))
+        }
+    };
+    compressor.set_fields_from(vlr.items())?;
+    Ok(compressor)
+}
+
+/// Compresses the points as one chunk, returns its bytes
+pub(crate) fn compress_chunk(vlr: &LazVlr, points: &[u8]) -> laz::Result<Vec<u8>> {
+    let mut compressor = record_compressor(vlr, Cursor::new(Vec::new()))?;
+    compressor.compress_many(points)?;
+    compressor.done()?;
+    Ok(compressor.box_into_inner().into_inner())
+}
+
+/// Returns the number of points layered chunks store after their first point
+pub(crate) fn layered_point_count(vlr: &LazVlr, chunk: &[u8]) -> Option<u32> {
+    let start = vlr.items_size() as usize;
+    let bytes = chunk.get(start..start + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Decompresses the `point_count` points of the chunk, calling `on_point` with each of them.
+///
+/// Returns the number of bytes of the chunk that were read.
+pub(crate) fn decompress_chunk<F>(
+    vlr: &LazVlr,
+    chunk: &[u8],
+    point_count: u64,
+    mut on_point: F,
+) -> laz::Result<u64>
+where
+    F: FnMut(&[u8]),
+{
+    let mut decompressor = record_decompressor(vlr, Cursor::new(chunk))?;
+    let mut point = vec![0u8; vlr.items_size() as usize];
+    for _ in 0..point_count {
+        decompressor.decompress_next(&mut point)?;
+        on_point(&point);
+    }
+    Ok(decompressor.get().position())
+}
+
+/// Decompresses the chunk that starts at the current position of `src`,
+/// finding where it ends without the chunk table.
+///
+/// Layered chunks store their number of points, for the others `max_points`
+/// (the chunk size, or the number of points left for the last chunk) are decompressed,
+/// which is not possible with variable-size chunks.
+///
+/// `on_point` is called with each point as soon as it is decompressed,
+/// so the points before an error are still given.
+pub(crate) fn scan_chunk<R, F>(
+    src: &mut R,
+    vlr: &LazVlr,
+    max_points: u64,
+    mut on_point: F,
+) -> laz::Result<ChunkTableEntry>
+where
+    R: Read + Seek + Send,
+    F: FnMut(&[u8]),
+{
+    let start = src.stream_position()?;
+    let point_count = if is_layered(vlr) {
+        src.seek(SeekFrom::Current(vlr.items_size() as i64))?;
+        let mut count = [0u8; 4];
+        src.read_exact(&mut count)?;
+        src.seek(SeekFrom::Start(start))?;
+        u64::from(u32::from_le_bytes(count))
+    } else if vlr.uses_variable_size_chunks() {
+        return Err(LasZipError::MissingChunkTable);
+    } else {
+        max_points
+    };
+
+    let mut decompressor = record_decompressor(vlr, &mut *src)?;
+    let mut point = vec![0u8; vlr.items_size() as usize];
+    for _ in 0..point_count {
+        decompressor.decompress_next(&mut point)?;
+        on_point(&point);
+    }
+    let end = decompressor.get_mut().stream_position()?;
+    Ok(ChunkTableEntry {
+        point_count,
+        byte_count: end - start,
+    })
+}
+
+/// Where the chunks are in the source
+pub(crate) struct ChunkLayout {
+    /// Position of the first chunk, right after the offset to the chunk table
+    pub(crate) data_start: u64,
+    /// Offset to the chunk table as written at the start of the data,
+    /// `None` if the writer could not update it
+    pub(crate) table_offset: Option<u64>,
+    pub(crate) table: ChunkTable,
+}
+
+impl ChunkLayout {
+    /// Reads the layout, `src` must be positioned at the start of the point data
+    pub(crate) fn read_from<R: Read + Seek>(mut src: R, vlr: &LazVlr) -> laz::Result<Self> {
+        let start = src.stream_position()?;
+        let mut offset = [0u8; ChunkTable::OFFSET_SIZE];
+        src.read_exact(&mut offset)?;
+        let offset = i64::from_le_bytes(offset);
+        let table_offset = if offset > start as i64 {
+            Some(offset as u64)
+        } else {
+            None
+        };
+
+        src.seek(SeekFrom::Start(start))?;
+        let table = ChunkTable::read_from(&mut src, vlr)?;
+        Ok(Self {
+            data_start: start + ChunkTable::OFFSET_SIZE as u64,
+            table_offset,
+            table,
+        })
+    }
+
+    /// Returns the expected number of points of each chunk,
+    /// for fixed-size chunks they are deduced from the chunk size and `num_points`.
+    pub(crate) fn point_counts(&self, vlr: &LazVlr, num_points: u64) -> Vec<u64> {
+        if vlr.uses_variable_size_chunks() {
+            return self.table.as_ref().iter().map(|e| e.point_count).collect();
+        }
+        let chunk_size = u64::from(vlr.chunk_size());
+        let mut remaining = num_points;
+        self.table
+            .as_ref()
+            .iter()
+            .map(|_| {
+                let count = remaining.min(chunk_size);
+                remaining -= count;
+                count
+            })
+            .collect()
+    }
+
+    /// Returns whether the table describes chunks that fit in the source
+    pub(crate) fn is_consistent(&self, source_end: u64) -> bool {
+        let chunks_end = self
+            .table
+            .as_ref()
+            .iter()
+            .try_fold(self.data_start, chunk_end);
+        chunks_end.is_some_and(|end| {
+            end <= source_end && self.table_offset.is_none_or(|offset| offset == end)
+        })
+    }
+
+    /// Returns the position of each chunk in the source.
+    ///
+    /// laz reads the byte counts as `i32`, so a corrupt table can have byte counts
+    /// close to `u64::MAX`, the positions after them are `u64::MAX`.
+    /// Use `chunk_end` to know where a chunk ends.
+    pub(crate) fn chunk_offsets(&self) -> Vec<u64> {
+        let mut offset = self.data_start;
+        self.table
+            .as_ref()
+            .iter()
+            .map(|entry| {
+                let start = offset;
+                offset = offset.saturating_add(entry.byte_count);
+                start
+            })
+            .collect()
+    }
+}
+
+/// Returns the position of the end of the chunk starting at `offset`,
+/// `None` if it does not fit in a `u64` (the chunk table is corrupt)
+pub(crate) fn chunk_end(offset: u64, entry: &ChunkTableEntry) -> Option<u64> {
+    offset.checked_add(entry.byte_count)
+}
+
+/// LAZ data of format 0 shared by the tests of the modules working on chunks
+#[cfg(test)]
+pub(crate) mod test_data {
+    use std::io::Cursor;
+
+    use laz::laszip::ChunkTable;
+
+    use super::ChunkLayout;
+
+    pub(crate) const POINT_SIZE: usize = 20;
+
+    /// LASzip VLR of point format 0, in chunks of 10 points
+    pub(crate) fn vlr() -> laz::LazVlr {
+        let items = laz::LazItemRecordBuilder::default_for_point_format_id(0, 0).unwrap();
+        laz::LazVlrBuilder::new(items)
+            .with_fixed_chunk_size(10)
+            .build()
+    }
+
+    /// Records of format 0 that are all different
+    pub(crate) fn points(count: usize) -> Vec<u8> {
+        (0..count * POINT_SIZE)
+            .map(|i| (i * 7 + i / POINT_SIZE) as u8)
+            .collect()
+    }
+
+    /// LAZ data of the first `count` points
+    pub(crate) fn compressed(vlr: &laz::LazVlr, count: usize) -> Vec<u8> {
+        let mut data = Cursor::new(vec![]);
+        laz::compress_buffer(&mut data, &points(count), vlr.clone()).unwrap();
+        data.into_inner()
+    }
+
+    /// Rewrites the chunk table with the byte count of a chunk changed
+    pub(crate) fn set_byte_count(
+        data: &mut Vec<u8>,
+        vlr: &laz::LazVlr,
+        index: usize,
+        byte_count: u64,
+    ) {
+        let layout = ChunkLayout::read_from(Cursor::new(&data), vlr).unwrap();
+        let mut entries = layout.table.as_ref().to_vec();
+        entries[index].byte_count = byte_count;
+        let mut table = ChunkTable::with_capacity(entries.len());
+        entries.into_iter().for_each(|entry| table.push(entry));
+        data.truncate(layout.table_offset.unwrap() as usize);
+        table.write_to(&mut *data, vlr).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_data::*;
+    use super::*;
+
+    #[test]
+    fn negative_byte_count_does_not_overflow() {
+        let vlr = vlr();
+        let mut data = compressed(&vlr, 25);
+        let layout = ChunkLayout::read_from(Cursor::new(&data), &vlr).unwrap();
+        assert!(layout.is_consistent(data.len() as u64));
+
+        // laz reads the byte counts as i32, a negative one ends up close to u64::MAX
+        set_byte_count(&mut data, &vlr, 1, -16i32 as u64);
+
+        let layout = ChunkLayout::read_from(Cursor::new(&data), &vlr).unwrap();
+        let entries = layout.table.as_ref();
+        let offsets = layout.chunk_offsets();
+        assert_eq!(entries[1].byte_count, -16i32 as u64);
+        assert!(chunk_end(offsets[0], &entries[0]).is_some());
+        assert_eq!(chunk_end(offsets[1], &entries[1]), None);
+        assert_eq!(offsets[2], u64::MAX);
+        assert!(!layout.is_consistent(data.len() as u64));
+    }
+}
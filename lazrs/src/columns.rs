@@ -0,0 +1,580 @@
+//! Decompression of points directly into columns (one array per field),
+//! and compression of points from columns.
+
+use std::ffi::c_void;
+
+use crate::cancel::{points_per_step, run_in_steps};
+use crate::point::{Lazrs_Point, PointFormat};
+use crate::{Lazrs_LasZipCompressor, Lazrs_LasZipDecompressor, Lazrs_Result};
+
+/// The fields of a point that can be put in a column
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Lazrs_Field {
+    LAZRS_FIELD_X,
+    LAZRS_FIELD_Y,
+    LAZRS_FIELD_Z,
+    LAZRS_FIELD_INTENSITY,
+    LAZRS_FIELD_RETURN_NUMBER,
+    LAZRS_FIELD_NUMBER_OF_RETURNS,
+    LAZRS_FIELD_SCAN_DIRECTION_FLAG,
+    LAZRS_FIELD_EDGE_OF_FLIGHT_LINE,
+    LAZRS_FIELD_CLASSIFICATION,
+    LAZRS_FIELD_SYNTHETIC,
+    LAZRS_FIELD_KEY_POINT,
+    LAZRS_FIELD_WITHHELD,
+    LAZRS_FIELD_OVERLAP,
+    LAZRS_FIELD_SCANNER_CHANNEL,
+    LAZRS_FIELD_USER_DATA,
+    /// In degrees
+    LAZRS_FIELD_SCAN_ANGLE,
+    LAZRS_FIELD_POINT_SOURCE_ID,
+    LAZRS_FIELD_GPS_TIME,
+    LAZRS_FIELD_RED,
+    LAZRS_FIELD_GREEN,
+    LAZRS_FIELD_BLUE,
+    LAZRS_FIELD_NIR,
+}
+
+impl Lazrs_Field {
+    fn is_coordinate(self) -> bool {
+        matches!(
+            self,
+            Lazrs_Field::LAZRS_FIELD_X | Lazrs_Field::LAZRS_FIELD_Y | Lazrs_Field::LAZRS_FIELD_Z
+        )
+    }
+
+    fn get(self, point: &Lazrs_Point) -> f64 {
+        match self {
+            Lazrs_Field::LAZRS_FIELD_X => f64::from(point.x),
+            Lazrs_Field::LAZRS_FIELD_Y => f64::from(point.y),
+            Lazrs_Field::LAZRS_FIELD_Z => f64::from(point.z),
+            Lazrs_Field::LAZRS_FIELD_INTENSITY => f64::from(point.intensity),
+            Lazrs_Field::LAZRS_FIELD_RETURN_NUMBER => f64::from(point.return_number),
+            Lazrs_Field::LAZRS_FIELD_NUMBER_OF_RETURNS => f64::from(point.number_of_returns),
+            Lazrs_Field::LAZRS_FIELD_SCAN_DIRECTION_FLAG => {
+                f64::from(u8::from(point.scan_direction_flag))
+            }
+            Lazrs_Field::LAZRS_FIELD_EDGE_OF_FLIGHT_LINE => {
+                f64::from(u8::from(point.edge_of_flight_line))
+            }
+            Lazrs_Field::LAZRS_FIELD_CLASSIFICATION => f64::from(point.classification),
+            Lazrs_Field::LAZRS_FIELD_SYNTHETIC => f64::from(u8::from(point.synthetic)),
+            Lazrs_Field::LAZRS_FIELD_KEY_POINT => f64::from(u8::from(point.key_point)),
+            Lazrs_Field::LAZRS_FIELD_WITHHELD => f64::from(u8::from(point.withheld)),
+            Lazrs_Field::LAZRS_FIELD_OVERLAP => f64::from(u8::from(point.overlap)),
+            Lazrs_Field::LAZRS_FIELD_SCANNER_CHANNEL => f64::from(point.scanner_channel),
+            Lazrs_Field::LAZRS_FIELD_USER_DATA => f64::from(point.user_data),
+            Lazrs_Field::LAZRS_FIELD_SCAN_ANGLE => f64::from(point.scan_angle),
+            Lazrs_Field::LAZRS_FIELD_POINT_SOURCE_ID => f64::from(point.point_source_id),
+            Lazrs_Field::LAZRS_FIELD_GPS_TIME => point.gps_time,
+            Lazrs_Field::LAZRS_FIELD_RED => f64::from(point.red),
+            Lazrs_Field::LAZRS_FIELD_GREEN => f64::from(point.green),
+            Lazrs_Field::LAZRS_FIELD_BLUE => f64::from(point.blue),
+            Lazrs_Field::LAZRS_FIELD_NIR => f64::from(point.nir),
+        }
+    }
+
+    /// Sets the field of the point, the value is converted
+    /// with the semantics of `as`, any non zero value is true for flags
+    fn set(self, point: &mut Lazrs_Point, value: f64) {
+        match self {
+            Lazrs_Field::LAZRS_FIELD_X => point.x = value as i32,
+            Lazrs_Field::LAZRS_FIELD_Y => point.y = value as i32,
+            Lazrs_Field::LAZRS_FIELD_Z => point.z = value as i32,
+            Lazrs_Field::LAZRS_FIELD_INTENSITY => point.intensity = value as u16,
+            Lazrs_Field::LAZRS_FIELD_RETURN_NUMBER => point.return_number = value as u8,
+            Lazrs_Field::LAZRS_FIELD_NUMBER_OF_RETURNS => point.number_of_returns = value as u8,
+            Lazrs_Field::LAZRS_FIELD_SCAN_DIRECTION_FLAG => {
+                point.scan_direction_flag = value != 0.0
+            }
+            Lazrs_Field::LAZRS_FIELD_EDGE_OF_FLIGHT_LINE => {
+                point.edge_of_flight_line = value != 0.0
+            }
+            Lazrs_Field::LAZRS_FIELD_CLASSIFICATION => point.classification = value as u8,
+            Lazrs_Field::LAZRS_FIELD_SYNTHETIC => point.synthetic = value != 0.0,
+            Lazrs_Field::LAZRS_FIELD_KEY_POINT => point.key_point = value != 0.0,
+            Lazrs_Field::LAZRS_FIELD_WITHHELD => point.withheld = value != 0.0,
+            Lazrs_Field::LAZRS_FIELD_OVERLAP => point.overlap = value != 0.0,
+            Lazrs_Field::LAZRS_FIELD_SCANNER_CHANNEL => point.scanner_channel = value as u8,
+            Lazrs_Field::LAZRS_FIELD_USER_DATA => point.user_data = value as u8,
+            Lazrs_Field::LAZRS_FIELD_SCAN_ANGLE => point.scan_angle = value as f32,
+            Lazrs_Field::LAZRS_FIELD_POINT_SOURCE_ID => point.point_source_id = value as u16,
+            Lazrs_Field::LAZRS_FIELD_GPS_TIME => point.gps_time = value,
+            Lazrs_Field::LAZRS_FIELD_RED => point.red = value as u16,
+            Lazrs_Field::LAZRS_FIELD_GREEN => point.green = value as u16,
+            Lazrs_Field::LAZRS_FIELD_BLUE => point.blue = value as u16,
+            Lazrs_Field::LAZRS_FIELD_NIR => point.nir = value as u16,
+        }
+    }
+}
+
+/// The type of the values of a column
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Lazrs_ValueType {
+    LAZRS_VALUE_U8,
+    LAZRS_VALUE_I8,
+    LAZRS_VALUE_U16,
+    LAZRS_VALUE_I16,
+    LAZRS_VALUE_U32,
+    LAZRS_VALUE_I32,
+    LAZRS_VALUE_U64,
+    LAZRS_VALUE_I64,
+    LAZRS_VALUE_F32,
+    LAZRS_VALUE_F64,
+}
+
+impl Lazrs_ValueType {
+    pub(crate) fn size(self) -> usize {
+        match self {
+            Lazrs_ValueType::LAZRS_VALUE_U8 | Lazrs_ValueType::LAZRS_VALUE_I8 => 1,
+            Lazrs_ValueType::LAZRS_VALUE_U16 | Lazrs_ValueType::LAZRS_VALUE_I16 => 2,
+            Lazrs_ValueType::LAZRS_VALUE_U32
+            | Lazrs_ValueType::LAZRS_VALUE_I32
+            | Lazrs_ValueType::LAZRS_VALUE_F32 => 4,
+            Lazrs_ValueType::LAZRS_VALUE_U64
+            | Lazrs_ValueType::LAZRS_VALUE_I64
+            | Lazrs_ValueType::LAZRS_VALUE_F64 => 8,
+        }
+    }
+
+    /// Writes the value at `dst` (which does not have to be aligned),
+    /// converting it with the semantics of `as`
+    pub(crate) unsafe fn write(self, dst: *mut u8, value: f64) {
+        match self {
+            Lazrs_ValueType::LAZRS_VALUE_U8 => dst.write(value as u8),
+            Lazrs_ValueType::LAZRS_VALUE_I8 => dst.cast::<i8>().write(value as i8),
+            Lazrs_ValueType::LAZRS_VALUE_U16 => dst.cast::<u16>().write_unaligned(value as u16),
+            Lazrs_ValueType::LAZRS_VALUE_I16 => dst.cast::<i16>().write_unaligned(value as i16),
+            Lazrs_ValueType::LAZRS_VALUE_U32 => dst.cast::<u32>().write_unaligned(value as u32),
+            Lazrs_ValueType::LAZRS_VALUE_I32 => dst.cast::<i32>().write_unaligned(value as i32),
+            Lazrs_ValueType::LAZRS_VALUE_U64 => dst.cast::<u64>().write_unaligned(value as u64),
+            Lazrs_ValueType::LAZRS_VALUE_I64 => dst.cast::<i64>().write_unaligned(value as i64),
+            Lazrs_ValueType::LAZRS_VALUE_F32 => dst.cast::<f32>().write_unaligned(value as f32),
+            Lazrs_ValueType::LAZRS_VALUE_F64 => dst.cast::<f64>().write_unaligned(value),
+        }
+    }
+
+    /// Reads the value at `src` (which does not have to be aligned)
+    pub(crate) unsafe fn read(self, src: *const u8) -> f64 {
+        match self {
+            Lazrs_ValueType::LAZRS_VALUE_U8 => f64::from(src.read()),
+            Lazrs_ValueType::LAZRS_VALUE_I8 => f64::from(src.cast::<i8>().read()),
+            Lazrs_ValueType::LAZRS_VALUE_U16 => f64::from(src.cast::<u16>().read_unaligned()),
+            Lazrs_ValueType::LAZRS_VALUE_I16 => f64::from(src.cast::<i16>().read_unaligned()),
+            Lazrs_ValueType::LAZRS_VALUE_U32 => f64::from(src.cast::<u32>().read_unaligned()),
+            Lazrs_ValueType::LAZRS_VALUE_I32 => f64::from(src.cast::<i32>().read_unaligned()),
+            Lazrs_ValueType::LAZRS_VALUE_U64 => src.cast::<u64>().read_unaligned() as f64,
+            Lazrs_ValueType::LAZRS_VALUE_I64 => src.cast::<i64>().read_unaligned() as f64,
+            Lazrs_ValueType::LAZRS_VALUE_F32 => f64::from(src.cast::<f32>().read_unaligned()),
+            Lazrs_ValueType::LAZRS_VALUE_F64 => src.cast::<f64>().read_unaligned(),
+        }
+    }
+}
+
+/// A caller-owned array holding the values of one field, one value per point
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Lazrs_Column {
+    field: Lazrs_Field,
+    value_type: Lazrs_ValueType,
+    /// Address of the value of the first point
+    data: *mut c_void,
+    /// Number of bytes between the values of two consecutive points,
+    /// 0 means the values are contiguous
+    stride: usize,
+    /// Whether the coordinates are scaled: `value = stored * scale + offset`,
+    /// when compressing the stored value is rounded to the nearest integer.
+    /// Ignored for the fields other than X, Y and Z
+    apply_scale: bool,
+    scale: f64,
+    offset: f64,
+}
+
+unsafe impl Send for Lazrs_Column {}
+unsafe impl Sync for Lazrs_Column {}
+
+impl Lazrs_Column {
+    fn value_ptr(&self, index: usize) -> *mut u8 {
+        let stride = if self.stride == 0 {
+            self.value_type.size()
+        } else {
+            self.stride
+        };
+        (self.data as *mut u8).wrapping_add(index * stride)
+    }
+
+    /// Stores the field of the point as the value at `index`
+    unsafe fn store(&self, index: usize, point: &Lazrs_Point) {
+        let mut value = self.field.get(point);
+        if self.apply_scale && self.field.is_coordinate() {
+            value = value * self.scale + self.offset;
+        }
+        self.value_type.write(self.value_ptr(index), value);
+    }
+
+    /// Sets the field of the point from the value at `index`
+    unsafe fn load(&self, index: usize, point: &mut Lazrs_Point) {
+        let mut value = self.value_type.read(self.value_ptr(index));
+        if self.apply_scale && self.field.is_coordinate() {
+            value = ((value - self.offset) / self.scale).round();
+        }
+        self.field.set(point, value);
+    }
+}
+
+/// Builds the records of the points `start..` from the columns,
+/// the extra bytes of the records are left untouched
+unsafe fn pack_records(
+    format: &PointFormat,
+    columns: &[Lazrs_Column],
+    start: usize,
+    records: &mut [u8],
+    point_size: usize,
+    parallel: bool,
+) {
+    let pack = |(i, record): (usize, &mut [u8])| {
+        let mut point = Lazrs_Point::default();
+        for column in columns {
+            column.load(start + i, &mut point);
+        }
+        point.write_to(format, record);
+    };
+    #[cfg(feature = "parallel")]
+    if parallel {
+        use rayon::prelude::*;
+        records
+            .par_chunks_exact_mut(point_size)
+            .enumerate()
+            .for_each(pack);
+        return;
+    }
+    let _ = parallel;
+    records
+        .chunks_exact_mut(point_size)
+        .enumerate()
+        .for_each(pack);
+}
+
+unsafe fn columns_from_raw<'a>(
+    columns: *const Lazrs_Column,
+    num_columns: usize,
+) -> &'a [Lazrs_Column] {
+    if num_columns == 0 {
+        &[]
+    } else {
+        debug_assert!(!columns.is_null());
+        std::slice::from_raw_parts(columns, num_columns)
+    }
+}
+
+/// Decompresses points and writes their fields in the columns
+///
+/// The LAS records are decompressed in a buffer of a few chunks
+/// that is reused, so no buffer for all the points is needed.
+/// Fields the point format does not have are written as 0.
+/// Values are converted to the type of the column, saturating for integer types.
+///
+/// The decompressor can be used with `lazrs_decompressor_decompress_many`
+/// afterwards, it continues with the point after the last one decompressed.
+/// If cancelled, `lazrs_decompressor_position` tells how many points were written in the columns.
+///
+/// @decompressor: the decompressor, must not be NULL
+/// @point_format_id: the point format of the compressed points, LAZRS_OTHER is returned
+///                   if the LAZ items of the decompressor are not the ones of this format
+/// @columns: the columns, each one must have room for `num_points` values
+/// @num_columns: number of columns
+/// @num_points: number of points to decompress
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_decompressor_decompress_columns(
+    decompressor: *mut Lazrs_LasZipDecompressor,
+    point_format_id: u8,
+    columns: *const Lazrs_Column,
+    num_columns: usize,
+    num_points: usize,
+) -> Lazrs_Result {
+    debug_assert!(!decompressor.is_null());
+    let columns = columns_from_raw(columns, num_columns);
+    let Lazrs_LasZipDecompressor {
+        decompressor,
+        vlr,
+        cancel_token,
+        thread_pool,
+        position,
+    } = &mut *decompressor;
+    let (format, point_size) = match PointFormat::of_vlr(point_format_id, vlr) {
+        Ok(format) => format,
+        Err(result) => return result,
+    };
+
+    thread_pool.install(|| {
+        let step = points_per_step(vlr, decompressor.is_parallel()).max(1);
+        let mut records = vec![0u8; step.min(num_points) * point_size];
+        run_in_steps(num_points, step, cancel_token.as_ref(), |range| {
+            let records = &mut records[..range.len() * point_size];
+            if let Err(error) = decompressor.decompress_many(records) {
+                return error.into();
+            }
+            *position += range.len() as u64;
+            for (index, record) in range.zip(records.chunks_exact(point_size)) {
+                let point = Lazrs_Point::read_from(&format, record);
+                for column in columns {
+                    column.store(index, &point);
+                }
+            }
+            Lazrs_Result::LAZRS_OK
+        })
+    })
+}
+
+/// Builds the points from the columns and compresses them
+///
+/// The LAS records are built in a buffer of a few chunks that is reused,
+/// by multiple threads when the compressor is multi-threaded.
+/// Fields that have no column are 0, as are the extra bytes.
+/// Values are converted to the type of the fields, saturating for integer types.
+/// If cancelled, `lazrs_compressor_position` tells how many points were compressed.
+///
+/// @compressor: the compressor, must not be NULL
+/// @point_format_id: the point format to compress, LAZRS_OTHER is returned
+///                   if the compressor was not created for this format
+/// @columns: the columns, each one must have `num_points` values
+/// @num_columns: number of columns
+/// @num_points: number of points to compress
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_compressor_compress_columns(
+    compressor: *mut Lazrs_LasZipCompressor,
+    point_format_id: u8,
+    columns: *const Lazrs_Column,
+    num_columns: usize,
+    num_points: usize,
+) -> Lazrs_Result {
+    debug_assert!(!compressor.is_null());
+    let columns = columns_from_raw(columns, num_columns);
+    let Lazrs_LasZipCompressor {
+        compressor,
+        cancel_token,
+        thread_pool,
+        position,
+        chunk_bounds,
+        point_stats,
+    } = &mut *compressor;
+    let (format, point_size) = match PointFormat::of_vlr(point_format_id, compressor.vlr()) {
+        Ok(format) => format,
+        Err(result) => return result,
+    };
+
+    thread_pool.install(|| {
+        let parallel = compressor.is_parallel();
+        let step = points_per_step(compressor.vlr(), parallel).max(1);
+        let mut records = vec![0u8; step.min(num_points) * point_size];
+        run_in_steps(num_points, step, cancel_token.as_ref(), |range| {
+            let records = &mut records[..range.len() * point_size];
+            pack_records(&format, columns, range.start, records, point_size, parallel);
+            if let Err(error) = compressor.compress_many(records) {
+                return error.into();
+            }
+            if let Some(tracker) = chunk_bounds.as_mut() {
+                tracker.add_points(records);
+            }
+            if let Some(tracker) = point_stats.as_mut() {
+                tracker.add_points(records);
+            }
+            *position += range.len() as u64;
+            Lazrs_Result::LAZRS_OK
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Seek, SeekFrom};
+
+    use crate::io::CFile;
+    use crate::{
+        lazrs_compressor_delete, lazrs_compressor_done, lazrs_compressor_new_for_point_format,
+        lazrs_compressor_position, lazrs_decompressor_delete, lazrs_decompressor_new,
+        lazrs_decompressor_position, Lazrs_Buffer, Lazrs_CompressorParams,
+        Lazrs_DecompressorParams, Lazrs_Dest, Lazrs_DestType, Lazrs_Source, Lazrs_SourceType,
+    };
+
+    const NUM_POINTS: usize = 50;
+
+    fn column<T>(
+        field: Lazrs_Field,
+        value_type: Lazrs_ValueType,
+        values: &mut [T],
+    ) -> Lazrs_Column {
+        assert_eq!(std::mem::size_of::<T>(), value_type.size());
+        Lazrs_Column {
+            field,
+            value_type,
+            data: values.as_mut_ptr() as *mut c_void,
+            stride: 0,
+            apply_scale: false,
+            scale: 1.0,
+            offset: 0.0,
+        }
+    }
+
+    fn scaled(mut column: Lazrs_Column) -> Lazrs_Column {
+        column.apply_scale = true;
+        column.scale = 0.01;
+        column.offset = 100.0;
+        column
+    }
+
+    /// Compresses points of format 1 from columns, returns the LAZ data and the LASzip VLR
+    fn compress(
+        xs: &mut [f64],
+        intensities: &mut [u16],
+        gps_times: &mut [f64],
+    ) -> (Vec<u8>, Vec<u8>) {
+        let columns = [
+            scaled(column(
+                Lazrs_Field::LAZRS_FIELD_X,
+                Lazrs_ValueType::LAZRS_VALUE_F64,
+                xs,
+            )),
+            column(
+                Lazrs_Field::LAZRS_FIELD_INTENSITY,
+                Lazrs_ValueType::LAZRS_VALUE_U16,
+                intensities,
+            ),
+            column(
+                Lazrs_Field::LAZRS_FIELD_GPS_TIME,
+                Lazrs_ValueType::LAZRS_VALUE_F64,
+                gps_times,
+            ),
+        ];
+        unsafe {
+            let fh = libc::tmpfile();
+            assert!(!fh.is_null());
+            let params = Lazrs_CompressorParams {
+                dest_type: Lazrs_DestType::LAZRS_DEST_CFILE,
+                dest: Lazrs_Dest { file: fh },
+                point_format_id: 1,
+                num_extra_bytes: 0,
+            };
+            let mut compressor = std::ptr::null_mut();
+            assert_eq!(
+                lazrs_compressor_new_for_point_format(params, false, &mut compressor),
+                Lazrs_Result::LAZRS_OK
+            );
+            for point_format_id in [0, 3] {
+                let result = lazrs_compressor_compress_columns(
+                    compressor,
+                    point_format_id,
+                    columns.as_ptr(),
+                    columns.len(),
+                    NUM_POINTS,
+                );
+                assert_eq!(result, Lazrs_Result::LAZRS_OTHER);
+            }
+            let result = lazrs_compressor_compress_columns(
+                compressor,
+                1,
+                columns.as_ptr(),
+                columns.len(),
+                NUM_POINTS,
+            );
+            assert_eq!(result, Lazrs_Result::LAZRS_OK);
+            assert_eq!(lazrs_compressor_position(compressor), NUM_POINTS as u64);
+            assert_eq!(lazrs_compressor_done(compressor), Lazrs_Result::LAZRS_OK);
+            let mut vlr = vec![];
+            (*compressor).compressor.vlr().write_to(&mut vlr).unwrap();
+            lazrs_compressor_delete(compressor);
+
+            let mut file = CFile::new_unchecked(fh);
+            let mut data = vec![];
+            file.seek(SeekFrom::Start(0)).unwrap();
+            file.read_to_end(&mut data).unwrap();
+            libc::fclose(fh);
+            (data, vlr)
+        }
+    }
+
+    #[test]
+    fn columns_are_compressed_and_decompressed() {
+        let mut xs: Vec<f64> = (0..NUM_POINTS).map(|i| 100.0 + i as f64 * 0.25).collect();
+        let mut intensities: Vec<u16> = (0..NUM_POINTS).map(|i| i as u16 * 10).collect();
+        let mut gps_times: Vec<f64> = (0..NUM_POINTS).map(|i| 1000.0 + i as f64 * 0.5).collect();
+        let (data, vlr) = compress(&mut xs, &mut intensities, &mut gps_times);
+
+        let mut read_xs = vec![0f64; NUM_POINTS];
+        let mut read_intensities = vec![0u8; NUM_POINTS];
+        let mut read_gps_times = vec![0f64; NUM_POINTS];
+        let mut read_reds = vec![1u16; NUM_POINTS];
+        let columns = [
+            scaled(column(
+                Lazrs_Field::LAZRS_FIELD_X,
+                Lazrs_ValueType::LAZRS_VALUE_F64,
+                &mut read_xs,
+            )),
+            column(
+                Lazrs_Field::LAZRS_FIELD_INTENSITY,
+                Lazrs_ValueType::LAZRS_VALUE_U8,
+                &mut read_intensities,
+            ),
+            column(
+                Lazrs_Field::LAZRS_FIELD_GPS_TIME,
+                Lazrs_ValueType::LAZRS_VALUE_F64,
+                &mut read_gps_times,
+            ),
+            column(
+                Lazrs_Field::LAZRS_FIELD_RED,
+                Lazrs_ValueType::LAZRS_VALUE_U16,
+                &mut read_reds,
+            ),
+        ];
+        unsafe {
+            let params = Lazrs_DecompressorParams {
+                source_type: Lazrs_SourceType::LAZRS_SOURCE_BUFFER,
+                source: Lazrs_Source {
+                    buffer: Lazrs_Buffer {
+                        data: data.as_ptr(),
+                        len: data.len(),
+                    },
+                },
+                source_offset: 0,
+                laszip_vlr: Lazrs_Buffer {
+                    data: vlr.as_ptr(),
+                    len: vlr.len(),
+                },
+            };
+            let mut decompressor = std::ptr::null_mut();
+            assert_eq!(
+                lazrs_decompressor_new(params, false, &mut decompressor),
+                Lazrs_Result::LAZRS_OK
+            );
+            let result = lazrs_decompressor_decompress_columns(
+                decompressor,
+                3,
+                columns.as_ptr(),
+                columns.len(),
+                NUM_POINTS,
+            );
+            assert_eq!(result, Lazrs_Result::LAZRS_OTHER);
+            let result = lazrs_decompressor_decompress_columns(
+                decompressor,
+                1,
+                columns.as_ptr(),
+                columns.len(),
+                NUM_POINTS,
+            );
+            assert_eq!(result, Lazrs_Result::LAZRS_OK);
+            assert_eq!(lazrs_decompressor_position(decompressor), NUM_POINTS as u64);
+            lazrs_decompressor_delete(decompressor);
+        }
+
+        assert_eq!(read_xs, xs);
+        assert_eq!(read_gps_times, gps_times);
+        // Values saturate when converted to a smaller type
+        let expected: Vec<u8> = intensities.iter().map(|&i| i.min(255) as u8).collect();
+        assert_eq!(read_intensities, expected);
+        // Format 1 has no colors
+        assert!(read_reds.iter().all(|&red| red == 0));
+    }
+}
@@ -0,0 +1,813 @@
+//! LASzip's compatibility mode, in which points of formats 6 to 8 are stored as points
+//! of formats 1 and 3 with extra bytes, so that readers which do not know
+//! LAS 1.4 can still read them.
+//!
+//! Formats 9 and 10 would be stored as formats 4 and 5, but laz cannot compress wave packets.
+//!
+//! The fields of the LAS 1.4 formats that do not fit in the legacy ones are stored
+//! in extra bytes attributes, and the LAS 1.4 fields of the header
+//! in the "lascompatible" VLR.
+
+use std::convert::TryInto;
+use std::ops::Range;
+
+use crate::extra_bytes::{
+    Lazrs_ExtraBytesDescriptor, DESCRIPTOR_SIZE, EXTRA_BYTES_RECORD_ID, EXTRA_BYTES_USER_ID,
+    SCALE_BIT,
+};
+use crate::io::CSource;
+use crate::las::{LasMetadata, Vlr, GLOBAL_ENCODING_WKT};
+use crate::point::{PointFormat, SCAN_ANGLE_UNIT};
+use crate::{Lazrs_Result, Lazrs_Source, Lazrs_SourceType};
+
+const COMPATIBILITY_USER_ID: &str = "lascompatible";
+const COMPATIBILITY_RECORD_ID: u16 = 22204;
+const COMPATIBILITY_VERSION: u16 = 3;
+/// Version, compatibility version, 4 unused bytes and the LAS 1.4 fields of the header
+const COMPATIBILITY_DATA_SIZE: usize = 2 + 2 + 4 + 148;
+
+const PROJECTION_USER_ID: &str = "LASF_Projection";
+const WKT_RECORD_ID: u16 = 2112;
+
+/// Names of the extra bytes attributes holding the LAS 1.4 fields
+const SCAN_ANGLE_NAME: &str = "LAS 1.4 scan angle";
+const RETURNS_NAME: &str = "LAS 1.4 extended returns";
+const CLASSIFICATION_NAME: &str = "LAS 1.4 classification";
+const FLAGS_AND_CHANNEL_NAME: &str = "LAS 1.4 flags and channel";
+const NIR_NAME: &str = "LAS 1.4 NIR band";
+const ATTRIBUTES_DESCRIPTION: &str = "additional attributes";
+/// Description of the descriptors added for the extra bytes the file did not describe,
+/// which are removed when undoing the compatibility mode
+const UNDESCRIBED_DESCRIPTION: &str = "undescribed extra bytes";
+
+/// Data types of the Extra Bytes VLR
+const U8_DATA_TYPE: u8 = 1;
+const U16_DATA_TYPE: u8 = 3;
+const I16_DATA_TYPE: u8 = 4;
+
+/// Rounds like LASzip does, so that the scan angles match the ones it computes
+fn quantize(value: f32) -> i16 {
+    if value >= 0.0 {
+        (value + 0.5) as i16
+    } else {
+        (value - 0.5) as i16
+    }
+}
+
+/// The return number and number of returns stored in the legacy fields, as LASzip chooses them
+fn legacy_returns(return_number: u8, number_of_returns: u8) -> (u8, u8) {
+    if number_of_returns <= 7 {
+        (return_number.min(7), number_of_returns)
+    } else if return_number <= 4 {
+        (return_number, 7)
+    } else {
+        let difference = i32::from(number_of_returns) - i32::from(return_number);
+        let return_number = if difference <= 0 {
+            7
+        } else if difference >= 3 {
+            4
+        } else {
+            7 - difference as u8
+        };
+        (return_number, 7)
+    }
+}
+
+/// Creates the descriptor of an attribute
+fn descriptor_data(data_type: u8, options: u8, name: &str, scale: f64) -> Vec<u8> {
+    let mut data = vec![0u8; DESCRIPTOR_SIZE];
+    data[2] = data_type;
+    data[3] = options;
+    data[4..4 + name.len()].copy_from_slice(name.as_bytes());
+    data[112..120].copy_from_slice(&scale.to_le_bytes());
+    data[160..160 + ATTRIBUTES_DESCRIPTION.len()]
+        .copy_from_slice(ATTRIBUTES_DESCRIPTION.as_bytes());
+    data
+}
+
+/// Creates the descriptor of extra bytes the file did not describe
+fn undescribed_descriptor_data(size: u8) -> Vec<u8> {
+    let mut data = vec![0u8; DESCRIPTOR_SIZE];
+    data[3] = size;
+    data[160..160 + UNDESCRIBED_DESCRIPTION.len()]
+        .copy_from_slice(UNDESCRIBED_DESCRIPTION.as_bytes());
+    data
+}
+
+/// Whether the descriptor was created by `undescribed_descriptor_data`
+fn is_undescribed(data: &[u8]) -> bool {
+    data[2] == 0
+        && data[160..].split(|&b| b == 0).next() == Some(UNDESCRIBED_DESCRIPTION.as_bytes())
+}
+
+/// Replaces the VLR with the same ids, or adds it at the end
+fn replace_vlr(vlrs: &mut Vec<Vlr>, user_id: &str, record_id: u16, data: Vec<u8>) {
+    let vlr = Vlr::new(user_id, record_id, "", data);
+    match vlrs.iter_mut().find(|v| v.is(user_id, record_id)) {
+        Some(existing) => *existing = vlr,
+        None => vlrs.push(vlr),
+    }
+}
+
+/// How the records of a file in compatibility mode map to the records of its LAS 1.4 format
+pub(crate) struct Compatibility {
+    native: PointFormat,
+    legacy: PointFormat,
+    /// Sizes of the records, extra bytes included
+    pub(crate) native_point_size: usize,
+    pub(crate) legacy_point_size: usize,
+    /// Where the attributes are in the extra bytes of the legacy records
+    scan_angle: usize,
+    returns: usize,
+    classification: usize,
+    flags_and_channel: usize,
+    nir: Option<usize>,
+    /// Where the other extra bytes are in the extra bytes of the legacy records,
+    /// they are the extra bytes of the native records, in this order
+    extra_bytes: Vec<Range<usize>>,
+}
+
+impl Compatibility {
+    /// Converts the metadata of a file of formats 6 to 8 to compatibility mode,
+    /// `None` if the file has a format of LAS 1.2, which needs no conversion
+    ///
+    /// The header becomes a LAS 1.2 one, so the EVLRs are moved to the VLRs.
+    /// LAZRS_UNSUPPORTED_POINT_FORMAT is returned for the formats with wave packets,
+    /// LAZRS_OTHER if an EVLR is too large to be a VLR, if the file
+    /// has more points than LAS 1.2 can count, or if its Extra Bytes VLR
+    /// describes more bytes than the points have.
+    pub(crate) fn enter(metadata: &mut LasMetadata) -> Result<Option<Self>, Lazrs_Result> {
+        let native_id = metadata.header.point_format_id();
+        let legacy_id = match native_id {
+            6 => 1,
+            7 | 8 => 3,
+            9 | 10 => return Err(Lazrs_Result::LAZRS_UNSUPPORTED_POINT_FORMAT),
+            _ => return Ok(None),
+        };
+        let native = PointFormat::new(native_id).unwrap();
+        let legacy = PointFormat::new(legacy_id).unwrap();
+        let num_extra_bytes = usize::from(
+            metadata
+                .header
+                .num_extra_bytes()
+                .ok_or(Lazrs_Result::LAZRS_UNSUPPORTED_POINT_FORMAT)?,
+        );
+        let num_points = metadata.header.number_of_points();
+        if num_points > u64::from(u32::MAX)
+            || metadata
+                .evlrs
+                .iter()
+                .any(|evlr| evlr.data.len() > usize::from(u16::MAX))
+        {
+            return Err(Lazrs_Result::LAZRS_OTHER);
+        }
+
+        // The attributes follow the extra bytes the points already have,
+        // which must all be described
+        let mut descriptors = metadata
+            .vlrs
+            .iter()
+            .find(|vlr| vlr.is(EXTRA_BYTES_USER_ID, EXTRA_BYTES_RECORD_ID))
+            .map_or_else(Vec::new, |vlr| vlr.data.clone());
+        if descriptors.len() % DESCRIPTOR_SIZE != 0 {
+            return Err(Lazrs_Result::LAZRS_OTHER);
+        }
+        let mut described = 0;
+        for data in descriptors.chunks_exact(DESCRIPTOR_SIZE) {
+            let descriptor = Lazrs_ExtraBytesDescriptor::read_from(data, described)
+                .ok_or(Lazrs_Result::LAZRS_OTHER)?;
+            described += descriptor.size;
+        }
+        if described > num_extra_bytes {
+            return Err(Lazrs_Result::LAZRS_OTHER);
+        }
+        let mut undescribed = num_extra_bytes - described;
+        while undescribed > 0 {
+            let size = undescribed.min(usize::from(u8::MAX));
+            descriptors.extend(undescribed_descriptor_data(size as u8));
+            undescribed -= size;
+        }
+        let scan_angle = num_extra_bytes;
+        descriptors.extend(descriptor_data(
+            I16_DATA_TYPE,
+            SCALE_BIT,
+            SCAN_ANGLE_NAME,
+            0.006,
+        ));
+        for name in &[RETURNS_NAME, CLASSIFICATION_NAME, FLAGS_AND_CHANNEL_NAME] {
+            descriptors.extend(descriptor_data(U8_DATA_TYPE, 0, name, 0.0));
+        }
+        let mut attributes_size = 5;
+        let nir = native.nir_offset.map(|_| {
+            descriptors.extend(descriptor_data(U16_DATA_TYPE, 0, NIR_NAME, 0.0));
+            attributes_size += 2;
+            scan_angle + 5
+        });
+        replace_vlr(
+            &mut metadata.vlrs,
+            EXTRA_BYTES_USER_ID,
+            EXTRA_BYTES_RECORD_ID,
+            descriptors,
+        );
+
+        for mut evlr in metadata.evlrs.drain(..) {
+            evlr.extended = false;
+            metadata.vlrs.push(evlr);
+        }
+
+        // The LAS 1.4 fields of the header are kept in the compatibility VLR
+        let by_return = metadata.header.number_of_points_by_return();
+        metadata.header.upgrade_to_1_4()?;
+        metadata.header.set_number_of_points(num_points, &by_return);
+        metadata.header.set_evlrs(0, 0);
+        let fields = metadata.header.downgrade_to_1_2();
+        let mut data = Vec::with_capacity(COMPATIBILITY_DATA_SIZE);
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(&COMPATIBILITY_VERSION.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&fields);
+        replace_vlr(
+            &mut metadata.vlrs,
+            COMPATIBILITY_USER_ID,
+            COMPATIBILITY_RECORD_ID,
+            data,
+        );
+
+        let global_encoding = metadata.header.global_encoding();
+        metadata
+            .header
+            .set_global_encoding(global_encoding & !GLOBAL_ENCODING_WKT);
+        metadata.header.set_point_format(legacy_id, false);
+        let legacy_point_size = legacy.size + num_extra_bytes + attributes_size;
+        metadata.header.set_point_size(legacy_point_size as u16);
+        let mut legacy_by_return = [0u64; 15];
+        legacy_by_return[..5].copy_from_slice(&by_return[..5]);
+        metadata
+            .header
+            .set_number_of_points(num_points, &legacy_by_return);
+
+        Ok(Some(Self {
+            native,
+            legacy,
+            native_point_size: native.size + num_extra_bytes,
+            legacy_point_size,
+            scan_angle,
+            returns: scan_angle + 2,
+            classification: scan_angle + 3,
+            flags_and_channel: scan_angle + 4,
+            nir,
+            extra_bytes: std::iter::once(0..num_extra_bytes).collect(),
+        }))
+    }
+
+    /// Converts the metadata of a file in compatibility mode back to its LAS 1.4 format,
+    /// `None` (and the metadata is left as is) if the file is not in compatibility mode
+    pub(crate) fn undo(metadata: &mut LasMetadata) -> Option<Self> {
+        let legacy_id = metadata.header.point_format_id();
+        if !matches!(legacy_id, 1 | 3) {
+            return None;
+        }
+        let compatibility_data = &metadata
+            .vlrs
+            .iter()
+            .find(|vlr| vlr.is(COMPATIBILITY_USER_ID, COMPATIBILITY_RECORD_ID))?
+            .data;
+        if compatibility_data.len() < COMPATIBILITY_DATA_SIZE
+            || u16::from_le_bytes(compatibility_data[2..4].try_into().unwrap())
+                > COMPATIBILITY_VERSION
+        {
+            return None;
+        }
+        let fields = compatibility_data[8..COMPATIBILITY_DATA_SIZE].to_vec();
+        let num_extra_bytes = usize::from(metadata.header.num_extra_bytes()?);
+
+        let extra_bytes_vlr = metadata
+            .vlrs
+            .iter()
+            .position(|vlr| vlr.is(EXTRA_BYTES_USER_ID, EXTRA_BYTES_RECORD_ID))?;
+        let (mut scan_angle, mut returns, mut classification, mut flags_and_channel, mut nir) =
+            (None, None, None, None, None);
+        let mut kept_descriptors = Vec::new();
+        let mut extra_bytes = Vec::new();
+        let mut offset = 0;
+        for data in metadata.vlrs[extra_bytes_vlr]
+            .data
+            .chunks_exact(DESCRIPTOR_SIZE)
+        {
+            let descriptor = Lazrs_ExtraBytesDescriptor::read_from(data, offset)?;
+            let mut attributes = [
+                (SCAN_ANGLE_NAME, &mut scan_angle, 2),
+                (RETURNS_NAME, &mut returns, 1),
+                (CLASSIFICATION_NAME, &mut classification, 1),
+                (FLAGS_AND_CHANNEL_NAME, &mut flags_and_channel, 1),
+                (NIR_NAME, &mut nir, 2),
+            ];
+            let attribute = attributes
+                .iter_mut()
+                .find(|(name, _, _)| descriptor.has_name(name.as_bytes()));
+            match attribute {
+                Some((_, position, size)) if descriptor.size == *size => **position = Some(offset),
+                Some(_) => return None,
+                None => {
+                    if !is_undescribed(data) {
+                        kept_descriptors.extend_from_slice(data);
+                    }
+                    extra_bytes.push(offset..offset + descriptor.size);
+                }
+            }
+            offset += descriptor.size;
+        }
+        if offset > num_extra_bytes {
+            return None;
+        }
+        if offset < num_extra_bytes {
+            extra_bytes.push(offset..num_extra_bytes);
+        }
+
+        let native_id = match (legacy_id, nir) {
+            (1, _) => 6,
+            (_, Some(_)) => 8,
+            _ => 7,
+        };
+        let native = PointFormat::new(native_id).unwrap();
+        let legacy = PointFormat::new(legacy_id).unwrap();
+        if nir.is_some() != native.nir_offset.is_some() {
+            return None;
+        }
+        let compatibility = Self {
+            native,
+            legacy,
+            native_point_size: native.size + extra_bytes.iter().map(|r| r.len()).sum::<usize>(),
+            legacy_point_size: legacy.size + num_extra_bytes,
+            scan_angle: scan_angle?,
+            returns: returns?,
+            classification: classification?,
+            flags_and_channel: flags_and_channel?,
+            nir,
+            extra_bytes,
+        };
+
+        if kept_descriptors.is_empty() {
+            metadata.vlrs.remove(extra_bytes_vlr);
+        } else {
+            metadata.vlrs[extra_bytes_vlr].data = kept_descriptors;
+        }
+        metadata
+            .vlrs
+            .retain(|vlr| !vlr.is(COMPATIBILITY_USER_ID, COMPATIBILITY_RECORD_ID));
+        metadata.header.restore_1_4_fields(&fields);
+        let compressed = metadata.header.is_compressed();
+        metadata.header.set_point_format(native_id, compressed);
+        metadata
+            .header
+            .set_point_size(compatibility.native_point_size as u16);
+        let num_points = metadata.header.number_of_points();
+        let by_return = metadata.header.number_of_points_by_return();
+        metadata.header.set_number_of_points(num_points, &by_return);
+        if metadata
+            .vlrs
+            .iter()
+            .any(|vlr| vlr.is(PROJECTION_USER_ID, WKT_RECORD_ID))
+        {
+            let global_encoding = metadata.header.global_encoding();
+            metadata
+                .header
+                .set_global_encoding(global_encoding | GLOBAL_ENCODING_WKT);
+        }
+        Some(compatibility)
+    }
+
+    /// Converts records of the LAS 1.4 format to records in compatibility mode
+    pub(crate) fn to_legacy(&self, native_points: &[u8], legacy_points: &mut [u8]) {
+        let (native, legacy) = (&self.native, &self.legacy);
+        for (n, l) in native_points
+            .chunks_exact(self.native_point_size)
+            .zip(legacy_points.chunks_exact_mut(self.legacy_point_size))
+        {
+            l[..14].copy_from_slice(&n[..14]);
+            let flags = n[15];
+            let (return_number, number_of_returns) = (n[14] & 0x0F, n[14] >> 4);
+            let (legacy_return_number, legacy_number_of_returns) =
+                legacy_returns(return_number, number_of_returns);
+            // The scan direction and edge of flight line flags are the high bits of both
+            l[14] = legacy_return_number | legacy_number_of_returns << 3 | (flags & 0xC0);
+            let classification = n[16];
+            let (legacy_classification, extra_classification) = if classification > 0x1F {
+                (0, classification)
+            } else {
+                (classification, 0)
+            };
+            // Synthetic, key point and withheld
+            l[15] = legacy_classification | (flags & 0x07) << 5;
+            let scan_angle = i16::from_le_bytes(n[18..20].try_into().unwrap());
+            let scan_angle_rank =
+                quantize(SCAN_ANGLE_UNIT * f32::from(scan_angle)).clamp(-128, 127);
+            let scan_angle_remainder =
+                scan_angle.wrapping_sub(quantize(f32::from(scan_angle_rank) / SCAN_ANGLE_UNIT));
+            l[16] = scan_angle_rank as i8 as u8;
+            l[17] = n[17];
+            l[18..20].copy_from_slice(&n[20..22]);
+            copy_field(native.gps_time_offset, n, legacy.gps_time_offset, l, 8);
+            copy_field(native.rgb_offset, n, legacy.rgb_offset, l, 6);
+
+            let extra = &mut l[legacy.size..];
+            let mut start = native.size;
+            for range in &self.extra_bytes {
+                extra[range.clone()].copy_from_slice(&n[start..start + range.len()]);
+                start += range.len();
+            }
+            extra[self.scan_angle..self.scan_angle + 2]
+                .copy_from_slice(&scan_angle_remainder.to_le_bytes());
+            extra[self.returns] = (return_number - legacy_return_number) << 4
+                | (number_of_returns - legacy_number_of_returns);
+            extra[self.classification] = extra_classification;
+            extra[self.flags_and_channel] = ((flags >> 4) & 0x03) << 1 | ((flags >> 3) & 0x01);
+            if let (Some(nir), Some(nir_offset)) = (self.nir, native.nir_offset) {
+                extra[nir..nir + 2].copy_from_slice(&n[nir_offset..nir_offset + 2]);
+            }
+        }
+    }
+
+    /// Converts records in compatibility mode back to records of the LAS 1.4 format
+    pub(crate) fn to_native(&self, legacy_points: &[u8], native_points: &mut [u8]) {
+        let (native, legacy) = (&self.native, &self.legacy);
+        for (l, n) in legacy_points
+            .chunks_exact(self.legacy_point_size)
+            .zip(native_points.chunks_exact_mut(self.native_point_size))
+        {
+            n[..14].copy_from_slice(&l[..14]);
+            let extra = &l[legacy.size..];
+            let returns = extra[self.returns];
+            let flags_and_channel = extra[self.flags_and_channel];
+            let return_number = (l[14] & 0x07).wrapping_add(returns >> 4);
+            let number_of_returns = ((l[14] >> 3) & 0x07).wrapping_add(returns & 0x0F);
+            n[14] = (return_number & 0x0F) | (number_of_returns & 0x0F) << 4;
+            n[15] = (l[15] >> 5)
+                | (flags_and_channel & 0x01) << 3
+                | ((flags_and_channel >> 1) & 0x03) << 4
+                | (l[14] & 0xC0);
+            n[16] = (l[15] & 0x1F).wrapping_add(extra[self.classification]);
+            n[17] = l[17];
+            let scan_angle_remainder = i16::from_le_bytes(
+                extra[self.scan_angle..self.scan_angle + 2]
+                    .try_into()
+                    .unwrap(),
+            );
+            let scan_angle_rank = l[16] as i8;
+            let scan_angle = scan_angle_remainder
+                .wrapping_add(quantize(f32::from(scan_angle_rank) / SCAN_ANGLE_UNIT));
+            n[18..20].copy_from_slice(&scan_angle.to_le_bytes());
+            n[20..22].copy_from_slice(&l[18..20]);
+            copy_field(legacy.gps_time_offset, l, native.gps_time_offset, n, 8);
+            copy_field(legacy.rgb_offset, l, native.rgb_offset, n, 6);
+            if let (Some(nir), Some(nir_offset)) = (self.nir, native.nir_offset) {
+                n[nir_offset..nir_offset + 2].copy_from_slice(&extra[nir..nir + 2]);
+            }
+
+            let mut start = native.size;
+            for range in &self.extra_bytes {
+                n[start..start + range.len()].copy_from_slice(&extra[range.clone()]);
+                start += range.len();
+            }
+        }
+    }
+}
+
+/// Copies a field both formats have
+fn copy_field(
+    src_offset: Option<usize>,
+    src: &[u8],
+    dst_offset: Option<usize>,
+    dst: &mut [u8],
+    size: usize,
+) {
+    if let (Some(src_offset), Some(dst_offset)) = (src_offset, dst_offset) {
+        dst[dst_offset..dst_offset + size].copy_from_slice(&src[src_offset..src_offset + size]);
+    }
+}
+
+/// How to convert the records of a file in LASzip's compatibility mode
+/// back to records of its LAS 1.4 format
+///
+/// Only `lazrs_laz_to_las` undoes the compatibility mode by itself.
+/// The decompressors, `lazrs_decompressor_decompress_columns`,
+/// `lazrs_decompressor_decompress_filtered`, `lazrs_query_bbox`, `lazrs_decompress_recover`,
+/// `lazrs_rechunk` and `lazrs_convert_point_format` give the records as they are stored,
+/// which can be converted with `lazrs_compatibility_mode_to_native`.
+pub struct Lazrs_CompatibilityMode {
+    compatibility: Compatibility,
+    point_format_id: u8,
+}
+
+/// Reads the header and VLRs of a LAS or LAZ file to know if it is in compatibility mode
+///
+/// The file is read from the current position of the source.
+///
+/// @source_type: type of the source
+/// @source: where the file is read from
+/// @mode: will receive how to convert the records, to be freed with
+///        `lazrs_compatibility_mode_delete`, or NULL if the file is not
+///        in compatibility mode or if an error occurred
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_compatibility_mode_read(
+    source_type: Lazrs_SourceType,
+    source: Lazrs_Source,
+    mode: *mut *mut Lazrs_CompatibilityMode,
+) -> Lazrs_Result {
+    debug_assert!(!mode.is_null());
+    *mode = std::ptr::null_mut();
+    let mut src = match CSource::from_c_source(source_type, source) {
+        Ok(src) => src,
+        Err(result) => return result,
+    };
+    let mut metadata = match LasMetadata::read_from(&mut src) {
+        Ok(metadata) => metadata,
+        Err(error) => return error.into(),
+    };
+    if let Some(compatibility) = Compatibility::undo(&mut metadata) {
+        *mode = Box::into_raw(Box::new(Lazrs_CompatibilityMode {
+            compatibility,
+            point_format_id: metadata.header.point_format_id(),
+        }));
+    }
+    Lazrs_Result::LAZRS_OK
+}
+
+/// Frees the compatibility mode
+///
+/// @mode can be NULL (no-op)
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_compatibility_mode_delete(mode: *mut Lazrs_CompatibilityMode) {
+    if !mode.is_null() {
+        let _ = Box::from_raw(mode);
+    }
+}
+
+/// Returns the LAS 1.4 point format of the file (6, 7 or 8)
+///
+/// @mode: must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_compatibility_mode_point_format(
+    mode: *const Lazrs_CompatibilityMode,
+) -> u8 {
+    debug_assert!(!mode.is_null());
+    (*mode).point_format_id
+}
+
+/// Returns the size of the records of the LAS 1.4 point format, extra bytes included
+///
+/// @mode: must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_compatibility_mode_point_size(
+    mode: *const Lazrs_CompatibilityMode,
+) -> usize {
+    debug_assert!(!mode.is_null());
+    (*mode).compatibility.native_point_size
+}
+
+/// Returns the size of the records as they are stored in the file
+///
+/// @mode: must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_compatibility_mode_legacy_point_size(
+    mode: *const Lazrs_CompatibilityMode,
+) -> usize {
+    debug_assert!(!mode.is_null());
+    (*mode).compatibility.legacy_point_size
+}
+
+/// Converts records as stored in the file to records of the LAS 1.4 point format
+///
+/// LAZRS_OTHER is returned if `legacy_size` is not a multiple of the size of the
+/// stored records, or if `native_size` is too small for the converted records.
+///
+/// @mode: must not be NULL
+/// @legacy_points: the records as decompressed
+/// @legacy_size: size of `legacy_points`
+/// @native_points: will receive the records of the LAS 1.4 point format
+/// @native_size: size of `native_points`
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_compatibility_mode_to_native(
+    mode: *const Lazrs_CompatibilityMode,
+    legacy_points: *const u8,
+    legacy_size: usize,
+    native_points: *mut u8,
+    native_size: usize,
+) -> Lazrs_Result {
+    debug_assert!(!mode.is_null());
+    debug_assert!(!legacy_points.is_null());
+    debug_assert!(!native_points.is_null());
+    let compatibility = &(*mode).compatibility;
+    let num_points = legacy_size / compatibility.legacy_point_size;
+    if num_points * compatibility.legacy_point_size != legacy_size
+        || native_size < num_points * compatibility.native_point_size
+    {
+        return Lazrs_Result::LAZRS_OTHER;
+    }
+    compatibility.to_native(
+        std::slice::from_raw_parts(legacy_points, legacy_size),
+        std::slice::from_raw_parts_mut(native_points, native_size),
+    );
+    Lazrs_Result::LAZRS_OK
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::las::Header;
+
+    /// A LAS 1.4 file without points, with an EVLR
+    fn las_1_4_metadata(
+        point_format_id: u8,
+        num_extra_bytes: u16,
+        descriptors: Vec<u8>,
+    ) -> LasMetadata {
+        let mut raw = vec![0u8; 375];
+        raw[..4].copy_from_slice(b"LASF");
+        raw[24] = 1;
+        raw[25] = 4;
+        raw[94..96].copy_from_slice(&375u16.to_le_bytes());
+        let mut header = Header::read_from(&mut raw.as_slice()).unwrap();
+        header.set_point_format(point_format_id, true);
+        let size = crate::las::point_format_size(point_format_id).unwrap();
+        header.set_point_size(size + num_extra_bytes);
+        header.set_global_encoding(GLOBAL_ENCODING_WKT);
+        let mut by_return = [0u64; 15];
+        by_return[0] = 10;
+        header.set_number_of_points(10, &by_return);
+
+        let mut vlrs = vec![Vlr::new(
+            PROJECTION_USER_ID,
+            WKT_RECORD_ID,
+            "",
+            b"WKT".to_vec(),
+        )];
+        if !descriptors.is_empty() {
+            vlrs.push(Vlr::new(
+                EXTRA_BYTES_USER_ID,
+                EXTRA_BYTES_RECORD_ID,
+                "",
+                descriptors,
+            ));
+        }
+        let mut evlr = Vlr::new("user", 1, "", vec![1, 2, 3]);
+        evlr.extended = true;
+        LasMetadata {
+            header,
+            vlrs,
+            padding: Vec::new(),
+            evlrs: vec![evlr],
+        }
+    }
+
+    fn pseudo_random_bytes(len: usize) -> Vec<u8> {
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn legacy_returns_keep_what_they_can() {
+        assert_eq!(legacy_returns(3, 5), (3, 5));
+        assert_eq!(legacy_returns(9, 5), (7, 5));
+        assert_eq!(legacy_returns(4, 12), (4, 7));
+        assert_eq!(legacy_returns(10, 12), (5, 7));
+        assert_eq!(legacy_returns(11, 12), (6, 7));
+        assert_eq!(legacy_returns(12, 12), (7, 7));
+        assert_eq!(legacy_returns(5, 15), (4, 7));
+    }
+
+    #[test]
+    fn only_the_formats_6_to_8_enter_compatibility_mode() {
+        let mut metadata = las_1_4_metadata(1, 0, Vec::new());
+        assert!(Compatibility::enter(&mut metadata).unwrap().is_none());
+        assert!(Compatibility::undo(&mut metadata).is_none());
+        assert_eq!(metadata.header.point_format_id(), 1);
+
+        let mut metadata = las_1_4_metadata(9, 0, Vec::new());
+        assert_eq!(
+            Compatibility::enter(&mut metadata).err(),
+            Some(Lazrs_Result::LAZRS_UNSUPPORTED_POINT_FORMAT)
+        );
+        assert_eq!(metadata.header.point_format_id(), 9);
+        assert_eq!(metadata.evlrs.len(), 1);
+
+        // The descriptors describe more bytes than the points have
+        let mut metadata = las_1_4_metadata(6, 0, descriptor_data(U16_DATA_TYPE, 0, "a", 0.0));
+        assert_eq!(
+            Compatibility::enter(&mut metadata).err(),
+            Some(Lazrs_Result::LAZRS_OTHER)
+        );
+    }
+
+    #[test]
+    fn metadata_is_converted_back_and_forth() {
+        let descriptor = descriptor_data(U8_DATA_TYPE, 0, "a", 0.0);
+        let mut metadata = las_1_4_metadata(8, 3, descriptor.clone());
+        let compatibility = Compatibility::enter(&mut metadata).unwrap().unwrap();
+        assert_eq!(compatibility.native_point_size, 38 + 3);
+        assert_eq!(compatibility.legacy_point_size, 34 + 3 + 7);
+        let header = &metadata.header;
+        assert_eq!(header.point_format_id(), 3);
+        assert_eq!(
+            usize::from(header.point_size()),
+            compatibility.legacy_point_size
+        );
+        assert_eq!(header.header_size(), 227);
+        assert_eq!(header.global_encoding() & GLOBAL_ENCODING_WKT, 0);
+        assert_eq!(header.number_of_points(), 10);
+        assert!(metadata.evlrs.is_empty());
+        assert!(metadata
+            .vlrs
+            .iter()
+            .any(|vlr| vlr.is("user", 1) && !vlr.extended));
+        let descriptors = &metadata
+            .vlrs
+            .iter()
+            .find(|vlr| vlr.is(EXTRA_BYTES_USER_ID, EXTRA_BYTES_RECORD_ID))
+            .unwrap()
+            .data;
+        // "a", the 2 undescribed bytes, and the 5 attributes
+        assert_eq!(descriptors.len(), 7 * DESCRIPTOR_SIZE);
+        assert!(is_undescribed(
+            &descriptors[DESCRIPTOR_SIZE..2 * DESCRIPTOR_SIZE]
+        ));
+
+        let undone = Compatibility::undo(&mut metadata).unwrap();
+        assert_eq!(undone.native_point_size, compatibility.native_point_size);
+        assert_eq!(undone.legacy_point_size, compatibility.legacy_point_size);
+        assert_eq!(undone.extra_bytes, vec![0..1, 1..3]);
+        let header = &metadata.header;
+        assert_eq!(header.point_format_id(), 8);
+        assert_eq!(header.point_size(), 38 + 3);
+        assert_eq!(header.header_size(), 375);
+        assert_eq!(
+            header.global_encoding() & GLOBAL_ENCODING_WKT,
+            GLOBAL_ENCODING_WKT
+        );
+        assert_eq!(header.number_of_points(), 10);
+        assert_eq!(header.number_of_points_by_return()[0], 10);
+        assert!(!metadata
+            .vlrs
+            .iter()
+            .any(|vlr| vlr.is(COMPATIBILITY_USER_ID, COMPATIBILITY_RECORD_ID)));
+        // Only the descriptors of the file remain
+        let descriptors = &metadata
+            .vlrs
+            .iter()
+            .find(|vlr| vlr.is(EXTRA_BYTES_USER_ID, EXTRA_BYTES_RECORD_ID))
+            .unwrap()
+            .data;
+        assert_eq!(descriptors, &descriptor);
+    }
+
+    #[test]
+    fn the_extra_bytes_vlr_is_removed_if_it_only_had_the_added_descriptors() {
+        let mut metadata = las_1_4_metadata(7, 4, Vec::new());
+        Compatibility::enter(&mut metadata).unwrap().unwrap();
+        let undone = Compatibility::undo(&mut metadata).unwrap();
+        assert_eq!(undone.native_point_size, 36 + 4);
+        assert_eq!(metadata.header.point_format_id(), 7);
+        assert!(!metadata
+            .vlrs
+            .iter()
+            .any(|vlr| vlr.is(EXTRA_BYTES_USER_ID, EXTRA_BYTES_RECORD_ID)));
+    }
+
+    #[test]
+    fn records_are_converted_back_and_forth() {
+        for &(point_format_id, num_extra_bytes) in &[(6, 0), (7, 2), (8, 5)] {
+            let mut metadata = las_1_4_metadata(point_format_id, num_extra_bytes, Vec::new());
+            let compatibility = Compatibility::enter(&mut metadata).unwrap().unwrap();
+            let num_points = 500;
+            let native_points = pseudo_random_bytes(num_points * compatibility.native_point_size);
+            let mut legacy_points = vec![0u8; num_points * compatibility.legacy_point_size];
+            compatibility.to_legacy(&native_points, &mut legacy_points);
+
+            let legacy = &compatibility.legacy;
+            for (n, l) in native_points
+                .chunks_exact(compatibility.native_point_size)
+                .zip(legacy_points.chunks_exact(compatibility.legacy_point_size))
+            {
+                assert_eq!(&l[..14], &n[..14]);
+                assert_eq!(l[14] & 0x07, legacy_returns(n[14] & 0x0F, n[14] >> 4).0);
+                let classification = if n[16] > 0x1F { 0 } else { n[16] };
+                assert_eq!(l[15] & 0x1F, classification);
+                let gps_time_offset = legacy.gps_time_offset.unwrap();
+                assert_eq!(&l[gps_time_offset..gps_time_offset + 8], &n[22..30]);
+            }
+
+            // The records are read back with what is stored in the file
+            let compatibility = Compatibility::undo(&mut metadata).unwrap();
+            let mut native_again = vec![0u8; native_points.len()];
+            compatibility.to_native(&legacy_points, &mut native_again);
+            assert_eq!(native_again, native_points, "format {}", point_format_id);
+        }
+    }
+}
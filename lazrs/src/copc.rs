@@ -0,0 +1,1108 @@
+//! Reading and writing of COPC (Cloud Optimized Point Cloud) files: LAZ 1.4 files whose
+//! variable-size chunks are the nodes of an octree, described by hierarchy pages.
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use laz::laszip::{ChunkTable, ChunkTableEntry};
+
+use crate::chunks::{compress_chunk, decompress_chunk};
+use crate::compatibility::Compatibility;
+use crate::filter::Lazrs_Bounds;
+use crate::io::{CDest, CSource};
+use crate::las::{LasMetadata, Vlr};
+use crate::point::{Lazrs_Point, PointFormat};
+use crate::query::batch_size;
+use crate::transcode::{open, read_laz_metadata, PointReader};
+use crate::{
+    Decompressor, Lazrs_Dest, Lazrs_DestType, Lazrs_Result, Lazrs_Source, Lazrs_SourceType,
+};
+
+/// User id of the COPC info VLR and of the hierarchy EVLR
+const COPC_USER_ID: &str = "copc";
+/// Record id of the COPC info VLR
+const COPC_INFO_RECORD_ID: u16 = 1;
+/// Record id of the hierarchy EVLR
+const COPC_HIERARCHY_RECORD_ID: u16 = 1000;
+const COPC_INFO_SIZE: usize = 160;
+const HIERARCHY_ENTRY_SIZE: usize = 32;
+/// The COPC info VLR must be the first one, right after a LAS 1.4 header
+const COPC_HEADER_SIZE: u16 = 375;
+const VLR_HEADER_SIZE: u64 = 54;
+const EVLR_HEADER_SIZE: u64 = 60;
+/// Number of voxels along the sides of the root node, when the spacing is not given
+const DEFAULT_ROOT_VOXELS: f64 = 128.0;
+/// Nodes at this level keep all their points, which can only be duplicates
+const MAX_LEVEL: i32 = 24;
+
+/// Options of `lazrs_las_to_copc`
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Lazrs_LasToCopcOptions {
+    /// Space between the points kept in the root node, halved at each level,
+    /// 0 to use 1/128 of the size of the root node
+    pub(crate) spacing: f64,
+    /// Whether to decompress and compress using multiple threads, if possible
+    pub(crate) prefer_parallel: bool,
+}
+
+/// The content of the COPC info VLR
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Lazrs_CopcInfo {
+    /// Center of the root node
+    pub(crate) center_x: f64,
+    pub(crate) center_y: f64,
+    pub(crate) center_z: f64,
+    /// Half of the size of the root node, in every dimension
+    pub(crate) halfsize: f64,
+    /// Space between points at the root node
+    pub(crate) spacing: f64,
+    /// Position of the root hierarchy page, from the start of the file
+    pub(crate) root_hier_offset: u64,
+    pub(crate) root_hier_size: u64,
+    pub(crate) gpstime_minimum: f64,
+    pub(crate) gpstime_maximum: f64,
+}
+
+impl Lazrs_CopcInfo {
+    fn from_vlr_data(data: &[u8]) -> Option<Self> {
+        if data.len() < COPC_INFO_SIZE {
+            return None;
+        }
+        let f64_at = |i: usize| f64::from_le_bytes(data[8 * i..8 * i + 8].try_into().unwrap());
+        let u64_at = |i: usize| u64::from_le_bytes(data[8 * i..8 * i + 8].try_into().unwrap());
+        Some(Self {
+            center_x: f64_at(0),
+            center_y: f64_at(1),
+            center_z: f64_at(2),
+            halfsize: f64_at(3),
+            spacing: f64_at(4),
+            root_hier_offset: u64_at(5),
+            root_hier_size: u64_at(6),
+            gpstime_minimum: f64_at(7),
+            gpstime_maximum: f64_at(8),
+        })
+    }
+
+    fn to_vlr_data(self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(COPC_INFO_SIZE);
+        for value in [
+            self.center_x,
+            self.center_y,
+            self.center_z,
+            self.halfsize,
+            self.spacing,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&self.root_hier_offset.to_le_bytes());
+        data.extend_from_slice(&self.root_hier_size.to_le_bytes());
+        data.extend_from_slice(&self.gpstime_minimum.to_le_bytes());
+        data.extend_from_slice(&self.gpstime_maximum.to_le_bytes());
+        data.resize(COPC_INFO_SIZE, 0);
+        data
+    }
+
+    /// Size of the sides of the nodes of the level
+    fn node_size(&self, level: i32) -> f64 {
+        2.0 * self.halfsize / f64::from(level).exp2()
+    }
+
+    /// The cube of a node
+    fn bounds(&self, key: &Lazrs_CopcVoxelKey) -> Lazrs_Bounds {
+        let size = self.node_size(key.level);
+        let min = |center: f64, i: i32| center - self.halfsize + f64::from(i) * size;
+        let (min_x, min_y, min_z) = (
+            min(self.center_x, key.x),
+            min(self.center_y, key.y),
+            min(self.center_z, key.z),
+        );
+        Lazrs_Bounds {
+            min_x,
+            min_y,
+            min_z,
+            max_x: min_x + size,
+            max_y: min_y + size,
+            max_z: min_z + size,
+        }
+    }
+}
+
+/// Identifies a node of the octree: its depth and its position at that depth
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Lazrs_CopcVoxelKey {
+    pub(crate) level: i32,
+    pub(crate) x: i32,
+    pub(crate) y: i32,
+    pub(crate) z: i32,
+}
+
+/// A node of the octree and where its chunk is
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Lazrs_CopcEntry {
+    pub(crate) key: Lazrs_CopcVoxelKey,
+    /// Position of the chunk, from the start of the file
+    pub(crate) offset: u64,
+    pub(crate) byte_size: i32,
+    /// Number of points of the node, 0 if it has none
+    pub(crate) point_count: i32,
+}
+
+impl Lazrs_CopcEntry {
+    fn read_from(data: &[u8]) -> Self {
+        let i32_at =
+            |offset: usize| i32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+        Self {
+            key: Lazrs_CopcVoxelKey {
+                level: i32_at(0),
+                x: i32_at(4),
+                y: i32_at(8),
+                z: i32_at(12),
+            },
+            offset: u64::from_le_bytes(data[16..24].try_into().unwrap()),
+            byte_size: i32_at(24),
+            point_count: i32_at(28),
+        }
+    }
+
+    fn write_to(&self, data: &mut Vec<u8>) {
+        for value in [self.key.level, self.key.x, self.key.y, self.key.z] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&self.offset.to_le_bytes());
+        data.extend_from_slice(&self.byte_size.to_le_bytes());
+        data.extend_from_slice(&self.point_count.to_le_bytes());
+    }
+}
+
+/// A COPC file opened for reading
+pub struct Lazrs_CopcReader {
+    src: CSource<'static>,
+    /// Position of the start of the file in the source
+    start: u64,
+    vlr: laz::LazVlr,
+    info: Lazrs_CopcInfo,
+    /// The nodes of all the hierarchy pages
+    nodes: Vec<Lazrs_CopcEntry>,
+    node_indices: HashMap<Lazrs_CopcVoxelKey, usize>,
+}
+
+/// Reads the hierarchy page, and the pages it points to
+fn read_hierarchy<R: Read + Seek>(
+    src: &mut R,
+    start: u64,
+    info: &Lazrs_CopcInfo,
+) -> Result<Vec<Lazrs_CopcEntry>, Lazrs_Result> {
+    // The pages are allocated from their size, they must fit in the source
+    let source_end = src.seek(SeekFrom::End(0))?;
+    let mut nodes = Vec::new();
+    let mut pages = vec![(info.root_hier_offset, info.root_hier_size)];
+    let mut visited = HashSet::new();
+    while let Some((offset, size)) = pages.pop() {
+        let page_end = start
+            .checked_add(offset)
+            .and_then(|page_start| page_start.checked_add(size));
+        // A page pointing to an already read one would make us loop forever
+        if !visited.insert(offset)
+            || size % HIERARCHY_ENTRY_SIZE as u64 != 0
+            || page_end.is_none_or(|end| end > source_end)
+        {
+            return Err(Lazrs_Result::LAZRS_OTHER);
+        }
+        src.seek(SeekFrom::Start(start + offset))?;
+        let mut page = vec![0u8; size as usize];
+        src.read_exact(&mut page)?;
+        for entry in page
+            .chunks_exact(HIERARCHY_ENTRY_SIZE)
+            .map(Lazrs_CopcEntry::read_from)
+        {
+            match entry.point_count {
+                -1 => pages.push((entry.offset, entry.byte_size.max(0) as u64)),
+                count if count >= 0 => nodes.push(entry),
+                _ => return Err(Lazrs_Result::LAZRS_OTHER),
+            }
+        }
+    }
+    Ok(nodes)
+}
+
+fn open_reader(mut src: CSource<'static>) -> Result<Lazrs_CopcReader, Lazrs_Result> {
+    let start = src.stream_position()?;
+    let (metadata, vlr) = read_laz_metadata(&mut src)?;
+    let info = metadata
+        .vlrs
+        .iter()
+        .find(|vlr| vlr.is(COPC_USER_ID, COPC_INFO_RECORD_ID))
+        .and_then(|vlr| Lazrs_CopcInfo::from_vlr_data(&vlr.data))
+        .ok_or(Lazrs_Result::LAZRS_OTHER)?;
+    let nodes = read_hierarchy(&mut src, start, &info)?;
+    let node_indices = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| (node.key, i))
+        .collect();
+    Ok(Lazrs_CopcReader {
+        src,
+        start,
+        vlr,
+        info,
+        nodes,
+        node_indices,
+    })
+}
+
+/// Opens a COPC file
+///
+/// The COPC info VLR and all the hierarchy pages are read,
+/// the points are only read by `lazrs_copc_reader_decompress_node`.
+/// The file is read from the current position of the source,
+/// which must support seeking and stay valid until the reader is freed.
+/// LAZRS_OTHER is returned if the file is not compressed, has no COPC info VLR,
+/// or if its hierarchy is not valid.
+///
+/// @source_type: type of the source
+/// @source: where the COPC file is read from
+/// @reader: will receive the reader, or NULL if an error occurred,
+///          to be freed with `lazrs_copc_reader_delete`
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_copc_reader_open(
+    source_type: Lazrs_SourceType,
+    source: Lazrs_Source,
+    reader: *mut *mut Lazrs_CopcReader,
+) -> Lazrs_Result {
+    debug_assert!(!reader.is_null());
+    *reader = std::ptr::null_mut();
+    let src = match CSource::from_c_source(source_type, source) {
+        Ok(src) => src,
+        Err(result) => return result,
+    };
+    match open_reader(src) {
+        Ok(copc) => {
+            *reader = Box::into_raw(Box::new(copc));
+            Lazrs_Result::LAZRS_OK
+        }
+        Err(result) => result,
+    }
+}
+
+/// Frees the reader
+///
+/// @reader can be NULL (no-op)
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_copc_reader_delete(reader: *mut Lazrs_CopcReader) {
+    if !reader.is_null() {
+        let _ = Box::from_raw(reader);
+    }
+}
+
+/// Gets the content of the COPC info VLR
+///
+/// @reader: must not be NULL
+/// @info: will receive the info
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_copc_reader_info(
+    reader: *const Lazrs_CopcReader,
+    info: *mut Lazrs_CopcInfo,
+) {
+    debug_assert!(!reader.is_null());
+    debug_assert!(!info.is_null());
+    *info = (*reader).info;
+}
+
+/// Returns the size in bytes of the points
+///
+/// @reader: must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_copc_reader_point_size(reader: *const Lazrs_CopcReader) -> u16 {
+    debug_assert!(!reader.is_null());
+    (*reader).vlr.items_size() as u16
+}
+
+/// Returns the number of nodes of the octree
+///
+/// @reader: must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_copc_reader_num_nodes(reader: *const Lazrs_CopcReader) -> usize {
+    debug_assert!(!reader.is_null());
+    (*reader).nodes.len()
+}
+
+/// Gets a node of the octree
+///
+/// The nodes are in no particular order.
+///
+/// @reader: must not be NULL
+/// @index: index of the node, LAZRS_OTHER is returned if it is out of bounds
+/// @entry: will receive the node
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_copc_reader_node(
+    reader: *const Lazrs_CopcReader,
+    index: usize,
+    entry: *mut Lazrs_CopcEntry,
+) -> Lazrs_Result {
+    debug_assert!(!reader.is_null());
+    debug_assert!(!entry.is_null());
+    let reader = &*reader;
+    match reader.nodes.get(index) {
+        Some(node) => {
+            *entry = *node;
+            Lazrs_Result::LAZRS_OK
+        }
+        None => Lazrs_Result::LAZRS_OTHER,
+    }
+}
+
+/// Finds the index of a node from its key
+///
+/// @reader: must not be NULL
+/// @key: the key of the node, LAZRS_OTHER is returned if no node has it
+/// @index: will receive the index of the node
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_copc_reader_find_node(
+    reader: *const Lazrs_CopcReader,
+    key: Lazrs_CopcVoxelKey,
+    index: *mut usize,
+) -> Lazrs_Result {
+    debug_assert!(!reader.is_null());
+    debug_assert!(!index.is_null());
+    let reader = &*reader;
+    match reader.node_indices.get(&key) {
+        Some(i) => {
+            *index = *i;
+            Lazrs_Result::LAZRS_OK
+        }
+        None => Lazrs_Result::LAZRS_OTHER,
+    }
+}
+
+/// Gets the cube covered by a node, in the coordinates of the file
+/// (scales and offsets applied)
+///
+/// @reader: must not be NULL
+/// @key: the key of the node
+/// @bounds: will receive the cube
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_copc_reader_node_bounds(
+    reader: *const Lazrs_CopcReader,
+    key: Lazrs_CopcVoxelKey,
+    bounds: *mut Lazrs_Bounds,
+) {
+    debug_assert!(!reader.is_null());
+    debug_assert!(!bounds.is_null());
+    *bounds = (*reader).info.bounds(&key);
+}
+
+/// Decompresses the points of a node
+///
+/// The chunk of the node is read from the source, then decompressed.
+///
+/// @reader: must not be NULL
+/// @index: index of the node, LAZRS_OTHER is returned if it is out of bounds
+/// @out: out buffer that will receive the LAS data of the points of the node
+/// @len: size of the output buffer, LAZRS_OTHER is returned if it is smaller
+///       than the point count of the node times the size of the points
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_copc_reader_decompress_node(
+    reader: *mut Lazrs_CopcReader,
+    index: usize,
+    out: *mut u8,
+    len: libc::size_t,
+) -> Lazrs_Result {
+    debug_assert!(!reader.is_null());
+    debug_assert!(!out.is_null());
+    let reader = &mut *reader;
+    let node = match reader.nodes.get(index) {
+        Some(node) => *node,
+        None => return Lazrs_Result::LAZRS_OTHER,
+    };
+    let point_size = reader.vlr.items_size() as usize;
+    let point_count = node.point_count.max(0) as usize;
+    if len < point_count * point_size || node.byte_size < 0 {
+        return Lazrs_Result::LAZRS_OTHER;
+    }
+    if point_count == 0 {
+        return Lazrs_Result::LAZRS_OK;
+    }
+
+    let mut data = vec![0u8; node.byte_size as usize];
+    let read = reader
+        .src
+        .seek(SeekFrom::Start(reader.start + node.offset))
+        .and_then(|_| reader.src.read_exact(&mut data));
+    if let Err(error) = read {
+        return error.into();
+    }
+    let buf = std::slice::from_raw_parts_mut(out, point_count * point_size);
+    let mut points = buf.chunks_exact_mut(point_size);
+    let result = decompress_chunk(&reader.vlr, &data, point_count as u64, |point| {
+        if let Some(dest) = points.next() {
+            dest.copy_from_slice(point);
+        }
+    });
+    match result {
+        Ok(_) => Lazrs_Result::LAZRS_OK,
+        Err(error) => error.into(),
+    }
+}
+
+/// A node of the octree being built and the indices of its points
+struct Node {
+    key: Lazrs_CopcVoxelKey,
+    points: Vec<u32>,
+}
+
+/// Distributes the points in the nodes of the octree
+///
+/// Each node keeps the first point of each of its voxels, whose size is
+/// the spacing of the level, the other points go to the child nodes.
+struct OctreeBuilder<'a> {
+    info: &'a Lazrs_CopcInfo,
+    coordinates: &'a [[f64; 3]],
+    nodes: Vec<Node>,
+}
+
+impl OctreeBuilder<'_> {
+    fn add_node(&mut self, key: Lazrs_CopcVoxelKey, points: Vec<u32>) {
+        if key.level >= MAX_LEVEL {
+            self.nodes.push(Node { key, points });
+            return;
+        }
+        let bounds = self.info.bounds(&key);
+        let min = [bounds.min_x, bounds.min_y, bounds.min_z];
+        let voxel_size = self.info.spacing / f64::from(key.level).exp2();
+        let child_size = self.info.node_size(key.level + 1);
+        let root_min = [
+            self.info.center_x - self.info.halfsize,
+            self.info.center_y - self.info.halfsize,
+            self.info.center_z - self.info.halfsize,
+        ];
+        let position = [key.x, key.y, key.z];
+
+        let mut occupied = HashSet::new();
+        let mut kept = Vec::new();
+        let mut children = vec![Vec::new(); 8];
+        for i in points {
+            let c = &self.coordinates[i as usize];
+            let voxel = [0, 1, 2].map(|d| ((c[d] - min[d]) / voxel_size).floor() as i64);
+            if occupied.insert(voxel) {
+                kept.push(i);
+                continue;
+            }
+            // Computed like the bounds of the children, so that they contain their points
+            let octant = (0..3).fold(0, |octant, d| {
+                let child = ((c[d] - root_min[d]) / child_size).floor() as i64;
+                let first = 2 * i64::from(position[d]);
+                let half = (child.clamp(first, first + 1) - first) as usize;
+                octant | (half << d)
+            });
+            children[octant].push(i);
+        }
+        self.nodes.push(Node { key, points: kept });
+        for (octant, child) in children.into_iter().enumerate() {
+            if !child.is_empty() {
+                let half = |d: usize| ((octant >> d) & 1) as i32;
+                let child_key = Lazrs_CopcVoxelKey {
+                    level: key.level + 1,
+                    x: 2 * key.x + half(0),
+                    y: 2 * key.y + half(1),
+                    z: 2 * key.z + half(2),
+                };
+                self.add_node(child_key, child);
+            }
+        }
+    }
+}
+
+/// Compresses the points of a node as one chunk, empty nodes have no chunk
+fn compress_node(
+    vlr: &laz::LazVlr,
+    points: &[u8],
+    point_size: usize,
+    node: &Node,
+) -> laz::Result<Vec<u8>> {
+    if node.points.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut records = Vec::with_capacity(node.points.len() * point_size);
+    for &i in &node.points {
+        let start = i as usize * point_size;
+        records.extend_from_slice(&points[start..start + point_size]);
+    }
+    compress_chunk(vlr, &records)
+}
+
+/// Compresses a batch of nodes
+fn compress_nodes(
+    vlr: &laz::LazVlr,
+    points: &[u8],
+    point_size: usize,
+    nodes: &[Node],
+    parallel: bool,
+) -> laz::Result<Vec<Vec<u8>>> {
+    #[cfg(feature = "parallel")]
+    if parallel {
+        use rayon::prelude::*;
+        return nodes
+            .par_iter()
+            .map(|node| compress_node(vlr, points, point_size, node))
+            .collect();
+    }
+    let _ = parallel;
+    nodes
+        .iter()
+        .map(|node| compress_node(vlr, points, point_size, node))
+        .collect()
+}
+
+fn las_to_copc(
+    mut src: CSource<'static>,
+    mut dest: CDest,
+    options: &Lazrs_LasToCopcOptions,
+) -> Result<(), Lazrs_Result> {
+    let mut metadata = LasMetadata::read_from(&mut src)?;
+    let src_vlr = metadata.laszip_vlr().transpose()?;
+    // The points of a file in compatibility mode are read back in their LAS 1.4 format
+    let compatibility = match src_vlr {
+        Some(_) => Compatibility::undo(&mut metadata),
+        None => None,
+    };
+    let point_format_id = metadata.header.point_format_id();
+    let format = PointFormat::new(point_format_id)
+        .filter(|_| (6..=8).contains(&point_format_id))
+        .ok_or(Lazrs_Result::LAZRS_UNSUPPORTED_POINT_FORMAT)?;
+    let num_extra_bytes = metadata
+        .header
+        .num_extra_bytes()
+        .ok_or(Lazrs_Result::LAZRS_UNSUPPORTED_POINT_FORMAT)?;
+    let num_points = metadata.header.number_of_points();
+    if num_points > u64::from(u32::MAX) || metadata.header.header_size() != COPC_HEADER_SIZE {
+        return Err(Lazrs_Result::LAZRS_OTHER);
+    }
+    let vlr = laz::LazVlrBuilder::default()
+        .with_point_format(point_format_id, num_extra_bytes)?
+        .with_variable_chunk_size()
+        .build();
+    let point_size = vlr.items_size() as usize;
+
+    // All the points are needed to build the octree
+    let mut points = vec![0u8; num_points as usize * point_size];
+    let mut reader = match src_vlr {
+        Some(src_vlr) => PointReader::Laz(Box::new(Decompressor::new(
+            src,
+            src_vlr,
+            options.prefer_parallel,
+        )?)),
+        None => PointReader::Las(src),
+    };
+    match &compatibility {
+        Some(compatibility) => {
+            let mut legacy_points =
+                vec![0u8; num_points as usize * compatibility.legacy_point_size];
+            reader.read(&mut legacy_points)?;
+            compatibility.to_native(&legacy_points, &mut points);
+        }
+        None => reader.read(&mut points)?,
+    }
+
+    let scales = metadata.header.scales();
+    let offsets = metadata.header.offsets();
+    let mut min = [f64::INFINITY; 3];
+    let mut max = [f64::NEG_INFINITY; 3];
+    let mut gps_time = (f64::INFINITY, f64::NEG_INFINITY);
+    let coordinates: Vec<[f64; 3]> = points
+        .chunks_exact(point_size)
+        .map(|record| {
+            let point = Lazrs_Point::read_from(&format, record);
+            let stored = [point.x, point.y, point.z];
+            let c = [0, 1, 2].map(|i| f64::from(stored[i]) * scales[i] + offsets[i]);
+            for i in 0..3 {
+                min[i] = min[i].min(c[i]);
+                max[i] = max[i].max(c[i]);
+            }
+            gps_time = (
+                gps_time.0.min(point.gps_time),
+                gps_time.1.max(point.gps_time),
+            );
+            c
+        })
+        .collect();
+    if coordinates.is_empty() {
+        min = [0.0; 3];
+        max = [0.0; 3];
+        gps_time = (0.0, 0.0);
+    }
+
+    let halfsize = (0..3).map(|i| (max[i] - min[i]) / 2.0).fold(0.0, f64::max);
+    let halfsize = if halfsize > 0.0 { halfsize } else { 1.0 };
+    let mut info = Lazrs_CopcInfo {
+        center_x: (min[0] + max[0]) / 2.0,
+        center_y: (min[1] + max[1]) / 2.0,
+        center_z: (min[2] + max[2]) / 2.0,
+        halfsize,
+        spacing: if options.spacing > 0.0 {
+            options.spacing
+        } else {
+            2.0 * halfsize / DEFAULT_ROOT_VOXELS
+        },
+        root_hier_offset: 0,
+        root_hier_size: 0,
+        gpstime_minimum: gps_time.0,
+        gpstime_maximum: gps_time.1,
+    };
+    let mut builder = OctreeBuilder {
+        info: &info,
+        coordinates: &coordinates,
+        nodes: Vec::new(),
+    };
+    let root = Lazrs_CopcVoxelKey {
+        level: 0,
+        x: 0,
+        y: 0,
+        z: 0,
+    };
+    builder.add_node(root, (0..num_points as u32).collect());
+    let nodes = builder.nodes;
+
+    metadata.header.set_point_format(point_format_id, true);
+    metadata.header.set_bounds(min, max);
+    metadata
+        .vlrs
+        .retain(|vlr| !vlr.is(COPC_USER_ID, COPC_INFO_RECORD_ID));
+    metadata.vlrs.insert(
+        0,
+        Vlr::new(
+            COPC_USER_ID,
+            COPC_INFO_RECORD_ID,
+            "COPC info",
+            info.to_vlr_data(),
+        ),
+    );
+    metadata.set_laszip_vlr(Some(&vlr))?;
+    metadata.padding.clear();
+    metadata
+        .evlrs
+        .retain(|evlr| !evlr.is(COPC_USER_ID, COPC_HIERARCHY_RECORD_ID));
+
+    let start = dest.stream_position()?;
+    metadata.write_head_to(&mut dest)?;
+
+    let table_offset_position = dest.stream_position()?;
+    dest.write_all(&(-1i64).to_le_bytes())?;
+    let mut entries = Vec::with_capacity(nodes.len());
+    let mut chunk_table = ChunkTable::with_capacity(nodes.len());
+    for batch in nodes.chunks(batch_size(options.prefer_parallel)) {
+        let chunks = compress_nodes(&vlr, &points, point_size, batch, options.prefer_parallel)?;
+        for (node, chunk) in batch.iter().zip(chunks) {
+            if node.points.is_empty() {
+                // Only the root of a file without points
+                entries.push(Lazrs_CopcEntry {
+                    key: node.key,
+                    offset: 0,
+                    byte_size: 0,
+                    point_count: 0,
+                });
+                continue;
+            }
+            let offset = dest.stream_position()? - start;
+            dest.write_all(&chunk)?;
+            entries.push(Lazrs_CopcEntry {
+                key: node.key,
+                offset,
+                byte_size: chunk
+                    .len()
+                    .try_into()
+                    .map_err(|_| Lazrs_Result::LAZRS_OTHER)?,
+                point_count: node.points.len() as i32,
+            });
+            chunk_table.push(ChunkTableEntry {
+                point_count: node.points.len() as u64,
+                byte_count: chunk.len() as u64,
+            });
+        }
+    }
+    let table_position = dest.stream_position()?;
+    chunk_table.write_to(&mut dest, &vlr)?;
+    let end = dest.stream_position()?;
+    dest.seek(SeekFrom::Start(table_offset_position))?;
+    dest.write_all(&(table_position as i64).to_le_bytes())?;
+
+    // The hierarchy is the first EVLR, in a single page
+    let mut page = Vec::with_capacity(entries.len() * HIERARCHY_ENTRY_SIZE);
+    for entry in &entries {
+        entry.write_to(&mut page);
+    }
+    info.root_hier_offset = end - start + EVLR_HEADER_SIZE;
+    info.root_hier_size = page.len() as u64;
+    dest.seek(SeekFrom::Start(
+        start + u64::from(COPC_HEADER_SIZE) + VLR_HEADER_SIZE,
+    ))?;
+    dest.write_all(&info.to_vlr_data())?;
+    dest.seek(SeekFrom::Start(end))?;
+
+    let mut hierarchy = Vlr::new(
+        COPC_USER_ID,
+        COPC_HIERARCHY_RECORD_ID,
+        "EPT hierarchy",
+        page,
+    );
+    hierarchy.extended = true;
+    metadata.evlrs.insert(0, hierarchy);
+    metadata.write_evlrs_to(&mut dest, start)?;
+    Ok(())
+}
+
+/// Converts a LAS or LAZ file to a COPC file
+///
+/// The points are organized in an octree: each node keeps one point per voxel,
+/// the voxels of the root node having the size of the spacing, halved at each level,
+/// and the other points go to the child nodes.
+/// Each node is compressed as one chunk, they are described by the hierarchy EVLR,
+/// and the COPC info VLR is added before the VLRs of the file.
+/// All the points are loaded in memory.
+///
+/// The file is read from the current position of the source,
+/// and written at the current position of the destination.
+/// LAZRS_UNSUPPORTED_POINT_FORMAT is returned if the point format is not 6, 7 or 8.
+/// The points of a LAZ file in LASzip's compatibility mode are read
+/// in their LAS 1.4 format.
+///
+/// @source_type: type of the source
+/// @source: where the LAS or LAZ file is read from
+/// @dest_type: type of the destination
+/// @dest: where the COPC file is written
+/// @options: how to build the octree and compress the points
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_las_to_copc(
+    source_type: Lazrs_SourceType,
+    source: Lazrs_Source,
+    dest_type: Lazrs_DestType,
+    dest: Lazrs_Dest,
+    options: Lazrs_LasToCopcOptions,
+) -> Lazrs_Result {
+    let (csource, cdest) = match open(source_type, source, dest_type, dest) {
+        Ok(v) => v,
+        Err(result) => return result,
+    };
+    match las_to_copc(csource, cdest, &options) {
+        Ok(()) => Lazrs_Result::LAZRS_OK,
+        Err(result) => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn info() -> Lazrs_CopcInfo {
+        Lazrs_CopcInfo {
+            center_x: 10.0,
+            center_y: -20.0,
+            center_z: 5.0,
+            halfsize: 8.0,
+            spacing: 0.5,
+            root_hier_offset: 1234,
+            root_hier_size: 64,
+            gpstime_minimum: 1.5,
+            gpstime_maximum: 99.25,
+        }
+    }
+
+    fn entry(level: i32, x: i32, offset: u64, byte_size: i32, point_count: i32) -> Lazrs_CopcEntry {
+        Lazrs_CopcEntry {
+            key: Lazrs_CopcVoxelKey {
+                level,
+                x,
+                y: 0,
+                z: 0,
+            },
+            offset,
+            byte_size,
+            point_count,
+        }
+    }
+
+    fn page(entries: &[Lazrs_CopcEntry]) -> Vec<u8> {
+        let mut page = Vec::new();
+        for entry in entries {
+            entry.write_to(&mut page);
+        }
+        page
+    }
+
+    #[test]
+    fn info_vlr_data_is_read() {
+        let info = info();
+        let mut data = Vec::new();
+        for value in [
+            info.center_x,
+            info.center_y,
+            info.center_z,
+            info.halfsize,
+            info.spacing,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&info.root_hier_offset.to_le_bytes());
+        data.extend_from_slice(&info.root_hier_size.to_le_bytes());
+        data.extend_from_slice(&info.gpstime_minimum.to_le_bytes());
+        data.extend_from_slice(&info.gpstime_maximum.to_le_bytes());
+        // Reserved
+        data.resize(COPC_INFO_SIZE, 0);
+
+        let read = Lazrs_CopcInfo::from_vlr_data(&data).unwrap();
+        assert_eq!(
+            [read.center_x, read.center_y, read.center_z, read.halfsize],
+            [10.0, -20.0, 5.0, 8.0]
+        );
+        assert_eq!(read.spacing, 0.5);
+        assert_eq!((read.root_hier_offset, read.root_hier_size), (1234, 64));
+        assert_eq!((read.gpstime_minimum, read.gpstime_maximum), (1.5, 99.25));
+
+        assert!(Lazrs_CopcInfo::from_vlr_data(&data[..COPC_INFO_SIZE - 1]).is_none());
+    }
+
+    #[test]
+    fn info_vlr_data_is_read_back() {
+        let data = info().to_vlr_data();
+        assert_eq!(data.len(), COPC_INFO_SIZE);
+        let read = Lazrs_CopcInfo::from_vlr_data(&data).unwrap();
+        assert_eq!(read.to_vlr_data(), data);
+        // The reserved bytes are zeros
+        assert!(data[72..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn nodes_are_cubes_of_the_root() {
+        let info = info();
+        let bounds = info.bounds(&Lazrs_CopcVoxelKey {
+            level: 0,
+            x: 0,
+            y: 0,
+            z: 0,
+        });
+        assert_eq!(
+            [bounds.min_x, bounds.min_y, bounds.min_z],
+            [2.0, -28.0, -3.0]
+        );
+        assert_eq!(
+            [bounds.max_x, bounds.max_y, bounds.max_z],
+            [18.0, -12.0, 13.0]
+        );
+
+        let bounds = info.bounds(&Lazrs_CopcVoxelKey {
+            level: 2,
+            x: 3,
+            y: 0,
+            z: 1,
+        });
+        assert_eq!(
+            [bounds.min_x, bounds.min_y, bounds.min_z],
+            [14.0, -28.0, 1.0]
+        );
+        assert_eq!(
+            [bounds.max_x, bounds.max_y, bounds.max_z],
+            [18.0, -24.0, 5.0]
+        );
+    }
+
+    #[test]
+    fn hierarchy_entries_are_read() {
+        let entry = Lazrs_CopcEntry {
+            key: Lazrs_CopcVoxelKey {
+                level: 3,
+                x: -1,
+                y: 7,
+                z: 2,
+            },
+            offset: u64::MAX - 1,
+            byte_size: 4096,
+            point_count: -1,
+        };
+        let data = page(&[entry]);
+        assert_eq!(data.len(), HIERARCHY_ENTRY_SIZE);
+        let read = Lazrs_CopcEntry::read_from(&data);
+        assert_eq!(read.key, entry.key);
+        assert_eq!(read.offset, entry.offset);
+        assert_eq!(read.byte_size, entry.byte_size);
+        assert_eq!(read.point_count, entry.point_count);
+    }
+
+    #[test]
+    fn hierarchy_pages_are_followed() {
+        let start = 10u64;
+        let child_page = page(&[entry(2, 0, 500, 10, 4), entry(2, 1, 600, 20, 0)]);
+        let child_offset = 200;
+        let root_page = page(&[
+            entry(0, 0, 100, 30, 12),
+            entry(1, 0, child_offset, child_page.len() as i32, -1),
+        ]);
+        let root_offset = 50;
+        let mut file = vec![0u8; start as usize + 300];
+        let at = |offset: u64| (start + offset) as usize;
+        file[at(root_offset)..at(root_offset) + root_page.len()].copy_from_slice(&root_page);
+        file[at(child_offset)..at(child_offset) + child_page.len()].copy_from_slice(&child_page);
+
+        let mut info = info();
+        info.root_hier_offset = root_offset;
+        info.root_hier_size = root_page.len() as u64;
+        let nodes = read_hierarchy(&mut Cursor::new(&file), start, &info).unwrap();
+        let keys: Vec<(i32, i32, i32)> = nodes
+            .iter()
+            .map(|node| (node.key.level, node.key.x, node.point_count))
+            .collect();
+        assert_eq!(keys, vec![(0, 0, 12), (2, 0, 4), (2, 1, 0)]);
+
+        // A page pointing to itself
+        let looping_page = page(&[entry(1, 0, root_offset, 32, -1)]);
+        file[at(root_offset)..at(root_offset) + 32].copy_from_slice(&looping_page);
+        info.root_hier_size = 32;
+        assert!(read_hierarchy(&mut Cursor::new(&file), start, &info).is_err());
+
+        let invalid_count = page(&[entry(0, 0, 100, 30, -2)]);
+        file[at(root_offset)..at(root_offset) + 32].copy_from_slice(&invalid_count);
+        assert!(read_hierarchy(&mut Cursor::new(&file), start, &info).is_err());
+
+        info.root_hier_size = 31;
+        assert!(read_hierarchy(&mut Cursor::new(&file), start, &info).is_err());
+
+        // Pages that do not fit in the file
+        info.root_hier_size = 32 << 40;
+        assert!(read_hierarchy(&mut Cursor::new(&file), start, &info).is_err());
+        info.root_hier_offset = u64::MAX - 16;
+        info.root_hier_size = 32;
+        assert!(read_hierarchy(&mut Cursor::new(&file), start, &info).is_err());
+        let too_large_child = page(&[entry(1, 0, child_offset, 32 * 1000, -1)]);
+        file[at(root_offset)..at(root_offset) + 32].copy_from_slice(&too_large_child);
+        info.root_hier_offset = root_offset;
+        assert!(read_hierarchy(&mut Cursor::new(&file), start, &info).is_err());
+        let child_page = page(&[entry(2, 0, 500, 10, 4)]);
+        file[at(child_offset)..at(child_offset) + 32].copy_from_slice(&child_page);
+        let fitting_child = page(&[entry(1, 0, child_offset, 32, -1)]);
+        file[at(root_offset)..at(root_offset) + 32].copy_from_slice(&fitting_child);
+        assert_eq!(
+            read_hierarchy(&mut Cursor::new(&file), start, &info)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    fn octree(info: &Lazrs_CopcInfo, coordinates: &[[f64; 3]]) -> Vec<Node> {
+        let mut builder = OctreeBuilder {
+            info,
+            coordinates,
+            nodes: Vec::new(),
+        };
+        let root = Lazrs_CopcVoxelKey {
+            level: 0,
+            x: 0,
+            y: 0,
+            z: 0,
+        };
+        builder.add_node(root, (0..coordinates.len() as u32).collect());
+        builder.nodes
+    }
+
+    #[test]
+    fn nodes_keep_one_point_per_voxel() {
+        let info = Lazrs_CopcInfo {
+            halfsize: 8.0,
+            spacing: 16.0,
+            ..Default::default()
+        };
+        let coordinates = [
+            [-4.0, -4.0, -4.0],
+            [-5.0, -5.0, -5.0],
+            [4.0, 4.0, 4.0],
+            [5.0, 5.0, 5.0],
+            [-4.0, -4.0, -4.0],
+        ];
+        let nodes: Vec<(i32, [i32; 3], Vec<u32>)> = octree(&info, &coordinates)
+            .into_iter()
+            .map(|node| {
+                let key = node.key;
+                (key.level, [key.x, key.y, key.z], node.points)
+            })
+            .collect();
+        assert_eq!(
+            nodes,
+            vec![
+                (0, [0, 0, 0], vec![0]),
+                (1, [0, 0, 0], vec![1]),
+                (2, [1, 1, 1], vec![4]),
+                (1, [1, 1, 1], vec![2]),
+                (2, [3, 3, 3], vec![3]),
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicated_points_stop_at_the_last_level() {
+        let info = Lazrs_CopcInfo {
+            halfsize: 1.0,
+            spacing: 2.0,
+            ..Default::default()
+        };
+        let coordinates = vec![[0.25, 0.5, 0.75]; MAX_LEVEL as usize + 3];
+        let nodes = octree(&info, &coordinates);
+        assert_eq!(nodes.len(), MAX_LEVEL as usize + 1);
+        let last = nodes.last().unwrap();
+        assert_eq!(last.key.level, MAX_LEVEL);
+        assert_eq!(
+            last.points,
+            vec![MAX_LEVEL as u32, MAX_LEVEL as u32 + 1, MAX_LEVEL as u32 + 2]
+        );
+    }
+
+    #[test]
+    fn points_are_in_one_node_that_contains_them() {
+        let info = Lazrs_CopcInfo {
+            center_x: 100.0,
+            center_y: 200.0,
+            center_z: 10.0,
+            halfsize: 50.0,
+            spacing: 10.0,
+            ..Default::default()
+        };
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut random = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+        let coordinates: Vec<[f64; 3]> = (0..2000)
+            .map(|_| {
+                [
+                    50.0 + 100.0 * random(),
+                    150.0 + 100.0 * random(),
+                    -40.0 + 100.0 * random(),
+                ]
+            })
+            .collect();
+
+        let nodes = octree(&info, &coordinates);
+        let mut seen = vec![false; coordinates.len()];
+        for node in &nodes {
+            let bounds = info.bounds(&node.key);
+            for &i in &node.points {
+                assert!(!seen[i as usize]);
+                seen[i as usize] = true;
+                let [x, y, z] = coordinates[i as usize];
+                assert!(
+                    bounds.contains(x, y, z),
+                    "{:?} not in {:?}",
+                    node.key,
+                    bounds
+                );
+            }
+        }
+        assert!(seen.into_iter().all(|seen| seen));
+        assert!(nodes.iter().all(|node| !node.points.is_empty()));
+    }
+}
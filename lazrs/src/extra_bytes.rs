@@ -0,0 +1,620 @@
+//! The Extra Bytes VLR, which describes the extra bytes at the end of the point records,
+//! and access to the extra dimensions it describes.
+
+use std::convert::TryInto;
+use std::ffi::CStr;
+
+use crate::columns::Lazrs_ValueType;
+use crate::io::CSource;
+use crate::las::LasMetadata;
+use crate::point::PointFormat;
+use crate::{Lazrs_Result, Lazrs_Source, Lazrs_SourceType};
+
+/// User id of the Extra Bytes VLR (or EVLR)
+pub(crate) const EXTRA_BYTES_USER_ID: &str = "LASF_Spec";
+/// Record id of the Extra Bytes VLR (or EVLR)
+pub(crate) const EXTRA_BYTES_RECORD_ID: u16 = 4;
+pub(crate) const DESCRIPTOR_SIZE: usize = 192;
+
+/// Bits of the options of a descriptor
+const NO_DATA_BIT: u8 = 1;
+const MIN_BIT: u8 = 1 << 1;
+const MAX_BIT: u8 = 1 << 2;
+pub(crate) const SCALE_BIT: u8 = 1 << 3;
+const OFFSET_BIT: u8 = 1 << 4;
+
+/// The types of the data types 1 to 10, in that order
+const VALUE_TYPES: [Lazrs_ValueType; 10] = [
+    Lazrs_ValueType::LAZRS_VALUE_U8,
+    Lazrs_ValueType::LAZRS_VALUE_I8,
+    Lazrs_ValueType::LAZRS_VALUE_U16,
+    Lazrs_ValueType::LAZRS_VALUE_I16,
+    Lazrs_ValueType::LAZRS_VALUE_U32,
+    Lazrs_ValueType::LAZRS_VALUE_I32,
+    Lazrs_ValueType::LAZRS_VALUE_U64,
+    Lazrs_ValueType::LAZRS_VALUE_I64,
+    Lazrs_ValueType::LAZRS_VALUE_F32,
+    Lazrs_ValueType::LAZRS_VALUE_F64,
+];
+
+/// Describes an extra dimension of the points
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Lazrs_ExtraBytesDescriptor {
+    /// NUL-terminated
+    name: [libc::c_char; 33],
+    /// NUL-terminated
+    description: [libc::c_char; 33],
+    /// Type of the elements, meaningless for undocumented bytes
+    value_type: Lazrs_ValueType,
+    /// Number of elements: 1, 2 or 3 for the deprecated array types,
+    /// 0 for undocumented bytes
+    num_elements: u8,
+    /// Position of the dimension in the point records
+    record_offset: usize,
+    /// Number of bytes of the dimension
+    pub(crate) size: usize,
+    has_no_data: bool,
+    has_min: bool,
+    has_max: bool,
+    /// The values as stored, before applying the scale and offset
+    no_data: [f64; 3],
+    min: [f64; 3],
+    max: [f64; 3],
+    /// 1 when the dimension has no scale
+    scale: [f64; 3],
+    /// 0 when the dimension has no offset
+    offset: [f64; 3],
+}
+
+/// Converts the text to a NUL-terminated string, cut at the first NUL
+fn c_string(text: &[u8]) -> [libc::c_char; 33] {
+    let mut string = [0; 33];
+    for (c, &byte) in string.iter_mut().zip(text.iter().take_while(|&&b| b != 0)) {
+        *c = byte as libc::c_char;
+    }
+    string
+}
+
+impl Lazrs_ExtraBytesDescriptor {
+    /// Reads a descriptor, `None` if its data type is not known
+    pub(crate) fn read_from(data: &[u8], record_offset: usize) -> Option<Self> {
+        let data_type = data[2];
+        let options = data[3];
+        let (value_type, num_elements, size) = match data_type {
+            0 => (Lazrs_ValueType::LAZRS_VALUE_U8, 0, usize::from(options)),
+            1..=30 => {
+                let value_type = VALUE_TYPES[usize::from((data_type - 1) % 10)];
+                let num_elements = (data_type - 1) / 10 + 1;
+                (
+                    value_type,
+                    num_elements,
+                    value_type.size() * usize::from(num_elements),
+                )
+            }
+            _ => return None,
+        };
+        // The options of undocumented bytes are their size
+        let has = |bit: u8| num_elements > 0 && options & bit != 0;
+
+        // Values of the type of the dimension, in 8 bytes
+        let any_values = |start: usize| {
+            let mut values = [0.0; 3];
+            for (i, value) in values.iter_mut().enumerate() {
+                let bytes: [u8; 8] = data[start + 8 * i..start + 8 * i + 8].try_into().unwrap();
+                *value = match value_type {
+                    Lazrs_ValueType::LAZRS_VALUE_F32 | Lazrs_ValueType::LAZRS_VALUE_F64 => {
+                        f64::from_le_bytes(bytes)
+                    }
+                    Lazrs_ValueType::LAZRS_VALUE_I8
+                    | Lazrs_ValueType::LAZRS_VALUE_I16
+                    | Lazrs_ValueType::LAZRS_VALUE_I32
+                    | Lazrs_ValueType::LAZRS_VALUE_I64 => i64::from_le_bytes(bytes) as f64,
+                    _ => u64::from_le_bytes(bytes) as f64,
+                };
+            }
+            values
+        };
+        let f64_values = |start: usize, default: f64| {
+            let mut values = [default; 3];
+            for (i, value) in values.iter_mut().enumerate() {
+                *value =
+                    f64::from_le_bytes(data[start + 8 * i..start + 8 * i + 8].try_into().unwrap());
+            }
+            values
+        };
+        Some(Self {
+            name: c_string(&data[4..36]),
+            description: c_string(&data[160..192]),
+            value_type,
+            num_elements,
+            record_offset,
+            size,
+            has_no_data: has(NO_DATA_BIT),
+            has_min: has(MIN_BIT),
+            has_max: has(MAX_BIT),
+            no_data: any_values(40),
+            min: any_values(64),
+            max: any_values(88),
+            scale: if has(SCALE_BIT) {
+                f64_values(112, 1.0)
+            } else {
+                [1.0; 3]
+            },
+            offset: if has(OFFSET_BIT) {
+                f64_values(136, 0.0)
+            } else {
+                [0.0; 3]
+            },
+        })
+    }
+
+    pub(crate) fn has_name(&self, name: &[u8]) -> bool {
+        self.name
+            .iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| c as u8)
+            .eq(name.iter().copied())
+    }
+
+    /// Position in the record of an element, `None` if the dimension has no such element
+    fn element_offset(&self, element: u8) -> Option<usize> {
+        (element < self.num_elements)
+            .then(|| self.record_offset + usize::from(element) * self.value_type.size())
+    }
+}
+
+/// The extra dimensions described by the Extra Bytes VLR of a file
+pub struct Lazrs_ExtraBytes {
+    descriptors: Vec<Lazrs_ExtraBytesDescriptor>,
+    /// Size of the point records the dimensions are in
+    point_size: usize,
+}
+
+impl Lazrs_ExtraBytes {
+    /// Reads the descriptors of the VLR data, `None` if it is not valid
+    /// or if the dimensions do not fit in records of `point_size` bytes
+    fn from_vlr_data(format: &PointFormat, point_size: usize, data: &[u8]) -> Option<Self> {
+        let descriptors_data = data.chunks_exact(DESCRIPTOR_SIZE);
+        if !descriptors_data.remainder().is_empty() || point_size < format.size {
+            return None;
+        }
+        let mut record_offset = format.size;
+        let mut descriptors = Vec::with_capacity(descriptors_data.len());
+        for descriptor_data in descriptors_data {
+            let descriptor = Lazrs_ExtraBytesDescriptor::read_from(descriptor_data, record_offset)?;
+            record_offset += descriptor.size;
+            if record_offset > point_size {
+                return None;
+            }
+            descriptors.push(descriptor);
+        }
+        Some(Self {
+            descriptors,
+            point_size,
+        })
+    }
+}
+
+fn read_extra_bytes(mut src: CSource<'static>) -> Result<Lazrs_ExtraBytes, Lazrs_Result> {
+    let metadata = LasMetadata::read_from(&mut src)?;
+    let format = PointFormat::new(metadata.header.point_format_id())
+        .ok_or(Lazrs_Result::LAZRS_UNSUPPORTED_POINT_FORMAT)?;
+    let point_size = usize::from(metadata.header.point_size());
+    match metadata
+        .vlrs
+        .iter()
+        .chain(metadata.evlrs.iter())
+        .find(|vlr| vlr.is(EXTRA_BYTES_USER_ID, EXTRA_BYTES_RECORD_ID))
+    {
+        Some(vlr) => Lazrs_ExtraBytes::from_vlr_data(&format, point_size, &vlr.data)
+            .ok_or(Lazrs_Result::LAZRS_OTHER),
+        None => Ok(Lazrs_ExtraBytes {
+            descriptors: Vec::new(),
+            point_size,
+        }),
+    }
+}
+
+/// Parses the data of an Extra Bytes VLR
+///
+/// LAZRS_OTHER is returned if the data is not valid,
+/// or if the dimensions do not fit in the point records.
+///
+/// @point_format_id: the point format of the records, the extra bytes follow its fields
+/// @point_size: size of the point records (the point data record length of the header)
+/// @data: the VLR data
+/// @size: size of the data
+/// @extra_bytes: will receive the extra dimensions, or NULL if an error occurred,
+///               to be freed with `lazrs_extra_bytes_delete`
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_extra_bytes_from_vlr_data(
+    point_format_id: u8,
+    point_size: u16,
+    data: *const u8,
+    size: usize,
+    extra_bytes: *mut *mut Lazrs_ExtraBytes,
+) -> Lazrs_Result {
+    debug_assert!(!data.is_null());
+    debug_assert!(!extra_bytes.is_null());
+    *extra_bytes = std::ptr::null_mut();
+    let format = match PointFormat::new(point_format_id) {
+        Some(format) => format,
+        None => return Lazrs_Result::LAZRS_UNSUPPORTED_POINT_FORMAT,
+    };
+    let data = std::slice::from_raw_parts(data, size);
+    match Lazrs_ExtraBytes::from_vlr_data(&format, usize::from(point_size), data) {
+        Some(parsed) => {
+            *extra_bytes = Box::into_raw(Box::new(parsed));
+            Lazrs_Result::LAZRS_OK
+        }
+        None => Lazrs_Result::LAZRS_OTHER,
+    }
+}
+
+/// Reads the extra dimensions described by the Extra Bytes VLR (or EVLR) of a LAS or LAZ file
+///
+/// A file without Extra Bytes VLR has no extra dimensions.
+/// The file is read from the current position of the source.
+/// LAZRS_OTHER is returned if the VLR is not valid,
+/// or if the dimensions do not fit in the point records of the file.
+///
+/// @source_type: type of the source
+/// @source: where the file is read from
+/// @extra_bytes: will receive the extra dimensions, or NULL if an error occurred,
+///               to be freed with `lazrs_extra_bytes_delete`
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_extra_bytes_read(
+    source_type: Lazrs_SourceType,
+    source: Lazrs_Source,
+    extra_bytes: *mut *mut Lazrs_ExtraBytes,
+) -> Lazrs_Result {
+    debug_assert!(!extra_bytes.is_null());
+    *extra_bytes = std::ptr::null_mut();
+    let src = match CSource::from_c_source(source_type, source) {
+        Ok(src) => src,
+        Err(result) => return result,
+    };
+    match read_extra_bytes(src) {
+        Ok(parsed) => {
+            *extra_bytes = Box::into_raw(Box::new(parsed));
+            Lazrs_Result::LAZRS_OK
+        }
+        Err(result) => result,
+    }
+}
+
+/// Frees the extra dimensions
+///
+/// @extra_bytes can be NULL (no-op)
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_extra_bytes_delete(extra_bytes: *mut Lazrs_ExtraBytes) {
+    if !extra_bytes.is_null() {
+        let _ = Box::from_raw(extra_bytes);
+    }
+}
+
+/// Returns the number of extra dimensions
+///
+/// @extra_bytes: must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_extra_bytes_len(extra_bytes: *const Lazrs_ExtraBytes) -> usize {
+    debug_assert!(!extra_bytes.is_null());
+    (*extra_bytes).descriptors.len()
+}
+
+/// Gets the descriptor of an extra dimension
+///
+/// @extra_bytes: must not be NULL
+/// @index: index of the dimension, LAZRS_OTHER is returned if it is out of bounds
+/// @descriptor: will receive the descriptor
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_extra_bytes_descriptor(
+    extra_bytes: *const Lazrs_ExtraBytes,
+    index: usize,
+    descriptor: *mut Lazrs_ExtraBytesDescriptor,
+) -> Lazrs_Result {
+    debug_assert!(!extra_bytes.is_null());
+    debug_assert!(!descriptor.is_null());
+    let extra_bytes = &*extra_bytes;
+    match extra_bytes.descriptors.get(index) {
+        Some(d) => {
+            *descriptor = *d;
+            Lazrs_Result::LAZRS_OK
+        }
+        None => Lazrs_Result::LAZRS_OTHER,
+    }
+}
+
+/// Finds an extra dimension by its name
+///
+/// @extra_bytes: must not be NULL
+/// @name: NUL-terminated name of the dimension, LAZRS_OTHER is returned if no dimension has it
+/// @index: will receive the index of the first dimension with that name
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_extra_bytes_find(
+    extra_bytes: *const Lazrs_ExtraBytes,
+    name: *const libc::c_char,
+    index: *mut usize,
+) -> Lazrs_Result {
+    debug_assert!(!extra_bytes.is_null());
+    debug_assert!(!name.is_null());
+    debug_assert!(!index.is_null());
+    let name = CStr::from_ptr(name).to_bytes();
+    let extra_bytes = &*extra_bytes;
+    match extra_bytes
+        .descriptors
+        .iter()
+        .position(|descriptor| descriptor.has_name(name))
+    {
+        Some(i) => {
+            *index = i;
+            Lazrs_Result::LAZRS_OK
+        }
+        None => Lazrs_Result::LAZRS_OTHER,
+    }
+}
+
+/// Reads the value of an extra dimension from a point record
+///
+/// Values of 64-bit integer dimensions that do not fit in a double lose precision.
+///
+/// @extra_bytes: must not be NULL
+/// @index: index of the dimension
+/// @element: the element of the dimension, 0 unless it is an array,
+///           LAZRS_OTHER is returned if the dimension (or undocumented bytes) has no such element
+/// @record: the LAS data of the point
+/// @record_len: size of the record, LAZRS_OTHER is returned if it is smaller
+///              than the point size the dimensions were read for
+/// @apply_scale: whether to return `stored * scale + offset` instead of the stored value
+/// @value: will receive the value
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_extra_bytes_get(
+    extra_bytes: *const Lazrs_ExtraBytes,
+    index: usize,
+    element: u8,
+    record: *const u8,
+    record_len: usize,
+    apply_scale: bool,
+    value: *mut f64,
+) -> Lazrs_Result {
+    debug_assert!(!extra_bytes.is_null());
+    debug_assert!(!record.is_null());
+    debug_assert!(!value.is_null());
+    let extra_bytes = &*extra_bytes;
+    if record_len < extra_bytes.point_size {
+        return Lazrs_Result::LAZRS_OTHER;
+    }
+    let descriptor = match extra_bytes.descriptors.get(index) {
+        Some(descriptor) => descriptor,
+        None => return Lazrs_Result::LAZRS_OTHER,
+    };
+    let offset = match descriptor.element_offset(element) {
+        Some(offset) => offset,
+        None => return Lazrs_Result::LAZRS_OTHER,
+    };
+    let mut v = descriptor.value_type.read(record.add(offset));
+    if apply_scale {
+        let e = usize::from(element);
+        v = v * descriptor.scale[e] + descriptor.offset[e];
+    }
+    *value = v;
+    Lazrs_Result::LAZRS_OK
+}
+
+/// Writes the value of an extra dimension in a point record
+///
+/// The value is converted with the semantics of a C cast,
+/// after being rounded to the nearest integer when the scale is applied.
+///
+/// @extra_bytes: must not be NULL
+/// @index: index of the dimension
+/// @element: the element of the dimension, 0 unless it is an array,
+///           LAZRS_OTHER is returned if the dimension (or undocumented bytes) has no such element
+/// @record: the LAS data of the point
+/// @record_len: size of the record, LAZRS_OTHER is returned if it is smaller
+///              than the point size the dimensions were read for
+/// @value: the value
+/// @apply_scale: whether the value is `stored * scale + offset` instead of the stored value
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_extra_bytes_set(
+    extra_bytes: *const Lazrs_ExtraBytes,
+    index: usize,
+    element: u8,
+    record: *mut u8,
+    record_len: usize,
+    value: f64,
+    apply_scale: bool,
+) -> Lazrs_Result {
+    debug_assert!(!extra_bytes.is_null());
+    debug_assert!(!record.is_null());
+    let extra_bytes = &*extra_bytes;
+    if record_len < extra_bytes.point_size {
+        return Lazrs_Result::LAZRS_OTHER;
+    }
+    let descriptor = match extra_bytes.descriptors.get(index) {
+        Some(descriptor) => descriptor,
+        None => return Lazrs_Result::LAZRS_OTHER,
+    };
+    let offset = match descriptor.element_offset(element) {
+        Some(offset) => offset,
+        None => return Lazrs_Result::LAZRS_OTHER,
+    };
+    let mut v = value;
+    if apply_scale {
+        let e = usize::from(element);
+        v = (v - descriptor.offset[e]) / descriptor.scale[e];
+        let is_float = matches!(
+            descriptor.value_type,
+            Lazrs_ValueType::LAZRS_VALUE_F32 | Lazrs_ValueType::LAZRS_VALUE_F64
+        );
+        if !is_float {
+            v = v.round();
+        }
+    }
+    descriptor.value_type.write(record.add(offset), v);
+    Lazrs_Result::LAZRS_OK
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn descriptor_data(data_type: u8, options: u8, name: &str) -> Vec<u8> {
+        let mut data = vec![0u8; DESCRIPTOR_SIZE];
+        data[2] = data_type;
+        data[3] = options;
+        data[4..4 + name.len()].copy_from_slice(name.as_bytes());
+        data
+    }
+
+    fn format() -> PointFormat {
+        PointFormat::new(6).unwrap()
+    }
+
+    #[test]
+    fn descriptors_are_parsed() {
+        let mut data = descriptor_data(4, NO_DATA_BIT | SCALE_BIT | OFFSET_BIT, "height");
+        data[40..48].copy_from_slice(&(-1i64).to_le_bytes());
+        data[112..120].copy_from_slice(&0.01f64.to_le_bytes());
+        data[136..144].copy_from_slice(&100.0f64.to_le_bytes());
+        data[160..174].copy_from_slice(b"above  ground\0");
+        let descriptor = Lazrs_ExtraBytesDescriptor::read_from(&data, 30).unwrap();
+        assert!(descriptor.has_name(b"height"));
+        assert!(!descriptor.has_name(b"heigh"));
+        assert_eq!(descriptor.value_type, Lazrs_ValueType::LAZRS_VALUE_I16);
+        assert_eq!((descriptor.num_elements, descriptor.size), (1, 2));
+        assert!(descriptor.has_no_data && !descriptor.has_min && !descriptor.has_max);
+        assert_eq!(descriptor.no_data[0], -1.0);
+        assert_eq!(descriptor.scale, [0.01, 0.0, 0.0]);
+        assert_eq!(descriptor.offset, [100.0, 0.0, 0.0]);
+        assert_eq!(descriptor.element_offset(0), Some(30));
+        assert_eq!(descriptor.element_offset(1), None);
+        let description = unsafe { CStr::from_ptr(descriptor.description.as_ptr()) };
+        assert_eq!(description.to_bytes(), b"above  ground");
+
+        // A deprecated array of 3 doubles, without scale
+        let descriptor =
+            Lazrs_ExtraBytesDescriptor::read_from(&descriptor_data(30, 0, "v"), 0).unwrap();
+        assert_eq!(descriptor.value_type, Lazrs_ValueType::LAZRS_VALUE_F64);
+        assert_eq!((descriptor.num_elements, descriptor.size), (3, 24));
+        assert_eq!(descriptor.scale, [1.0; 3]);
+        assert_eq!(descriptor.element_offset(2), Some(16));
+
+        // The options of undocumented bytes are their size, not flags
+        let descriptor =
+            Lazrs_ExtraBytesDescriptor::read_from(&descriptor_data(0, 0b11111, ""), 0).unwrap();
+        assert_eq!((descriptor.num_elements, descriptor.size), (0, 31));
+        assert!(!descriptor.has_no_data);
+        assert_eq!(descriptor.scale, [1.0; 3]);
+        assert_eq!(descriptor.element_offset(0), None);
+
+        assert!(Lazrs_ExtraBytesDescriptor::read_from(&descriptor_data(31, 0, ""), 0).is_none());
+    }
+
+    #[test]
+    fn dimensions_follow_each_other_in_the_records() {
+        let format = format();
+        let data = [
+            descriptor_data(2, 0, "a"),
+            descriptor_data(0, 3, ""),
+            descriptor_data(10, 0, "b"),
+        ]
+        .concat();
+        let extra_bytes =
+            Lazrs_ExtraBytes::from_vlr_data(&format, format.size + 12, &data).unwrap();
+        let offsets: Vec<usize> = extra_bytes
+            .descriptors
+            .iter()
+            .map(|descriptor| descriptor.record_offset)
+            .collect();
+        assert_eq!(offsets, vec![format.size, format.size + 1, format.size + 4]);
+
+        // Bytes after the described ones are allowed
+        assert!(Lazrs_ExtraBytes::from_vlr_data(&format, format.size + 20, &data).is_some());
+        assert!(Lazrs_ExtraBytes::from_vlr_data(&format, format.size + 11, &data).is_none());
+        assert!(Lazrs_ExtraBytes::from_vlr_data(&format, format.size - 1, &[]).is_none());
+        assert!(Lazrs_ExtraBytes::from_vlr_data(&format, format.size + 12, &data[1..]).is_none());
+    }
+
+    #[test]
+    fn values_are_read_and_written_in_bounds() {
+        let format = format();
+        let mut data = descriptor_data(0, 2, "");
+        let mut scaled = descriptor_data(6, SCALE_BIT | OFFSET_BIT, "scaled");
+        scaled[112..120].copy_from_slice(&0.5f64.to_le_bytes());
+        scaled[136..144].copy_from_slice(&(-10.0f64).to_le_bytes());
+        data.extend_from_slice(&scaled);
+        let point_size = format.size + 6;
+        let extra_bytes = Lazrs_ExtraBytes::from_vlr_data(&format, point_size, &data).unwrap();
+
+        let mut record = vec![0u8; point_size];
+        let mut value = 0.0;
+        unsafe {
+            let set = |record: &mut [u8], index, value, apply_scale| {
+                lazrs_extra_bytes_set(
+                    &extra_bytes,
+                    index,
+                    0,
+                    record.as_mut_ptr(),
+                    record.len(),
+                    value,
+                    apply_scale,
+                )
+            };
+            assert_eq!(set(&mut record, 1, 2.6, true), Lazrs_Result::LAZRS_OK);
+            assert_eq!(
+                &record[format.size + 2..],
+                &25i32.to_le_bytes(),
+                "(2.6 + 10) / 0.5 is rounded"
+            );
+            assert_eq!(
+                lazrs_extra_bytes_get(
+                    &extra_bytes,
+                    1,
+                    0,
+                    record.as_ptr(),
+                    point_size,
+                    true,
+                    &mut value
+                ),
+                Lazrs_Result::LAZRS_OK
+            );
+            assert_eq!(value, 2.5);
+            assert_eq!(
+                lazrs_extra_bytes_get(
+                    &extra_bytes,
+                    1,
+                    0,
+                    record.as_ptr(),
+                    point_size,
+                    false,
+                    &mut value
+                ),
+                Lazrs_Result::LAZRS_OK
+            );
+            assert_eq!(value, 25.0);
+
+            // Undocumented bytes, missing dimensions and short records
+            assert_eq!(set(&mut record, 0, 1.0, false), Lazrs_Result::LAZRS_OTHER);
+            assert_eq!(set(&mut record, 2, 1.0, false), Lazrs_Result::LAZRS_OTHER);
+            assert_eq!(
+                set(&mut record[..point_size - 1], 1, 1.0, false),
+                Lazrs_Result::LAZRS_OTHER
+            );
+            assert_eq!(
+                lazrs_extra_bytes_get(
+                    &extra_bytes,
+                    1,
+                    0,
+                    record.as_ptr(),
+                    point_size - 1,
+                    false,
+                    &mut value
+                ),
+                Lazrs_Result::LAZRS_OTHER
+            );
+        }
+        assert_eq!(&record[format.size + 2..], &25i32.to_le_bytes());
+    }
+}
@@ -0,0 +1,423 @@
+//! Decompression that only keeps the points matching a filter.
+
+use crate::cancel::{points_per_step, run_in_steps};
+use crate::point::{Lazrs_Point, PointFormat};
+use crate::{Lazrs_LasZipDecompressor, Lazrs_Result};
+
+/// A box, bounds included
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Lazrs_Bounds {
+    pub(crate) min_x: f64,
+    pub(crate) min_y: f64,
+    pub(crate) min_z: f64,
+    pub(crate) max_x: f64,
+    pub(crate) max_y: f64,
+    pub(crate) max_z: f64,
+}
+
+impl Lazrs_Bounds {
+    pub(crate) fn contains(&self, x: f64, y: f64, z: f64) -> bool {
+        (self.min_x..=self.max_x).contains(&x)
+            && (self.min_y..=self.max_y).contains(&y)
+            && (self.min_z..=self.max_z).contains(&z)
+    }
+}
+
+/// Which returns a filter keeps
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Lazrs_ReturnFilter {
+    LAZRS_RETURNS_ALL,
+    /// Points whose return number is 1
+    LAZRS_RETURNS_FIRST,
+    /// Points whose return number is their number of returns
+    LAZRS_RETURNS_LAST,
+    /// Points that are the only return of their pulse
+    LAZRS_RETURNS_SINGLE,
+    /// First returns of pulses with more than one return
+    LAZRS_RETURNS_FIRST_OF_MANY,
+    /// Last returns of pulses with more than one return
+    LAZRS_RETURNS_LAST_OF_MANY,
+    /// Returns that are neither the first nor the last
+    LAZRS_RETURNS_INTERMEDIATE,
+}
+
+impl Lazrs_ReturnFilter {
+    fn matches(self, return_number: u8, number_of_returns: u8) -> bool {
+        let is_first = return_number == 1;
+        let is_last = return_number == number_of_returns;
+        let is_single = number_of_returns == 1;
+        match self {
+            Lazrs_ReturnFilter::LAZRS_RETURNS_ALL => true,
+            Lazrs_ReturnFilter::LAZRS_RETURNS_FIRST => is_first,
+            Lazrs_ReturnFilter::LAZRS_RETURNS_LAST => is_last,
+            Lazrs_ReturnFilter::LAZRS_RETURNS_SINGLE => is_single,
+            Lazrs_ReturnFilter::LAZRS_RETURNS_FIRST_OF_MANY => is_first && !is_single,
+            Lazrs_ReturnFilter::LAZRS_RETURNS_LAST_OF_MANY => is_last && !is_single,
+            Lazrs_ReturnFilter::LAZRS_RETURNS_INTERMEDIATE => !is_first && !is_last,
+        }
+    }
+}
+
+/// The conditions a point must meet to be kept, all of them are checked
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Lazrs_Filter {
+    /// The classifications to keep, NULL (or 0 classifications) keeps all of them
+    classifications: *const u8,
+    num_classifications: usize,
+    returns: Lazrs_ReturnFilter,
+    /// Whether to keep only the points in `bounds`
+    use_bounds: bool,
+    /// Scaled coordinates, as in the LAS header
+    bounds: Lazrs_Bounds,
+    /// Scales and offsets of the LAS header, to compute the coordinates of the points
+    scales: [f64; 3],
+    offsets: [f64; 3],
+    /// Whether to keep only the points whose GPS time is in `min_gps_time..=max_gps_time`
+    use_gps_time: bool,
+    min_gps_time: f64,
+    max_gps_time: f64,
+    /// Whether to keep only the points whose intensity is in `min_intensity..=max_intensity`
+    use_intensity: bool,
+    min_intensity: u16,
+    max_intensity: u16,
+}
+
+/// A `Lazrs_Filter` ready to be used
+struct Filter {
+    format: PointFormat,
+    classifications: Option<[bool; 256]>,
+    returns: Lazrs_ReturnFilter,
+    bounds: Option<(Lazrs_Bounds, [f64; 3], [f64; 3])>,
+    gps_time: Option<(f64, f64)>,
+    intensity: Option<(u16, u16)>,
+}
+
+impl Filter {
+    unsafe fn new(format: PointFormat, filter: &Lazrs_Filter) -> Self {
+        let classifications = if filter.classifications.is_null() || filter.num_classifications == 0
+        {
+            None
+        } else {
+            let mut kept = [false; 256];
+            for &classification in
+                std::slice::from_raw_parts(filter.classifications, filter.num_classifications)
+            {
+                kept[usize::from(classification)] = true;
+            }
+            Some(kept)
+        };
+        Self {
+            format,
+            classifications,
+            returns: filter.returns,
+            bounds: filter
+                .use_bounds
+                .then_some((filter.bounds, filter.scales, filter.offsets)),
+            gps_time: filter
+                .use_gps_time
+                .then_some((filter.min_gps_time, filter.max_gps_time)),
+            intensity: filter
+                .use_intensity
+                .then_some((filter.min_intensity, filter.max_intensity)),
+        }
+    }
+
+    fn matches(&self, record: &[u8]) -> bool {
+        let point = Lazrs_Point::read_from(&self.format, record);
+        if let Some(kept) = &self.classifications {
+            if !kept[usize::from(point.classification)] {
+                return false;
+            }
+        }
+        if !self
+            .returns
+            .matches(point.return_number, point.number_of_returns)
+        {
+            return false;
+        }
+        if let Some((bounds, scales, offsets)) = &self.bounds {
+            let x = f64::from(point.x) * scales[0] + offsets[0];
+            let y = f64::from(point.y) * scales[1] + offsets[1];
+            let z = f64::from(point.z) * scales[2] + offsets[2];
+            if !bounds.contains(x, y, z) {
+                return false;
+            }
+        }
+        if let Some((min, max)) = self.gps_time {
+            if !(min..=max).contains(&point.gps_time) {
+                return false;
+            }
+        }
+        if let Some((min, max)) = self.intensity {
+            if !(min..=max).contains(&point.intensity) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Moves the records that match at the start of `records`, returns their number
+    fn retain(&self, records: &mut [u8], point_size: usize) -> usize {
+        let num_records = records.len() / point_size;
+        let mut num_kept = 0;
+        for i in 0..num_records {
+            let record = i * point_size..(i + 1) * point_size;
+            if self.matches(&records[record.clone()]) {
+                if num_kept != i {
+                    records.copy_within(record, num_kept * point_size);
+                }
+                num_kept += 1;
+            }
+        }
+        num_kept
+    }
+}
+
+/// Decompresses points and only keeps the ones that match the filter
+///
+/// `len / point size` points are read, the matching ones are written
+/// one after the other at the start of `out`, which is also used as the
+/// decompression buffer, so no other buffer is needed.
+///
+/// @decompressor: the decompressor, must not be NULL
+/// @point_format_id: the point format of the compressed points, LAZRS_OTHER is returned
+///                   if the LAZ items of the decompressor are not the ones of this format
+/// @filter: the filter, must not be NULL
+/// @out: out buffer that will receive the LAS data of the matching points
+/// @len: size of the output buffer
+/// @num_emitted: will receive the number of points written in `out`,
+///               also set when an error occurs or the decompression is cancelled
+///
+/// If cancelled, `lazrs_decompressor_position` tells how many points were read.
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_decompressor_decompress_filtered(
+    decompressor: *mut Lazrs_LasZipDecompressor,
+    point_format_id: u8,
+    filter: *const Lazrs_Filter,
+    out: *mut u8,
+    len: libc::size_t,
+    num_emitted: *mut u64,
+) -> Lazrs_Result {
+    debug_assert!(!decompressor.is_null());
+    debug_assert!(!filter.is_null());
+    debug_assert!(!out.is_null());
+    debug_assert!(!num_emitted.is_null());
+    *num_emitted = 0;
+    let Lazrs_LasZipDecompressor {
+        decompressor,
+        vlr,
+        cancel_token,
+        thread_pool,
+        position,
+    } = &mut *decompressor;
+    let (format, point_size) = match PointFormat::of_vlr(point_format_id, vlr) {
+        Ok(format) => format,
+        Err(result) => return result,
+    };
+    let filter = Filter::new(format, &*filter);
+    let buf = std::slice::from_raw_parts_mut(out, len);
+
+    let mut emitted = 0;
+    let result = thread_pool.install(|| {
+        let step = points_per_step(vlr, decompressor.is_parallel()).max(1);
+        run_in_steps(len / point_size, step, cancel_token.as_ref(), |range| {
+            let count = range.len();
+            let records = &mut buf[emitted * point_size..(emitted + count) * point_size];
+            if let Err(error) = decompressor.decompress_many(records) {
+                return error.into();
+            }
+            *position += count as u64;
+            emitted += filter.retain(records, point_size);
+            Lazrs_Result::LAZRS_OK
+        })
+    });
+    *num_emitted = emitted as u64;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    use crate::{
+        lazrs_decompressor_delete, lazrs_decompressor_new, lazrs_decompressor_position,
+        Lazrs_Buffer, Lazrs_DecompressorParams, Lazrs_Source, Lazrs_SourceType,
+    };
+
+    const NUM_POINTS: usize = 30;
+
+    /// Points of format 1, their classification is `i % 4` and their return number `i % 3 + 1`
+    fn points() -> Vec<u8> {
+        let format = PointFormat::new(1).unwrap();
+        let mut records = vec![0u8; NUM_POINTS * format.size];
+        for (i, record) in records.chunks_exact_mut(format.size).enumerate() {
+            let point = Lazrs_Point {
+                x: i as i32,
+                y: 2 * i as i32,
+                z: 3 * i as i32,
+                intensity: i as u16 * 10,
+                return_number: (i % 3) as u8 + 1,
+                number_of_returns: 3,
+                classification: (i % 4) as u8,
+                gps_time: 1000.0 + i as f64 * 0.5,
+                ..Default::default()
+            };
+            point.write_to(&format, record);
+        }
+        records
+    }
+
+    fn default_filter() -> Lazrs_Filter {
+        Lazrs_Filter {
+            classifications: std::ptr::null(),
+            num_classifications: 0,
+            returns: Lazrs_ReturnFilter::LAZRS_RETURNS_ALL,
+            use_bounds: false,
+            bounds: Lazrs_Bounds::default(),
+            scales: [1.0; 3],
+            offsets: [0.0; 3],
+            use_gps_time: false,
+            min_gps_time: 0.0,
+            max_gps_time: 0.0,
+            use_intensity: false,
+            min_intensity: 0,
+            max_intensity: 0,
+        }
+    }
+
+    /// Compresses the points, decompresses them with the filter and
+    /// returns the x of the emitted points
+    fn filtered_xs(filter: &Lazrs_Filter) -> Vec<i32> {
+        let items = laz::LazItemRecordBuilder::default_for_point_format_id(1, 0).unwrap();
+        let vlr = laz::LazVlrBuilder::new(items)
+            .with_fixed_chunk_size(10)
+            .build();
+        let mut data = Cursor::new(vec![]);
+        laz::compress_buffer(&mut data, &points(), vlr.clone()).unwrap();
+        let data = data.into_inner();
+        let mut vlr_data = vec![];
+        vlr.write_to(&mut vlr_data).unwrap();
+
+        let format = PointFormat::new(1).unwrap();
+        let mut out = vec![0u8; NUM_POINTS * format.size];
+        let mut num_emitted = 0;
+        unsafe {
+            let params = Lazrs_DecompressorParams {
+                source_type: Lazrs_SourceType::LAZRS_SOURCE_BUFFER,
+                source: Lazrs_Source {
+                    buffer: Lazrs_Buffer {
+                        data: data.as_ptr(),
+                        len: data.len(),
+                    },
+                },
+                source_offset: 0,
+                laszip_vlr: Lazrs_Buffer {
+                    data: vlr_data.as_ptr(),
+                    len: vlr_data.len(),
+                },
+            };
+            let mut decompressor = std::ptr::null_mut();
+            assert_eq!(
+                lazrs_decompressor_new(params, false, &mut decompressor),
+                Lazrs_Result::LAZRS_OK
+            );
+            let result = lazrs_decompressor_decompress_filtered(
+                decompressor,
+                3,
+                filter,
+                out.as_mut_ptr(),
+                out.len(),
+                &mut num_emitted,
+            );
+            assert_eq!(result, Lazrs_Result::LAZRS_OTHER);
+            let result = lazrs_decompressor_decompress_filtered(
+                decompressor,
+                1,
+                filter,
+                out.as_mut_ptr(),
+                out.len(),
+                &mut num_emitted,
+            );
+            assert_eq!(result, Lazrs_Result::LAZRS_OK);
+            assert_eq!(lazrs_decompressor_position(decompressor), NUM_POINTS as u64);
+            lazrs_decompressor_delete(decompressor);
+        }
+        out[..num_emitted as usize * format.size]
+            .chunks_exact(format.size)
+            .map(|record| Lazrs_Point::read_from(&format, record).x)
+            .collect()
+    }
+
+    #[test]
+    fn default_filter_keeps_all_points() {
+        let xs = filtered_xs(&default_filter());
+        assert_eq!(xs, (0..NUM_POINTS as i32).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn classifications_and_returns_are_all_checked() {
+        let classifications = [2u8];
+        let filter = Lazrs_Filter {
+            classifications: classifications.as_ptr(),
+            num_classifications: classifications.len(),
+            returns: Lazrs_ReturnFilter::LAZRS_RETURNS_FIRST,
+            ..default_filter()
+        };
+        // Classification 2 and first return: i % 4 == 2 and i % 3 == 0
+        assert_eq!(filtered_xs(&filter), vec![6, 18]);
+    }
+
+    #[test]
+    fn bounds_gps_time_and_intensity_are_inclusive() {
+        let filter = Lazrs_Filter {
+            use_bounds: true,
+            bounds: Lazrs_Bounds {
+                min_x: 0.0,
+                min_y: 0.0,
+                min_z: 0.0,
+                max_x: 100.0,
+                max_y: 100.0,
+                max_z: 100.0,
+            },
+            // The scaled x of point i is 10 + i / 2
+            scales: [0.5, 1.0, 1.0],
+            offsets: [10.0, 0.0, 0.0],
+            use_gps_time: true,
+            min_gps_time: 1002.0,
+            max_gps_time: 1010.0,
+            use_intensity: true,
+            min_intensity: 0,
+            max_intensity: 180,
+            ..default_filter()
+        };
+        assert_eq!(filtered_xs(&filter), (4..=18).collect::<Vec<_>>());
+
+        let filter = Lazrs_Filter {
+            bounds: Lazrs_Bounds {
+                max_x: 12.0,
+                ..filter.bounds
+            },
+            ..filter
+        };
+        assert_eq!(filtered_xs(&filter), (4..=4).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn return_filters() {
+        let filter = |returns| Lazrs_Filter {
+            returns,
+            ..default_filter()
+        };
+        let xs = filtered_xs(&filter(Lazrs_ReturnFilter::LAZRS_RETURNS_LAST));
+        assert!(xs.iter().all(|x| x % 3 == 2));
+        assert_eq!(xs.len(), NUM_POINTS / 3);
+        let xs = filtered_xs(&filter(Lazrs_ReturnFilter::LAZRS_RETURNS_INTERMEDIATE));
+        assert!(xs.iter().all(|x| x % 3 == 1));
+        assert_eq!(xs.len(), NUM_POINTS / 3);
+        assert!(filtered_xs(&filter(Lazrs_ReturnFilter::LAZRS_RETURNS_SINGLE)).is_empty());
+    }
+}
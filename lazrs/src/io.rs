@@ -3,7 +3,7 @@ use libc::c_int;
 use std::convert::TryInto;
 use std::ffi::c_void;
 use std::fs::File;
-use std::io::{BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::io::{BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
 use std::ptr::NonNull;
 
 fn seek_from_to_c_whence(seek_from: SeekFrom) -> (i64, c_int) {
@@ -49,14 +49,11 @@ impl Seek for CustomSource {
         let ret = unsafe { (self.seek_fn)(self.user_data, pos, whence) };
 
         if ret != 0 {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Failed to seek".to_string(),
-            ));
+            return Err(std::io::Error::other("Failed to seek".to_string()));
         }
         let position = unsafe { (self.tell_fn)(self.user_data) };
 
-        Ok(position as u64)
+        Ok(position)
     }
 }
 
@@ -103,7 +100,9 @@ impl Seek for CFile {
         unsafe {
             let (pos, whence) = seek_from_to_c_whence(pos);
 
-            if pos != 0 && whence != libc::SEEK_CUR {
+            if !(pos == 0 && whence == libc::SEEK_CUR) {
+                // c_long is not an i64 on every platform
+                #[allow(clippy::useless_conversion)]
                 let result = libc::fseek(self.fh.as_ptr(), pos.try_into().unwrap(), whence);
                 if result != 0 {
                     return Err(std::io::Error::from_raw_os_error(result));
@@ -242,10 +241,7 @@ impl Write for CustomDest {
         let r = unsafe { (self.flush_fn)(self.user_data) };
 
         if r != 0 {
-            Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Failed to flush".to_string(),
-            ))
+            Err(std::io::Error::other("Failed to flush".to_string()))
         } else {
             Ok(())
         }
@@ -258,26 +254,56 @@ impl Seek for CustomDest {
         let ret = unsafe { (self.seek_fn)(self.user_data, pos, whence) };
 
         if ret != 0 {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Failed to seek".to_string(),
-            ));
+            return Err(std::io::Error::other("Failed to seek".to_string()));
         }
         let position = unsafe { (self.tell_fn)(self.user_data) };
 
-        Ok(position as u64)
+        Ok(position)
     }
 }
 
 pub enum CDest {
     CFile(CFile),
+    File(BufWriter<File>),
     Custom(CustomDest),
 }
 
+impl CDest {
+    pub(crate) unsafe fn from_c_dest(
+        dest_type: crate::Lazrs_DestType,
+        dest: crate::Lazrs_Dest,
+    ) -> Result<Self, crate::Lazrs_Result> {
+        let cdest = match dest_type {
+            crate::Lazrs_DestType::LAZRS_DEST_CFILE => {
+                CDest::CFile(CFile::new_unchecked(dest.file))
+            }
+            crate::Lazrs_DestType::LAZRS_DEST_CUSTOM => CDest::Custom(dest.custom),
+            crate::Lazrs_DestType::LAZRS_DEST_FNAME => {
+                match std::str::from_utf8(std::slice::from_raw_parts(
+                    dest.buffer.data,
+                    dest.buffer.len,
+                )) {
+                    Ok(fname) => match File::create(std::path::Path::new(fname)) {
+                        Ok(f) => CDest::File(BufWriter::new(f)),
+                        Err(_error) => {
+                            return Err(crate::Lazrs_Result::LAZRS_IO_ERROR);
+                        }
+                    },
+                    Err(_error) => {
+                        return Err(crate::Lazrs_Result::LAZRS_IO_ERROR);
+                    }
+                }
+            }
+        };
+        Ok(cdest)
+    }
+}
+
 impl std::io::Write for CDest {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         match self {
             CDest::CFile(file) => file.write(buf),
+            CDest::File(file) => file.write(buf),
             CDest::Custom(custom) => custom.write(buf),
         }
     }
@@ -285,6 +311,7 @@ impl std::io::Write for CDest {
     fn flush(&mut self) -> std::io::Result<()> {
         match self {
             CDest::CFile(file) => file.flush(),
+            CDest::File(file) => file.flush(),
             CDest::Custom(custom) => custom.flush(),
         }
     }
@@ -294,7 +321,44 @@ impl Seek for CDest {
     fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
         match self {
             CDest::CFile(file) => file.seek(pos),
+            CDest::File(file) => file.seek(pos),
             CDest::Custom(custom) => custom.seek(pos),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A CFile on a temporary file holding `data`, positioned at its end
+    fn temp_file(data: &[u8]) -> CFile {
+        let fh = unsafe { libc::tmpfile() };
+        assert!(!fh.is_null());
+        let mut file = unsafe { CFile::new_unchecked(fh) };
+        file.write_all(data).unwrap();
+        file.flush().unwrap();
+        file
+    }
+
+    #[test]
+    fn cfile_seeks_to_the_start() {
+        let mut file = temp_file(b"abcdef");
+        assert_eq!(file.seek(SeekFrom::Start(0)).unwrap(), 0);
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"abcdef");
+        unsafe { libc::fclose(file.fh.as_ptr()) };
+    }
+
+    #[test]
+    fn cfile_seeks_relative_to_the_current_position() {
+        let mut file = temp_file(b"abcdef");
+        assert_eq!(file.seek(SeekFrom::Current(-2)).unwrap(), 4);
+        assert_eq!(file.stream_position().unwrap(), 4);
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"ef");
+        unsafe { libc::fclose(file.fh.as_ptr()) };
+    }
+}
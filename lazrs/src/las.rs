@@ -0,0 +1,682 @@
+//! Just enough of the LAS format to copy the header, VLRs and EVLRs of a file
+//! while its points are converted.
+
+use std::convert::TryInto;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+const SIGNATURE: &[u8; 4] = b"LASF";
+
+// Offsets of the fields of the header we need
+const GLOBAL_ENCODING_OFFSET: usize = 6;
+const VERSION_MINOR_OFFSET: usize = 25;
+const HEADER_SIZE_OFFSET: usize = 94;
+const OFFSET_TO_POINT_DATA_OFFSET: usize = 96;
+const NUMBER_OF_VLRS_OFFSET: usize = 100;
+const POINT_FORMAT_OFFSET: usize = 104;
+const POINT_SIZE_OFFSET: usize = 105;
+const LEGACY_NUMBER_OF_POINTS_OFFSET: usize = 107;
+const LEGACY_NUMBER_OF_POINTS_BY_RETURN_OFFSET: usize = 111;
+const SCALES_OFFSET: usize = 131;
+const OFFSETS_OFFSET: usize = 155;
+const BOUNDS_OFFSET: usize = 179;
+const START_OF_WAVEFORM_OFFSET: usize = 227;
+const START_OF_FIRST_EVLR_OFFSET: usize = 235;
+const NUMBER_OF_EVLRS_OFFSET: usize = 243;
+const NUMBER_OF_POINTS_OFFSET: usize = 247;
+const NUMBER_OF_POINTS_BY_RETURN_OFFSET: usize = 255;
+
+/// Size of the smallest header (LAS 1.0 - 1.2)
+const MIN_HEADER_SIZE: usize = 227;
+/// Size of the LAS 1.3 header
+const HEADER_SIZE_1_3: usize = 235;
+/// Size of the LAS 1.4 header
+const HEADER_SIZE_1_4: usize = 375;
+
+/// Bit of the global encoding telling the CRS is given as WKT,
+/// it must be set for point formats >= 6
+pub(crate) const GLOBAL_ENCODING_WKT: u16 = 1 << 4;
+
+/// Bits LASzip sets in the point format id of compressed files
+const COMPRESSION_BITS: u8 = 0xC0;
+
+pub(crate) const LASZIP_USER_ID: &str = "laszip encoded";
+pub(crate) const LASZIP_RECORD_ID: u16 = 22204;
+
+fn invalid_data(msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Size of the fields of the point format, without extra bytes
+pub(crate) fn point_format_size(point_format_id: u8) -> Option<u16> {
+    match point_format_id {
+        0 => Some(20),
+        1 => Some(28),
+        2 => Some(26),
+        3 => Some(34),
+        4 => Some(57),
+        5 => Some(63),
+        6 => Some(30),
+        7 => Some(36),
+        8 => Some(38),
+        9 => Some(59),
+        10 => Some(67),
+        _ => None,
+    }
+}
+
+/// The LAS header, kept as bytes so that fields we do not know about are copied as is
+#[derive(Clone, Debug)]
+pub(crate) struct Header {
+    raw: Vec<u8>,
+}
+
+impl Header {
+    pub(crate) fn read_from<R: Read>(src: &mut R) -> std::io::Result<Self> {
+        let mut raw = vec![0u8; HEADER_SIZE_OFFSET + 2];
+        src.read_exact(&mut raw)?;
+        if &raw[..4] != SIGNATURE {
+            return Err(invalid_data("not a LAS file"));
+        }
+        let header_size = u16::from_le_bytes([raw[94], raw[95]]) as usize;
+        if header_size < MIN_HEADER_SIZE {
+            return Err(invalid_data("LAS header is too small"));
+        }
+        raw.resize(header_size, 0);
+        src.read_exact(&mut raw[HEADER_SIZE_OFFSET + 2..])?;
+        Ok(Self { raw })
+    }
+
+    pub(crate) fn write_to<W: Write>(&self, dst: &mut W) -> std::io::Result<()> {
+        dst.write_all(&self.raw)
+    }
+
+    fn get<const N: usize>(&self, offset: usize) -> [u8; N] {
+        self.raw[offset..offset + N].try_into().unwrap()
+    }
+
+    fn set(&mut self, offset: usize, bytes: &[u8]) {
+        self.raw[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// Size of the fields the LAS version of the header has, the bytes after them are user bytes
+    fn fields_size(&self) -> usize {
+        let size = match self.raw[VERSION_MINOR_OFFSET] {
+            0..=2 => MIN_HEADER_SIZE,
+            3 => HEADER_SIZE_1_3,
+            _ => HEADER_SIZE_1_4,
+        };
+        size.min(self.raw.len())
+    }
+
+    fn has(&self, offset: usize, size: usize) -> bool {
+        offset + size <= self.fields_size()
+    }
+
+    /// Makes the header a LAS 1.4 one, as needed for point formats >= 6
+    ///
+    /// The fields the header misses are inserted before its user bytes.
+    pub(crate) fn upgrade_to_1_4(&mut self) -> std::io::Result<()> {
+        let end = self.fields_size();
+        if end == HEADER_SIZE_1_4 {
+            return Ok(());
+        }
+        let header_size: u16 = (self.raw.len() + HEADER_SIZE_1_4 - end)
+            .try_into()
+            .map_err(|_| invalid_data("LAS header is too large"))?;
+        let num_points = self.number_of_points();
+        let by_return = self.number_of_points_by_return();
+        self.raw.splice(end..end, vec![0u8; HEADER_SIZE_1_4 - end]);
+        self.raw[VERSION_MINOR_OFFSET] = 4;
+        self.set(HEADER_SIZE_OFFSET, &header_size.to_le_bytes());
+        self.set_number_of_points(num_points, &by_return);
+        Ok(())
+    }
+
+    /// Removes the fields LAS 1.3 and 1.4 added to the header, making it a LAS 1.2 one
+    ///
+    /// The header must be a LAS 1.4 one, the removed fields are returned as they were in it.
+    pub(crate) fn downgrade_to_1_2(&mut self) -> Vec<u8> {
+        let fields = self.raw.drain(MIN_HEADER_SIZE..HEADER_SIZE_1_4).collect();
+        self.raw[VERSION_MINOR_OFFSET] = 2;
+        let header_size = self.raw.len() as u16;
+        self.set(HEADER_SIZE_OFFSET, &header_size.to_le_bytes());
+        fields
+    }
+
+    /// Puts back the fields removed by `downgrade_to_1_2`, making the header a LAS 1.4 one
+    pub(crate) fn restore_1_4_fields(&mut self, fields: &[u8]) {
+        let end = if self.raw[VERSION_MINOR_OFFSET] >= 3 {
+            START_OF_FIRST_EVLR_OFFSET
+        } else {
+            MIN_HEADER_SIZE
+        };
+        let end = end.min(self.raw.len());
+        self.raw
+            .splice(MIN_HEADER_SIZE..end, fields.iter().copied());
+        self.raw[VERSION_MINOR_OFFSET] = 4;
+        let header_size = self.raw.len() as u16;
+        self.set(HEADER_SIZE_OFFSET, &header_size.to_le_bytes());
+    }
+
+    pub(crate) fn global_encoding(&self) -> u16 {
+        u16::from_le_bytes(self.get(GLOBAL_ENCODING_OFFSET))
+    }
+
+    pub(crate) fn set_global_encoding(&mut self, global_encoding: u16) {
+        self.set(GLOBAL_ENCODING_OFFSET, &global_encoding.to_le_bytes());
+    }
+
+    pub(crate) fn header_size(&self) -> u16 {
+        u16::from_le_bytes(self.get(HEADER_SIZE_OFFSET))
+    }
+
+    pub(crate) fn offset_to_point_data(&self) -> u32 {
+        u32::from_le_bytes(self.get(OFFSET_TO_POINT_DATA_OFFSET))
+    }
+
+    pub(crate) fn set_offset_to_point_data(&mut self, offset: u32) {
+        self.set(OFFSET_TO_POINT_DATA_OFFSET, &offset.to_le_bytes());
+    }
+
+    pub(crate) fn number_of_vlrs(&self) -> u32 {
+        u32::from_le_bytes(self.get(NUMBER_OF_VLRS_OFFSET))
+    }
+
+    pub(crate) fn set_number_of_vlrs(&mut self, n: u32) {
+        self.set(NUMBER_OF_VLRS_OFFSET, &n.to_le_bytes());
+    }
+
+    /// The point format id, without the bits LASzip sets for compressed data
+    pub(crate) fn point_format_id(&self) -> u8 {
+        self.raw[POINT_FORMAT_OFFSET] & !COMPRESSION_BITS
+    }
+
+    pub(crate) fn is_compressed(&self) -> bool {
+        self.raw[POINT_FORMAT_OFFSET] & COMPRESSION_BITS != 0
+    }
+
+    pub(crate) fn set_point_format(&mut self, point_format_id: u8, compressed: bool) {
+        self.raw[POINT_FORMAT_OFFSET] = if compressed {
+            point_format_id | 0x80
+        } else {
+            point_format_id
+        };
+    }
+
+    pub(crate) fn point_size(&self) -> u16 {
+        u16::from_le_bytes(self.get(POINT_SIZE_OFFSET))
+    }
+
+    pub(crate) fn set_point_size(&mut self, size: u16) {
+        self.set(POINT_SIZE_OFFSET, &size.to_le_bytes());
+    }
+
+    /// Number of extra bytes at the end of each point
+    pub(crate) fn num_extra_bytes(&self) -> Option<u16> {
+        let size = point_format_size(self.point_format_id())?;
+        self.point_size().checked_sub(size)
+    }
+
+    pub(crate) fn number_of_points(&self) -> u64 {
+        if self.has(NUMBER_OF_POINTS_OFFSET, 8) {
+            let n = u64::from_le_bytes(self.get(NUMBER_OF_POINTS_OFFSET));
+            if n != 0 {
+                return n;
+            }
+        }
+        u64::from(u32::from_le_bytes(self.get(LEGACY_NUMBER_OF_POINTS_OFFSET)))
+    }
+
+    /// Returns the number of points by return, from the 1.4 fields if they are set
+    pub(crate) fn number_of_points_by_return(&self) -> [u64; 15] {
+        let mut counts = [0u64; 15];
+        if self.has(NUMBER_OF_POINTS_BY_RETURN_OFFSET, 15 * 8) {
+            for (i, count) in counts.iter_mut().enumerate() {
+                *count = u64::from_le_bytes(self.get(NUMBER_OF_POINTS_BY_RETURN_OFFSET + 8 * i));
+            }
+        }
+        if counts.iter().all(|c| *c == 0) {
+            for (i, count) in counts.iter_mut().take(5).enumerate() {
+                *count = u64::from(u32::from_le_bytes(
+                    self.get(LEGACY_NUMBER_OF_POINTS_BY_RETURN_OFFSET + 4 * i),
+                ));
+            }
+        }
+        counts
+    }
+
+    /// Sets the number of points, and by return, in the legacy fields
+    /// when the point format and counts allow it, and in the 1.4 fields if present.
+    pub(crate) fn set_number_of_points(&mut self, n: u64, by_return: &[u64; 15]) {
+        let fits_legacy = self.point_format_id() < 6
+            && n <= u64::from(u32::MAX)
+            && by_return[5..].iter().all(|c| *c == 0);
+        let legacy = |v: u64| if fits_legacy { v as u32 } else { 0 };
+        self.set(LEGACY_NUMBER_OF_POINTS_OFFSET, &legacy(n).to_le_bytes());
+        for (i, count) in by_return.iter().take(5).enumerate() {
+            self.set(
+                LEGACY_NUMBER_OF_POINTS_BY_RETURN_OFFSET + 4 * i,
+                &legacy(*count).to_le_bytes(),
+            );
+        }
+        if self.has(NUMBER_OF_POINTS_BY_RETURN_OFFSET, 15 * 8) {
+            self.set(NUMBER_OF_POINTS_OFFSET, &n.to_le_bytes());
+            for (i, count) in by_return.iter().enumerate() {
+                self.set(
+                    NUMBER_OF_POINTS_BY_RETURN_OFFSET + 8 * i,
+                    &count.to_le_bytes(),
+                );
+            }
+        }
+    }
+
+    pub(crate) fn scales(&self) -> [f64; 3] {
+        let mut v = [0.0; 3];
+        for (i, s) in v.iter_mut().enumerate() {
+            *s = f64::from_le_bytes(self.get(SCALES_OFFSET + 8 * i));
+        }
+        v
+    }
+
+    pub(crate) fn offsets(&self) -> [f64; 3] {
+        let mut v = [0.0; 3];
+        for (i, o) in v.iter_mut().enumerate() {
+            *o = f64::from_le_bytes(self.get(OFFSETS_OFFSET + 8 * i));
+        }
+        v
+    }
+
+    /// Returns the bounds, as min x y z and max x y z
+    pub(crate) fn bounds(&self) -> ([f64; 3], [f64; 3]) {
+        let mut min = [0.0; 3];
+        let mut max = [0.0; 3];
+        for (i, (min, max)) in min.iter_mut().zip(max.iter_mut()).enumerate() {
+            *max = f64::from_le_bytes(self.get(BOUNDS_OFFSET + 16 * i));
+            *min = f64::from_le_bytes(self.get(BOUNDS_OFFSET + 16 * i + 8));
+        }
+        (min, max)
+    }
+
+    pub(crate) fn set_bounds(&mut self, min: [f64; 3], max: [f64; 3]) {
+        for i in 0..3 {
+            self.set(BOUNDS_OFFSET + 16 * i, &max[i].to_le_bytes());
+            self.set(BOUNDS_OFFSET + 16 * i + 8, &min[i].to_le_bytes());
+        }
+    }
+
+    pub(crate) fn start_of_waveform_data(&self) -> Option<u64> {
+        if self.has(START_OF_WAVEFORM_OFFSET, 8) {
+            Some(u64::from_le_bytes(self.get(START_OF_WAVEFORM_OFFSET)))
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn set_start_of_waveform_data(&mut self, start: u64) {
+        if self.has(START_OF_WAVEFORM_OFFSET, 8) {
+            self.set(START_OF_WAVEFORM_OFFSET, &start.to_le_bytes());
+        }
+    }
+
+    /// Returns where the EVLRs start and their number, if the header has these fields
+    pub(crate) fn evlrs(&self) -> Option<(u64, u32)> {
+        if self.has(NUMBER_OF_EVLRS_OFFSET, 4) {
+            Some((
+                u64::from_le_bytes(self.get(START_OF_FIRST_EVLR_OFFSET)),
+                u32::from_le_bytes(self.get(NUMBER_OF_EVLRS_OFFSET)),
+            ))
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn set_evlrs(&mut self, start: u64, count: u32) {
+        if self.has(NUMBER_OF_EVLRS_OFFSET, 4) {
+            self.set(START_OF_FIRST_EVLR_OFFSET, &start.to_le_bytes());
+            self.set(NUMBER_OF_EVLRS_OFFSET, &count.to_le_bytes());
+        }
+    }
+}
+
+/// A VLR, or EVLR when `extended` is true
+#[derive(Clone, Debug)]
+pub(crate) struct Vlr {
+    pub(crate) reserved: u16,
+    pub(crate) user_id: [u8; 16],
+    pub(crate) record_id: u16,
+    pub(crate) description: [u8; 32],
+    pub(crate) data: Vec<u8>,
+    pub(crate) extended: bool,
+}
+
+impl Vlr {
+    pub(crate) fn new(user_id: &str, record_id: u16, description: &str, data: Vec<u8>) -> Self {
+        let mut vlr = Self {
+            reserved: 0,
+            user_id: [0; 16],
+            record_id,
+            description: [0; 32],
+            data,
+            extended: false,
+        };
+        let n = user_id.len().min(16);
+        vlr.user_id[..n].copy_from_slice(&user_id.as_bytes()[..n]);
+        let n = description.len().min(32);
+        vlr.description[..n].copy_from_slice(&description.as_bytes()[..n]);
+        vlr
+    }
+
+    pub(crate) fn read_from<R: Read>(src: &mut R, extended: bool) -> std::io::Result<Self> {
+        let mut buf = [0u8; 2];
+        src.read_exact(&mut buf)?;
+        let reserved = u16::from_le_bytes(buf);
+        let mut user_id = [0u8; 16];
+        src.read_exact(&mut user_id)?;
+        src.read_exact(&mut buf)?;
+        let record_id = u16::from_le_bytes(buf);
+        let record_length = if extended {
+            let mut len = [0u8; 8];
+            src.read_exact(&mut len)?;
+            u64::from_le_bytes(len)
+        } else {
+            src.read_exact(&mut buf)?;
+            u64::from(u16::from_le_bytes(buf))
+        };
+        let mut description = [0u8; 32];
+        src.read_exact(&mut description)?;
+        let mut data = Vec::<u8>::new();
+        src.take(record_length).read_to_end(&mut data)?;
+        if data.len() as u64 != record_length {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(Self {
+            reserved,
+            user_id,
+            record_id,
+            description,
+            data,
+            extended,
+        })
+    }
+
+    pub(crate) fn write_to<W: Write>(&self, dst: &mut W) -> std::io::Result<()> {
+        dst.write_all(&self.reserved.to_le_bytes())?;
+        dst.write_all(&self.user_id)?;
+        dst.write_all(&self.record_id.to_le_bytes())?;
+        if self.extended {
+            dst.write_all(&(self.data.len() as u64).to_le_bytes())?;
+        } else {
+            let len: u16 = self
+                .data
+                .len()
+                .try_into()
+                .map_err(|_| invalid_data("VLR data is too large"))?;
+            dst.write_all(&len.to_le_bytes())?;
+        }
+        dst.write_all(&self.description)?;
+        dst.write_all(&self.data)
+    }
+
+    /// Size of the record, header included
+    pub(crate) fn size(&self) -> u64 {
+        let header_size = if self.extended { 60 } else { 54 };
+        header_size + self.data.len() as u64
+    }
+
+    pub(crate) fn is(&self, user_id: &str, record_id: u16) -> bool {
+        let len = self.user_id.iter().position(|c| *c == 0).unwrap_or(16);
+        self.record_id == record_id && &self.user_id[..len] == user_id.as_bytes()
+    }
+
+    pub(crate) fn is_laszip(&self) -> bool {
+        self.is(LASZIP_USER_ID, LASZIP_RECORD_ID)
+    }
+}
+
+/// Everything in a LAS file but the points
+#[derive(Clone, Debug)]
+pub(crate) struct LasMetadata {
+    pub(crate) header: Header,
+    pub(crate) vlrs: Vec<Vlr>,
+    /// Bytes between the last VLR and the points
+    pub(crate) padding: Vec<u8>,
+    pub(crate) evlrs: Vec<Vlr>,
+}
+
+impl LasMetadata {
+    /// Reads the metadata, the source is left at the start of the points
+    pub(crate) fn read_from<R: Read + Seek>(src: &mut R) -> std::io::Result<Self> {
+        let start = src.stream_position()?;
+        let header = Header::read_from(src)?;
+        let mut vlrs = Vec::with_capacity(header.number_of_vlrs() as usize);
+        for _ in 0..header.number_of_vlrs() {
+            vlrs.push(Vlr::read_from(src, false)?);
+        }
+        let vlrs_end = src.stream_position()? - start;
+        let padding_size = u64::from(header.offset_to_point_data())
+            .checked_sub(vlrs_end)
+            .ok_or_else(|| invalid_data("VLRs overlap the points"))?;
+        let mut padding = vec![0u8; padding_size as usize];
+        src.read_exact(&mut padding)?;
+
+        let mut evlrs = vec![];
+        if let Some((evlrs_start, count)) = header.evlrs() {
+            if count > 0 {
+                src.seek(SeekFrom::Start(start + evlrs_start))?;
+                for _ in 0..count {
+                    evlrs.push(Vlr::read_from(src, true)?);
+                }
+            }
+        }
+        src.seek(SeekFrom::Start(
+            start + u64::from(header.offset_to_point_data()),
+        ))?;
+        Ok(Self {
+            header,
+            vlrs,
+            padding,
+            evlrs,
+        })
+    }
+
+    /// Returns the LASzip VLR, if any
+    pub(crate) fn laszip_vlr(&self) -> Option<laz::Result<laz::LazVlr>> {
+        self.vlrs
+            .iter()
+            .find(|vlr| vlr.is_laszip())
+            .map(|vlr| laz::LazVlr::from_buffer(&vlr.data))
+    }
+
+    /// Replaces the LASzip VLR, or removes it if `laz_vlr` is `None`
+    pub(crate) fn set_laszip_vlr(&mut self, laz_vlr: Option<&laz::LazVlr>) -> std::io::Result<()> {
+        self.vlrs.retain(|vlr| !vlr.is_laszip());
+        if let Some(laz_vlr) = laz_vlr {
+            let mut data = vec![];
+            laz_vlr.write_to(&mut data)?;
+            self.vlrs.push(Vlr::new(
+                LASZIP_USER_ID,
+                LASZIP_RECORD_ID,
+                laz::LazVlr::DESCRIPTION,
+                data,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Writes the header, VLRs and padding, fixing the VLR count and offset to the points
+    ///
+    /// The header will have to be written again by `write_evlrs_to`
+    /// once the points are written.
+    pub(crate) fn write_head_to<W: Write>(&mut self, dst: &mut W) -> std::io::Result<()> {
+        let offset_to_point_data = u64::from(self.header.header_size())
+            + self.vlrs.iter().map(Vlr::size).sum::<u64>()
+            + self.padding.len() as u64;
+        self.header.set_offset_to_point_data(
+            offset_to_point_data
+                .try_into()
+                .map_err(|_| invalid_data("VLRs are too large"))?,
+        );
+        self.header.set_number_of_vlrs(self.vlrs.len() as u32);
+        self.header.write_to(dst)?;
+        for vlr in &self.vlrs {
+            vlr.write_to(dst)?;
+        }
+        dst.write_all(&self.padding)
+    }
+
+    /// Writes the EVLRs after the points, and rewrites the header with their position.
+    ///
+    /// `start` is where the file starts in `dst`, which must be positioned right after the points.
+    pub(crate) fn write_evlrs_to<W: Write + Seek>(
+        &mut self,
+        dst: &mut W,
+        start: u64,
+    ) -> std::io::Result<()> {
+        let evlrs_start = dst.stream_position()? - start;
+        if self.header.evlrs().is_some() {
+            // An EVLR may hold the waveform data, which has to follow it
+            if let Some((old_start, _)) = self.header.evlrs() {
+                if let Some(waveform_start) = self.header.start_of_waveform_data() {
+                    if waveform_start >= old_start && old_start != 0 && !self.evlrs.is_empty() {
+                        let shift = waveform_start - old_start;
+                        self.header.set_start_of_waveform_data(evlrs_start + shift);
+                    }
+                }
+            }
+            let start_field = if self.evlrs.is_empty() {
+                0
+            } else {
+                evlrs_start
+            };
+            self.header.set_evlrs(start_field, self.evlrs.len() as u32);
+        }
+        for evlr in &self.evlrs {
+            evlr.write_to(dst)?;
+        }
+        let end = dst.stream_position()?;
+        dst.seek(SeekFrom::Start(start))?;
+        self.header.write_to(dst)?;
+        dst.seek(SeekFrom::Start(end))?;
+        dst.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(version_minor: u8, header_size: usize) -> Header {
+        let mut raw = vec![0u8; header_size];
+        raw[..4].copy_from_slice(SIGNATURE);
+        raw[VERSION_MINOR_OFFSET - 1] = 1;
+        raw[VERSION_MINOR_OFFSET] = version_minor;
+        raw[HEADER_SIZE_OFFSET..HEADER_SIZE_OFFSET + 2]
+            .copy_from_slice(&(header_size as u16).to_le_bytes());
+        let mut header = Header { raw };
+        header.set_point_format(6, true);
+        header.set_point_size(30);
+        header
+    }
+
+    #[test]
+    fn upgrade_inserts_the_1_4_fields_before_the_user_bytes() {
+        let mut by_return = [0u64; 15];
+        by_return[..2].copy_from_slice(&[20, 5]);
+        // The user bytes of the second header are where the 1.4 fields would be
+        for size in [MIN_HEADER_SIZE + 4, HEADER_SIZE_1_4 + 4] {
+            let mut header = header(2, size);
+            header.set_point_format(1, false);
+            header.set_number_of_points(25, &by_return);
+            header.raw[size - 4..].copy_from_slice(b"user");
+            assert_eq!(header.evlrs(), None);
+
+            header.upgrade_to_1_4().unwrap();
+            let upgraded_size = size + HEADER_SIZE_1_4 - MIN_HEADER_SIZE;
+            assert_eq!(header.raw[VERSION_MINOR_OFFSET], 4);
+            assert_eq!(header.raw.len(), upgraded_size);
+            assert_eq!(usize::from(header.header_size()), upgraded_size);
+            assert_eq!(&header.raw[upgraded_size - 4..], b"user");
+            assert_eq!(header.start_of_waveform_data(), Some(0));
+            assert_eq!(header.evlrs(), Some((0, 0)));
+            assert_eq!(header.number_of_points(), 25);
+            assert_eq!(header.number_of_points_by_return(), by_return);
+        }
+    }
+
+    #[test]
+    fn upgrade_keeps_the_1_3_fields() {
+        let mut header = header(3, HEADER_SIZE_1_3 + 4);
+        header.set_start_of_waveform_data(789);
+        header.raw[HEADER_SIZE_1_3..].copy_from_slice(b"user");
+
+        header.upgrade_to_1_4().unwrap();
+        assert_eq!(header.raw.len(), HEADER_SIZE_1_4 + 4);
+        assert_eq!(header.start_of_waveform_data(), Some(789));
+        assert_eq!(header.evlrs(), Some((0, 0)));
+        assert_eq!(&header.raw[HEADER_SIZE_1_4..], b"user");
+
+        // A LAS 1.4 header is left as is
+        let upgraded = header.raw.clone();
+        header.upgrade_to_1_4().unwrap();
+        assert_eq!(header.raw, upgraded);
+    }
+
+    #[test]
+    fn upgrade_of_a_header_too_large_fails() {
+        let mut header = header(2, usize::from(u16::MAX) - 4);
+        assert!(header.upgrade_to_1_4().is_err());
+        assert_eq!(header.raw[VERSION_MINOR_OFFSET], 2);
+    }
+
+    #[test]
+    fn the_1_4_fields_are_restored() {
+        let mut header = header(4, HEADER_SIZE_1_4);
+        header.set_start_of_waveform_data(1 << 40);
+        header.set_evlrs(1 << 33, 2);
+        let mut by_return = [0u64; 15];
+        by_return[9] = 5_000_000_000;
+        header.set_number_of_points(5_000_000_000, &by_return);
+        let original = header.raw.clone();
+
+        let fields = header.downgrade_to_1_2();
+        assert_eq!(fields.len(), HEADER_SIZE_1_4 - MIN_HEADER_SIZE);
+        assert_eq!(header.raw.len(), MIN_HEADER_SIZE);
+        assert_eq!(header.header_size(), MIN_HEADER_SIZE as u16);
+        assert_eq!(header.raw[VERSION_MINOR_OFFSET], 2);
+        assert_eq!(header.evlrs(), None);
+        assert_eq!(header.start_of_waveform_data(), None);
+
+        header.restore_1_4_fields(&fields);
+        assert_eq!(header.raw, original);
+        assert_eq!(header.number_of_points(), 5_000_000_000);
+        assert_eq!(header.number_of_points_by_return(), by_return);
+    }
+
+    #[test]
+    fn the_1_4_fields_replace_the_1_3_ones() {
+        let mut native = header(4, HEADER_SIZE_1_4);
+        native.set_start_of_waveform_data(123);
+        native.set_evlrs(456, 1);
+        let fields = native.clone().downgrade_to_1_2();
+
+        // Something that made the header a LAS 1.3 one, with its own waveform field
+        let mut header = header(3, START_OF_FIRST_EVLR_OFFSET);
+        header.set_start_of_waveform_data(789);
+        header.restore_1_4_fields(&fields);
+        assert_eq!(header.raw, native.raw);
+        assert_eq!(header.start_of_waveform_data(), Some(123));
+        assert_eq!(header.evlrs(), Some((456, 1)));
+    }
+
+    #[test]
+    fn bytes_after_the_header_fields_are_kept() {
+        let mut header = header(2, MIN_HEADER_SIZE + 4);
+        header.raw[MIN_HEADER_SIZE..].copy_from_slice(b"user");
+        let fields = vec![7u8; HEADER_SIZE_1_4 - MIN_HEADER_SIZE];
+        header.restore_1_4_fields(&fields);
+        assert_eq!(header.raw.len(), HEADER_SIZE_1_4 + 4);
+        assert_eq!(header.header_size(), HEADER_SIZE_1_4 as u16 + 4);
+        assert_eq!(&header.raw[MIN_HEADER_SIZE..HEADER_SIZE_1_4], &fields[..]);
+        assert_eq!(&header.raw[HEADER_SIZE_1_4..], b"user");
+    }
+}
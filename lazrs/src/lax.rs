@@ -0,0 +1,750 @@
+//! LAStools' LAX spatial indexes: a quadtree whose cells list the intervals
+//! of points they contain, stored in `.lax` files or in an EVLR.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+use crate::cancel::points_per_step;
+use crate::chunk_bounds::{Lazrs_ChunkBounds, Lazrs_ChunkBoundsTable};
+use crate::chunks::ChunkLayout;
+use crate::io::{CDest, CSource};
+use crate::las::LasMetadata;
+use crate::transcode::read_laz_metadata;
+use crate::{
+    Decompressor, Lazrs_Dest, Lazrs_DestType, Lazrs_Result, Lazrs_Source, Lazrs_SourceType,
+};
+
+/// User id of the EVLR holding a LAX index
+const LAX_USER_ID: &str = "LAStools";
+/// Record id of the EVLR holding a LAX index
+const LAX_RECORD_ID: u16 = 30;
+
+/// The only spatial structure of LAX files
+const QUADTREE_TYPE: u32 = 0;
+/// Deeper quadtrees would have cell indices that do not fit in an i32
+const MAX_LEVELS: u32 = 14;
+/// Number of points per leaf cell aimed at when the leaf size is chosen automatically
+const POINTS_PER_LEAF: f64 = 10_000.0;
+/// Intervals of a cell beyond this number are merged, the smallest gaps first
+const MAX_INTERVALS_PER_CELL: usize = 20;
+
+/// Options of `lazrs_lax_index_create`
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Lazrs_LaxIndexOptions {
+    /// Size of the smallest cells of the quadtree,
+    /// 0 to choose it from the bounds and the number of points
+    leaf_size: f32,
+    /// Whether to decompress using multiple threads, if possible
+    prefer_parallel: bool,
+}
+
+/// The quadtree, cells are split until `levels`
+#[derive(Copy, Clone, Debug)]
+struct QuadTree {
+    levels: u32,
+    level_index: u32,
+    implicit_levels: u32,
+    min_x: f32,
+    max_x: f32,
+    min_y: f32,
+    max_y: f32,
+}
+
+/// Index of the first cell of the level
+fn level_offset(level: u32) -> u64 {
+    ((1u64 << (2 * level)) - 1) / 3
+}
+
+impl QuadTree {
+    /// Covers the bounds with square leaves of `leaf_size`
+    fn new(min: [f64; 2], max: [f64; 2], leaf_size: f64) -> Self {
+        let extent = (max[0] - min[0]).max(max[1] - min[1]).max(0.0);
+        let leaf_size = leaf_size.max(extent / f64::from(1u32 << MAX_LEVELS));
+        let min_x = (min[0] / leaf_size).floor() * leaf_size;
+        let min_y = (min[1] / leaf_size).floor() * leaf_size;
+        let leaves_x = ((max[0] - min_x) / leaf_size).floor() + 1.0;
+        let leaves_y = ((max[1] - min_y) / leaf_size).floor() + 1.0;
+        let mut levels = 0;
+        while f64::from(1u32 << levels) < leaves_x.max(leaves_y) && levels < MAX_LEVELS {
+            levels += 1;
+        }
+        let num_leaves = f64::from(1u32 << levels);
+        // Centers the points in the tree
+        let min_x = min_x - ((num_leaves - leaves_x) / 2.0).floor() * leaf_size;
+        let min_y = min_y - ((num_leaves - leaves_y) / 2.0).floor() * leaf_size;
+        Self {
+            levels,
+            level_index: 0,
+            implicit_levels: 0,
+            min_x: min_x as f32,
+            max_x: (min_x + num_leaves * leaf_size) as f32,
+            min_y: min_y as f32,
+            max_y: (min_y + num_leaves * leaf_size) as f32,
+        }
+    }
+
+    /// Index of the leaf cell of a position, computed like LAStools does
+    fn cell_index(&self, x: f64, y: f64) -> i32 {
+        let (mut min_x, mut max_x) = (self.min_x, self.max_x);
+        let (mut min_y, mut max_y) = (self.min_y, self.max_y);
+        let mut index = 0u64;
+        for _ in 0..self.levels {
+            index <<= 2;
+            let mid_x = (min_x + max_x) / 2.0;
+            let mid_y = (min_y + max_y) / 2.0;
+            if x < f64::from(mid_x) {
+                max_x = mid_x;
+            } else {
+                min_x = mid_x;
+                index |= 1;
+            }
+            if y < f64::from(mid_y) {
+                max_y = mid_y;
+            } else {
+                min_y = mid_y;
+                index |= 2;
+            }
+        }
+        (level_offset(self.levels) + index) as i32
+    }
+
+    /// Bounds of a cell, of any level, as min x, max x, min y, max y.
+    ///
+    /// The sides on the border of the tree are unbounded,
+    /// as points outside of it are put in the closest cells.
+    fn cell_bounds(&self, cell_index: i32) -> [f64; 4] {
+        let cell_index = cell_index.max(0) as u64;
+        let mut level = 0;
+        while level < MAX_LEVELS + 1 && cell_index >= level_offset(level + 1) {
+            level += 1;
+        }
+        let index = cell_index - level_offset(level);
+        let (mut min_x, mut max_x) = (self.min_x, self.max_x);
+        let (mut min_y, mut max_y) = (self.min_y, self.max_y);
+        for l in (0..level).rev() {
+            let quadrant = (index >> (2 * l)) & 3;
+            let mid_x = (min_x + max_x) / 2.0;
+            let mid_y = (min_y + max_y) / 2.0;
+            if quadrant & 1 != 0 {
+                min_x = mid_x;
+            } else {
+                max_x = mid_x;
+            }
+            if quadrant & 2 != 0 {
+                min_y = mid_y;
+            } else {
+                max_y = mid_y;
+            }
+        }
+        let unbounded_min = |value: f32, border: f32| {
+            if value <= border {
+                f64::NEG_INFINITY
+            } else {
+                f64::from(value)
+            }
+        };
+        let unbounded_max = |value: f32, border: f32| {
+            if value >= border {
+                f64::INFINITY
+            } else {
+                f64::from(value)
+            }
+        };
+        [
+            unbounded_min(min_x, self.min_x),
+            unbounded_max(max_x, self.max_x),
+            unbounded_min(min_y, self.min_y),
+            unbounded_max(max_y, self.max_y),
+        ]
+    }
+}
+
+/// A cell of the quadtree and where its points are in the file
+#[derive(Clone, Debug)]
+struct Cell {
+    index: i32,
+    num_points: u32,
+    /// Indices of the first and last points of the intervals, both included
+    intervals: Vec<(u32, u32)>,
+}
+
+impl Cell {
+    fn add_point(&mut self, point_index: u32) {
+        match self.intervals.last_mut() {
+            Some((_, end)) if *end + 1 == point_index => *end = point_index,
+            _ => self.intervals.push((point_index, point_index)),
+        }
+        self.num_points += 1;
+    }
+
+    /// Merges the intervals separated by the smallest gaps,
+    /// so that there are at most `max` of them
+    fn merge_intervals(&mut self, max: usize) {
+        if self.intervals.len() <= max {
+            return;
+        }
+        let mut gaps: Vec<(u32, usize)> = self
+            .intervals
+            .windows(2)
+            .enumerate()
+            .map(|(i, pair)| (pair[1].0 - pair[0].1, i))
+            .collect();
+        gaps.sort_unstable_by(|a, b| b.cmp(a));
+        let mut kept: Vec<usize> = gaps[..max - 1].iter().map(|(_, i)| *i).collect();
+        kept.sort_unstable();
+
+        let mut merged = Vec::with_capacity(max);
+        let mut start = self.intervals[0].0;
+        for i in kept {
+            merged.push((start, self.intervals[i].1));
+            start = self.intervals[i + 1].0;
+        }
+        merged.push((start, self.intervals[self.intervals.len() - 1].1));
+        self.intervals = merged;
+    }
+}
+
+/// A LAX spatial index
+pub struct Lazrs_LaxIndex {
+    quadtree: QuadTree,
+    cells: Vec<Cell>,
+}
+
+fn invalid_lax() -> Lazrs_Result {
+    Lazrs_Result::LAZRS_OTHER
+}
+
+fn read_signature<R: Read>(src: &mut R, signature: &[u8; 4]) -> Result<(), Lazrs_Result> {
+    let mut bytes = [0u8; 4];
+    src.read_exact(&mut bytes)?;
+    if &bytes != signature {
+        return Err(invalid_lax());
+    }
+    Ok(())
+}
+
+fn read_u32<R: Read>(src: &mut R) -> Result<u32, Lazrs_Result> {
+    let mut bytes = [0u8; 4];
+    src.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_f32<R: Read>(src: &mut R) -> Result<f32, Lazrs_Result> {
+    read_u32(src).map(f32::from_bits)
+}
+
+impl Lazrs_LaxIndex {
+    fn read_from<R: Read>(src: &mut R) -> Result<Self, Lazrs_Result> {
+        read_signature(src, b"LASX")?;
+        let _version = read_u32(src)?;
+
+        read_signature(src, b"LASS")?;
+        if read_u32(src)? != QUADTREE_TYPE {
+            return Err(invalid_lax());
+        }
+        read_signature(src, b"LASQ")?;
+        let _version = read_u32(src)?;
+        let quadtree = QuadTree {
+            levels: read_u32(src)?,
+            level_index: read_u32(src)?,
+            implicit_levels: read_u32(src)?,
+            min_x: read_f32(src)?,
+            max_x: read_f32(src)?,
+            min_y: read_f32(src)?,
+            max_y: read_f32(src)?,
+        };
+
+        read_signature(src, b"LASV")?;
+        let _version = read_u32(src)?;
+        let num_cells = read_u32(src)?;
+        let mut cells = Vec::new();
+        for _ in 0..num_cells {
+            let index = read_u32(src)? as i32;
+            let num_intervals = read_u32(src)?;
+            let num_points = read_u32(src)?;
+            let mut intervals = Vec::new();
+            for _ in 0..num_intervals {
+                let start = read_u32(src)?;
+                let end = read_u32(src)?;
+                if end < start {
+                    return Err(invalid_lax());
+                }
+                intervals.push((start, end));
+            }
+            cells.push(Cell {
+                index,
+                num_points,
+                intervals,
+            });
+        }
+        Ok(Self { quadtree, cells })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"LASX");
+        data.extend_from_slice(&0u32.to_le_bytes());
+
+        let quadtree = &self.quadtree;
+        data.extend_from_slice(b"LASS");
+        data.extend_from_slice(&QUADTREE_TYPE.to_le_bytes());
+        data.extend_from_slice(b"LASQ");
+        data.extend_from_slice(&0u32.to_le_bytes());
+        for value in [
+            quadtree.levels,
+            quadtree.level_index,
+            quadtree.implicit_levels,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [
+            quadtree.min_x,
+            quadtree.max_x,
+            quadtree.min_y,
+            quadtree.max_y,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+
+        data.extend_from_slice(b"LASV");
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&(self.cells.len() as u32).to_le_bytes());
+        for cell in &self.cells {
+            data.extend_from_slice(&cell.index.to_le_bytes());
+            data.extend_from_slice(&(cell.intervals.len() as u32).to_le_bytes());
+            data.extend_from_slice(&cell.num_points.to_le_bytes());
+            for (start, end) in &cell.intervals {
+                data.extend_from_slice(&start.to_le_bytes());
+                data.extend_from_slice(&end.to_le_bytes());
+            }
+        }
+        data
+    }
+
+    /// Computes bounds of the chunks that contain all their points,
+    /// from the cells whose intervals overlap each chunk
+    fn chunk_bounds(&self, metadata: &LasMetadata, point_counts: &[u64]) -> Lazrs_ChunkBoundsTable {
+        let mut chunk_starts = Vec::with_capacity(point_counts.len());
+        let mut start = 0u64;
+        for count in point_counts {
+            chunk_starts.push(start);
+            start += count;
+        }
+
+        // min x, max x, min y, max y of the cells of each chunk, None if no cell has points in it
+        let mut extents: Vec<Option<[f64; 4]>> = vec![None; point_counts.len()];
+        for cell in &self.cells {
+            let cell_bounds = self.quadtree.cell_bounds(cell.index);
+            for &(first, last) in &cell.intervals {
+                let first_chunk = chunk_starts
+                    .partition_point(|&start| start <= u64::from(first))
+                    .saturating_sub(1);
+                for (extent, _) in extents[first_chunk..]
+                    .iter_mut()
+                    .zip(&chunk_starts[first_chunk..])
+                    .take_while(|(_, &start)| start <= u64::from(last))
+                {
+                    *extent = Some(match extent {
+                        Some(e) => [
+                            e[0].min(cell_bounds[0]),
+                            e[1].max(cell_bounds[1]),
+                            e[2].min(cell_bounds[2]),
+                            e[3].max(cell_bounds[3]),
+                        ],
+                        None => cell_bounds,
+                    });
+                }
+            }
+        }
+
+        let scales = metadata.header.scales();
+        let offsets = metadata.header.offsets();
+        // The range of stored coordinates whose scaled values are in min..=max
+        let stored = |i: usize, min: f64, max: f64| {
+            let (a, b) = (
+                (min - offsets[i]) / scales[i],
+                (max - offsets[i]) / scales[i],
+            );
+            (a.min(b).floor() as i32, a.max(b).ceil() as i32)
+        };
+        let chunks = point_counts
+            .iter()
+            .zip(extents)
+            .map(|(&point_count, extent)| {
+                // Chunks not covered by the index could be anywhere
+                let [min_x, max_x, min_y, max_y] = extent.unwrap_or([
+                    f64::NEG_INFINITY,
+                    f64::INFINITY,
+                    f64::NEG_INFINITY,
+                    f64::INFINITY,
+                ]);
+                let (min_x, max_x) = stored(0, min_x, max_x);
+                let (min_y, max_y) = stored(1, min_y, max_y);
+                Lazrs_ChunkBounds {
+                    point_count,
+                    min_x,
+                    min_y,
+                    min_z: i32::MIN,
+                    max_x,
+                    max_y,
+                    max_z: i32::MAX,
+                    min_gps_time: f64::NEG_INFINITY,
+                    max_gps_time: f64::INFINITY,
+                }
+            })
+            .collect();
+        Lazrs_ChunkBoundsTable { chunks }
+    }
+}
+
+fn create(
+    mut src: CSource<'static>,
+    options: &Lazrs_LaxIndexOptions,
+) -> Result<Lazrs_LaxIndex, Lazrs_Result> {
+    let (metadata, vlr) = read_laz_metadata(&mut src)?;
+    let num_points = metadata.header.number_of_points();
+    if num_points > u64::from(u32::MAX) {
+        return Err(Lazrs_Result::LAZRS_OTHER);
+    }
+    let scales = metadata.header.scales();
+    let offsets = metadata.header.offsets();
+    let (min, max) = metadata.header.bounds();
+    let leaf_size = if options.leaf_size > 0.0 {
+        f64::from(options.leaf_size)
+    } else {
+        let area = (max[0] - min[0]) * (max[1] - min[1]);
+        (area * POINTS_PER_LEAF / num_points.max(1) as f64).sqrt()
+    };
+    let leaf_size = if leaf_size.is_normal() {
+        leaf_size
+    } else {
+        1.0
+    };
+    let quadtree = QuadTree::new([min[0], min[1]], [max[0], max[1]], leaf_size);
+
+    let point_size = vlr.items_size() as usize;
+    let mut decompressor = Decompressor::new(src, vlr.clone(), options.prefer_parallel)?;
+    let step = points_per_step(&vlr, decompressor.is_parallel()) as u64;
+    let mut points = vec![0u8; step.min(num_points) as usize * point_size];
+    let mut cells: HashMap<i32, Cell> = HashMap::new();
+    let mut point_index = 0u32;
+    let mut remaining = num_points;
+    while remaining > 0 {
+        let n = step.min(remaining) as usize;
+        decompressor.decompress_many(&mut points[..n * point_size])?;
+        for point in points[..n * point_size].chunks_exact(point_size) {
+            let coordinate = |i: usize| {
+                let bytes = [
+                    point[4 * i],
+                    point[4 * i + 1],
+                    point[4 * i + 2],
+                    point[4 * i + 3],
+                ];
+                f64::from(i32::from_le_bytes(bytes)) * scales[i] + offsets[i]
+            };
+            let index = quadtree.cell_index(coordinate(0), coordinate(1));
+            cells
+                .entry(index)
+                .or_insert_with(|| Cell {
+                    index,
+                    num_points: 0,
+                    intervals: Vec::new(),
+                })
+                .add_point(point_index);
+            point_index = point_index.wrapping_add(1);
+        }
+        remaining -= n as u64;
+    }
+
+    let mut cells: Vec<Cell> = cells.into_values().collect();
+    cells.sort_unstable_by_key(|cell| cell.index);
+    for cell in &mut cells {
+        cell.merge_intervals(MAX_INTERVALS_PER_CELL);
+    }
+    Ok(Lazrs_LaxIndex { quadtree, cells })
+}
+
+fn write(index: &Lazrs_LaxIndex, mut dest: CDest) -> Result<(), Lazrs_Result> {
+    dest.write_all(&index.to_bytes())?;
+    dest.flush()?;
+    Ok(())
+}
+
+fn chunk_bounds(
+    index: &Lazrs_LaxIndex,
+    mut src: CSource<'static>,
+) -> Result<Lazrs_ChunkBoundsTable, Lazrs_Result> {
+    let (metadata, vlr) = read_laz_metadata(&mut src)?;
+    let layout = ChunkLayout::read_from(&mut src, &vlr)?;
+    let point_counts = layout.point_counts(&vlr, metadata.header.number_of_points());
+    Ok(index.chunk_bounds(&metadata, &point_counts))
+}
+
+/// Reads a LAX index, as stored in `.lax` files
+///
+/// The index is read from the current position of the source.
+/// LAZRS_OTHER is returned if the data is not a LAX index.
+///
+/// @source_type: type of the source
+/// @source: where the index is read from
+/// @index: will receive the index, or NULL if an error occurred,
+///         to be freed with `lazrs_lax_index_delete`
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_lax_index_read(
+    source_type: Lazrs_SourceType,
+    source: Lazrs_Source,
+    index: *mut *mut Lazrs_LaxIndex,
+) -> Lazrs_Result {
+    debug_assert!(!index.is_null());
+    *index = std::ptr::null_mut();
+    let mut src = match CSource::from_c_source(source_type, source) {
+        Ok(src) => src,
+        Err(result) => return result,
+    };
+    match Lazrs_LaxIndex::read_from(&mut src) {
+        Ok(lax) => {
+            *index = Box::into_raw(Box::new(lax));
+            Lazrs_Result::LAZRS_OK
+        }
+        Err(result) => result,
+    }
+}
+
+/// Reads the LAX index stored in the EVLR of a LAS or LAZ file
+///
+/// The file is read from the current position of the source.
+/// LAZRS_OTHER is returned if the file has no LAX EVLR, or if it is not valid.
+///
+/// @source_type: type of the source
+/// @source: where the file is read from
+/// @index: will receive the index, or NULL if an error occurred,
+///         to be freed with `lazrs_lax_index_delete`
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_lax_index_read_evlr(
+    source_type: Lazrs_SourceType,
+    source: Lazrs_Source,
+    index: *mut *mut Lazrs_LaxIndex,
+) -> Lazrs_Result {
+    debug_assert!(!index.is_null());
+    *index = std::ptr::null_mut();
+    let mut src = match CSource::from_c_source(source_type, source) {
+        Ok(src) => src,
+        Err(result) => return result,
+    };
+    let metadata = match LasMetadata::read_from(&mut src) {
+        Ok(metadata) => metadata,
+        Err(error) => return error.into(),
+    };
+    let evlr = match metadata
+        .evlrs
+        .iter()
+        .find(|evlr| evlr.is(LAX_USER_ID, LAX_RECORD_ID))
+    {
+        Some(evlr) => evlr,
+        None => return Lazrs_Result::LAZRS_OTHER,
+    };
+    match Lazrs_LaxIndex::read_from(&mut evlr.data.as_slice()) {
+        Ok(lax) => {
+            *index = Box::into_raw(Box::new(lax));
+            Lazrs_Result::LAZRS_OK
+        }
+        Err(result) => result,
+    }
+}
+
+/// Creates the LAX index of a LAZ file, by decompressing all its points
+///
+/// The quadtree covers the bounds of the header,
+/// and the cells list the intervals of the points they contain.
+/// The file is read from the current position of the source.
+/// LAZRS_OTHER is returned if the source is not compressed,
+/// or if it has more points than a LAX index can describe (2^32 - 1).
+///
+/// @source_type: type of the source
+/// @source: where the LAZ file is read from
+/// @options: how to create the index
+/// @index: will receive the index, or NULL if an error occurred,
+///         to be freed with `lazrs_lax_index_delete`
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_lax_index_create(
+    source_type: Lazrs_SourceType,
+    source: Lazrs_Source,
+    options: Lazrs_LaxIndexOptions,
+    index: *mut *mut Lazrs_LaxIndex,
+) -> Lazrs_Result {
+    debug_assert!(!index.is_null());
+    *index = std::ptr::null_mut();
+    let src = match CSource::from_c_source(source_type, source) {
+        Ok(src) => src,
+        Err(result) => return result,
+    };
+    match create(src, &options) {
+        Ok(lax) => {
+            *index = Box::into_raw(Box::new(lax));
+            Lazrs_Result::LAZRS_OK
+        }
+        Err(result) => result,
+    }
+}
+
+/// Writes the index, as stored in `.lax` files
+///
+/// @index: must not be NULL
+/// @dest_type: type of the destination
+/// @dest: where the index is written to
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_lax_index_write(
+    index: *const Lazrs_LaxIndex,
+    dest_type: Lazrs_DestType,
+    dest: Lazrs_Dest,
+) -> Lazrs_Result {
+    debug_assert!(!index.is_null());
+    let dest = match CDest::from_c_dest(dest_type, dest) {
+        Ok(dest) => dest,
+        Err(result) => return result,
+    };
+    match write(&*index, dest) {
+        Ok(()) => Lazrs_Result::LAZRS_OK,
+        Err(result) => result,
+    }
+}
+
+/// Maps the cells of the index onto the chunks of the LAZ file it indexes
+///
+/// The bounds of each chunk cover the cells having points in it,
+/// they can be given to `lazrs_query_bbox` to only decompress the chunks
+/// that may have points in the box. The index has no z nor GPS time,
+/// so their ranges cover all the values.
+/// The file is read from the current position of the source.
+/// LAZRS_OTHER is returned if the source is not compressed.
+///
+/// @index: must not be NULL
+/// @source_type: type of the source
+/// @source: where the LAZ file is read from
+/// @table: will receive the bounds, or NULL if an error occurred,
+///         to be freed with `lazrs_chunk_bounds_table_delete`
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_lax_index_chunk_bounds(
+    index: *const Lazrs_LaxIndex,
+    source_type: Lazrs_SourceType,
+    source: Lazrs_Source,
+    table: *mut *mut Lazrs_ChunkBoundsTable,
+) -> Lazrs_Result {
+    debug_assert!(!index.is_null());
+    debug_assert!(!table.is_null());
+    *table = std::ptr::null_mut();
+    let src = match CSource::from_c_source(source_type, source) {
+        Ok(src) => src,
+        Err(result) => return result,
+    };
+    match chunk_bounds(&*index, src) {
+        Ok(bounds) => {
+            *table = Box::into_raw(Box::new(bounds));
+            Lazrs_Result::LAZRS_OK
+        }
+        Err(result) => result,
+    }
+}
+
+/// Frees the index
+///
+/// @index can be NULL (no-op)
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_lax_index_delete(index: *mut Lazrs_LaxIndex) {
+    if !index.is_null() {
+        let _ = Box::from_raw(index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(index: i32, intervals: &[(u32, u32)]) -> Cell {
+        let mut cell = Cell {
+            index,
+            num_points: 0,
+            intervals: Vec::new(),
+        };
+        for &(start, end) in intervals {
+            for point_index in start..=end {
+                cell.add_point(point_index);
+            }
+        }
+        cell
+    }
+
+    #[test]
+    fn leaves_contain_their_positions() {
+        let quadtree = QuadTree::new([0.0, 0.0], [100.0, 50.0], 10.0);
+        assert_eq!(quadtree.levels, 4);
+        let first_leaf = level_offset(4) as i32;
+        let last_leaf = level_offset(5) as i32 - 1;
+        for &(x, y) in &[(0.0, 0.0), (100.0, 50.0), (55.5, 12.25), (99.9, 0.1)] {
+            let index = quadtree.cell_index(x, y);
+            assert!((first_leaf..=last_leaf).contains(&index));
+            let [min_x, max_x, min_y, max_y] = quadtree.cell_bounds(index);
+            assert!(min_x <= x && x < max_x, "{} not in {}..{}", x, min_x, max_x);
+            assert!(min_y <= y && y < max_y, "{} not in {}..{}", y, min_y, max_y);
+        }
+        assert_ne!(
+            quadtree.cell_index(0.0, 0.0),
+            quadtree.cell_index(100.0, 50.0)
+        );
+        assert_eq!(
+            quadtree.cell_bounds(0),
+            [
+                f64::NEG_INFINITY,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::INFINITY
+            ]
+        );
+    }
+
+    #[test]
+    fn consecutive_points_extend_the_last_interval() {
+        let mut cell = cell(0, &[(0, 2)]);
+        cell.add_point(5);
+        cell.add_point(6);
+        assert_eq!(cell.intervals, vec![(0, 2), (5, 6)]);
+        assert_eq!(cell.num_points, 5);
+    }
+
+    #[test]
+    fn intervals_separated_by_the_smallest_gaps_are_merged() {
+        let mut cell = cell(0, &[(0, 1), (5, 5), (7, 9), (20, 20), (22, 22)]);
+        cell.merge_intervals(5);
+        assert_eq!(cell.intervals.len(), 5);
+
+        cell.merge_intervals(3);
+        assert_eq!(cell.intervals, vec![(0, 1), (5, 9), (20, 22)]);
+        cell.merge_intervals(1);
+        assert_eq!(cell.intervals, vec![(0, 22)]);
+        assert_eq!(cell.num_points, 8);
+    }
+
+    #[test]
+    fn lax_data_is_read_back() {
+        let index = Lazrs_LaxIndex {
+            quadtree: QuadTree::new([-10.0, 3.0], [250.0, 40.0], 25.0),
+            cells: vec![cell(85, &[(0, 3), (10, 10)]), cell(90, &[(4, 9)])],
+        };
+        let data = index.to_bytes();
+        let read = Lazrs_LaxIndex::read_from(&mut data.as_slice()).unwrap();
+        assert_eq!(read.to_bytes(), data);
+        assert_eq!(read.cells[1].intervals, vec![(4, 9)]);
+        assert_eq!(read.quadtree.levels, index.quadtree.levels);
+
+        assert!(Lazrs_LaxIndex::read_from(&mut &data[..data.len() - 1]).is_err());
+        let mut bad_signature = data.clone();
+        bad_signature[3] = b'Y';
+        assert!(Lazrs_LaxIndex::read_from(&mut bad_signature.as_slice()).is_err());
+        // The last interval ends before it starts
+        let mut reversed = data;
+        let len = reversed.len();
+        reversed[len - 4..].copy_from_slice(&3u32.to_le_bytes());
+        assert!(Lazrs_LaxIndex::read_from(&mut reversed.as_slice()).is_err());
+    }
+}
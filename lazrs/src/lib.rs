@@ -1,18 +1,53 @@
 #![allow(non_snake_case, non_camel_case_types, non_upper_case_globals)]
-
+#![allow(clippy::missing_safety_doc)]
+
+mod cancel;
+mod capabilities;
+mod chunk_bounds;
+mod chunk_table;
+mod chunks;
+mod columns;
+mod compatibility;
+mod copc;
+mod extra_bytes;
+mod filter;
 mod io;
+mod las;
+mod lax;
+mod point;
+mod point_stats;
+mod query;
+mod recover;
+mod thread_pool;
+mod transcode;
+mod validate;
+mod vlr;
+mod waveform;
 
 use laz::LasZipError;
-use libc;
 use std::convert::TryInto;
 use std::ffi::CString;
 use std::io::{Seek, SeekFrom};
 use std::panic::catch_unwind;
 use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 
+use crate::cancel::{points_per_step, run_in_steps, Lazrs_CancelToken};
+use crate::chunk_bounds::ChunkBoundsTracker;
 use crate::io::{CSource, CustomDest, CustomSource};
+use crate::point_stats::PointStatsTracker;
+#[cfg(feature = "parallel")]
+use crate::thread_pool::Lazrs_ThreadPool;
+use crate::thread_pool::PoolSlot;
+use crate::vlr::{laz_vlr_to_c, Lazrs_LazVlr};
 use crate::Lazrs_Result::LAZRS_IO_ERROR;
-use io::{CDest, CFile};
+use io::CDest;
+
+// The variants of these enums are only created on the C side,
+// exporting them keeps them from being seen as dead code
+pub use crate::columns::Lazrs_Field;
+pub use crate::filter::Lazrs_ReturnFilter;
 
 // enum LastError {
 //     Laz(laz::LasZipError),
@@ -35,6 +70,9 @@ pub enum Lazrs_Result {
     LAZRS_IO_ERROR,
     LAZRS_MISSING_CHUNK_TABLE,
     LAZRS_OTHER,
+    /// The operation was stopped because its cancel token was triggered,
+    /// the `_position` function of the (de)compressor tells how far it went
+    LAZRS_CANCELLED,
 }
 
 #[no_mangle]
@@ -73,6 +111,12 @@ impl From<Result<(), laz::LasZipError>> for Lazrs_Result {
     }
 }
 
+impl From<std::io::Error> for Lazrs_Result {
+    fn from(_: std::io::Error) -> Self {
+        Lazrs_Result::LAZRS_IO_ERROR
+    }
+}
+
 impl From<std::io::Result<()>> for Lazrs_Result {
     fn from(r: std::io::Result<()>) -> Self {
         match r {
@@ -124,7 +168,12 @@ pub struct Lazrs_DecompressorParams {
 }
 
 /// A single-threaded sequential
-pub struct Lazrs_SeqLasZipDecompressor(laz::LasZipDecompressor<'static, CSource<'static>>);
+pub struct Lazrs_SeqLasZipDecompressor {
+    decompressor: laz::LasZipDecompressor<'static, CSource<'static>>,
+    cancel_token: Option<Arc<AtomicBool>>,
+    /// Number of points decompressed so far
+    position: u64,
+}
 
 /// Creates a new sequential that decompresses data from the given file
 ///
@@ -138,8 +187,7 @@ pub unsafe extern "C" fn lazrs_seq_laszip_decompressor_new(
     debug_assert!(!decompressor.is_null());
 
     let call_result = panic::catch_unwind(AssertUnwindSafe(|| {
-        let vlr_data =
-            std::slice::from_raw_parts(params.laszip_vlr.data, usize::from(params.laszip_vlr.len));
+        let vlr_data = std::slice::from_raw_parts(params.laszip_vlr.data, params.laszip_vlr.len);
         let vlr = match laz::LazVlr::from_buffer(vlr_data) {
             Ok(vlr) => vlr,
             Err(error) => {
@@ -158,7 +206,11 @@ pub unsafe extern "C" fn lazrs_seq_laszip_decompressor_new(
 
         match laz::LasZipDecompressor::new(csource, vlr) {
             Ok(d) => {
-                *decompressor = Box::into_raw(Box::new(Lazrs_SeqLasZipDecompressor(d)));
+                *decompressor = Box::into_raw(Box::new(Lazrs_SeqLasZipDecompressor {
+                    decompressor: d,
+                    cancel_token: None,
+                    position: 0,
+                }));
                 Lazrs_Result::LAZRS_OK
             }
             Err(error) => {
@@ -203,7 +255,11 @@ pub unsafe extern "C" fn lazrs_seq_laszip_decompressor_decompress_one(
     let mut decompressor = AssertUnwindSafe(&mut *decompressor);
     let call_result = catch_unwind(move || -> Lazrs_Result {
         let buf = std::slice::from_raw_parts_mut(out, len);
-        (*decompressor).0.decompress_one(buf).into()
+        let result = decompressor.decompressor.decompress_one(buf).into();
+        if result == Lazrs_Result::LAZRS_OK {
+            decompressor.position += 1;
+        }
+        result
     });
     match call_result {
         Ok(r) => r,
@@ -228,7 +284,21 @@ pub unsafe extern "C" fn lazrs_seq_laszip_decompressor_decompress_many(
 
     let call_result = catch_unwind(move || -> Lazrs_Result {
         let buf = std::slice::from_raw_parts_mut(out, len);
-        (*decompressor).0.decompress_many(buf).into()
+        let Lazrs_SeqLasZipDecompressor {
+            decompressor,
+            cancel_token,
+            position,
+        } = &mut **decompressor;
+        let point_size = decompressor.vlr().items_size() as usize;
+        let step = points_per_step(decompressor.vlr(), false) * point_size;
+        run_in_steps(len, step, cancel_token.as_ref(), |range| {
+            let points = &mut buf[range];
+            let result = decompressor.decompress_many(points).into();
+            if result == Lazrs_Result::LAZRS_OK {
+                *position += (points.len() / point_size) as u64;
+            }
+            result
+        })
     });
 
     match call_result {
@@ -237,11 +307,49 @@ pub unsafe extern "C" fn lazrs_seq_laszip_decompressor_decompress_many(
     }
 }
 
+/// Attaches a cancel token to the decompressor.
+///
+/// The token is checked between each chunk decompressed by
+/// `lazrs_seq_laszip_decompressor_decompress_many`.
+///
+/// @decompressor: the decompressor, must not be NULL
+/// @token: the token, NULL detaches the current one
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_seq_laszip_decompressor_set_cancel_token(
+    decompressor: *mut Lazrs_SeqLasZipDecompressor,
+    token: *const Lazrs_CancelToken,
+) {
+    debug_assert!(!decompressor.is_null());
+    (*decompressor).cancel_token = token.as_ref().map(Lazrs_CancelToken::flag);
+}
+
+/// Returns the number of points decompressed so far
+///
+/// When `lazrs_seq_laszip_decompressor_decompress_many` is cancelled, the points
+/// decompressed by the call are at the start of its output buffer, there are as many
+/// as the position moved during the call.
+///
+/// @decompressor: the decompressor, must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_seq_laszip_decompressor_position(
+    decompressor: *const Lazrs_SeqLasZipDecompressor,
+) -> u64 {
+    debug_assert!(!decompressor.is_null());
+    (*decompressor).position
+}
+
 //==================================================================================================
 
 /// A multi-threaded decompressor
 #[cfg(feature = "parallel")]
-pub struct Lazrs_ParLasZipDecompressor(laz::ParLasZipDecompressor<CSource<'static>>);
+pub struct Lazrs_ParLasZipDecompressor {
+    decompressor: laz::ParLasZipDecompressor<CSource<'static>>,
+    vlr: laz::LazVlr,
+    cancel_token: Option<Arc<AtomicBool>>,
+    thread_pool: PoolSlot,
+    /// Number of points decompressed so far
+    position: u64,
+}
 
 /// Creates a new sequential that decompresses data from the given file
 ///
@@ -255,8 +363,7 @@ pub unsafe extern "C" fn lazrs_par_laszip_decompressor_new(
 ) -> Lazrs_Result {
     debug_assert!(!decompressor.is_null());
 
-    let vlr_data =
-        std::slice::from_raw_parts(params.laszip_vlr.data, usize::from(params.laszip_vlr.len));
+    let vlr_data = std::slice::from_raw_parts(params.laszip_vlr.data, params.laszip_vlr.len);
     let vlr = match laz::LazVlr::from_buffer(vlr_data) {
         Ok(vlr) => vlr,
         Err(error) => {
@@ -272,9 +379,15 @@ pub unsafe extern "C" fn lazrs_par_laszip_decompressor_new(
     if let Err(_error) = csource.seek(SeekFrom::Start(params.source_offset)) {
         return LAZRS_IO_ERROR;
     }
-    match laz::ParLasZipDecompressor::new(csource, vlr) {
+    match laz::ParLasZipDecompressor::new(csource, vlr.clone()) {
         Ok(d) => {
-            *decompressor = Box::into_raw(Box::new(Lazrs_ParLasZipDecompressor(d)));
+            *decompressor = Box::into_raw(Box::new(Lazrs_ParLasZipDecompressor {
+                decompressor: d,
+                vlr,
+                cancel_token: None,
+                thread_pool: PoolSlot::default(),
+                position: 0,
+            }));
             Lazrs_Result::LAZRS_OK
         }
         Err(error) => {
@@ -312,19 +425,139 @@ pub unsafe extern "C" fn lazrs_par_laszip_decompressor_decompress_many(
     debug_assert!(!decompressor.is_null());
     debug_assert!(!out.is_null());
     let buf = std::slice::from_raw_parts_mut(out, len);
-    (*decompressor).0.decompress_many(buf).into()
+    let Lazrs_ParLasZipDecompressor {
+        decompressor,
+        vlr,
+        cancel_token,
+        thread_pool,
+        position,
+    } = &mut *decompressor;
+    thread_pool.install(|| {
+        let point_size = vlr.items_size() as usize;
+        let step = points_per_step(vlr, true) * point_size;
+        run_in_steps(len, step, cancel_token.as_ref(), |range| {
+            let points = &mut buf[range];
+            let result = decompressor.decompress_many(points).into();
+            if result == Lazrs_Result::LAZRS_OK {
+                *position += (points.len() / point_size) as u64;
+            }
+            result
+        })
+    })
+}
+
+/// Makes the decompressor run on the given pool instead of the global one.
+///
+/// @decompressor: the decompressor, must not be NULL
+/// @pool: the pool, NULL goes back to using the global pool
+#[cfg(feature = "parallel")]
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_par_laszip_decompressor_set_thread_pool(
+    decompressor: *mut Lazrs_ParLasZipDecompressor,
+    pool: *const Lazrs_ThreadPool,
+) {
+    debug_assert!(!decompressor.is_null());
+    (*decompressor).thread_pool.set(pool.as_ref());
+}
+
+/// Attaches a cancel token to the decompressor.
+///
+/// The token is checked between each batch of chunks decompressed by
+/// `lazrs_par_laszip_decompressor_decompress_many`.
+///
+/// @decompressor: the decompressor, must not be NULL
+/// @token: the token, NULL detaches the current one
+#[cfg(feature = "parallel")]
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_par_laszip_decompressor_set_cancel_token(
+    decompressor: *mut Lazrs_ParLasZipDecompressor,
+    token: *const Lazrs_CancelToken,
+) {
+    debug_assert!(!decompressor.is_null());
+    (*decompressor).cancel_token = token.as_ref().map(Lazrs_CancelToken::flag);
+}
+
+/// Returns the number of points decompressed so far
+///
+/// When `lazrs_par_laszip_decompressor_decompress_many` is cancelled, the points
+/// decompressed by the call are at the start of its output buffer, there are as many
+/// as the position moved during the call.
+///
+/// @decompressor: the decompressor, must not be NULL
+#[cfg(feature = "parallel")]
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_par_laszip_decompressor_position(
+    decompressor: *const Lazrs_ParLasZipDecompressor,
+) -> u64 {
+    debug_assert!(!decompressor.is_null());
+    (*decompressor).position
 }
 
 //==================================================================================================
 
+/// The decompressor actually used by a `Lazrs_LasZipDecompressor`
+pub(crate) enum Decompressor {
+    sequential(laz::LasZipDecompressor<'static, CSource<'static>>),
+    #[cfg(feature = "parallel")]
+    parallel(laz::ParLasZipDecompressor<CSource<'static>>),
+}
+
+impl Decompressor {
+    /// Creates the decompressor, multi-threaded if possible and preferred,
+    /// `source` must be positioned at the start of the point data
+    pub(crate) fn new(
+        source: CSource<'static>,
+        vlr: laz::LazVlr,
+        prefer_parallel: bool,
+    ) -> laz::Result<Self> {
+        #[cfg(feature = "parallel")]
+        if prefer_parallel {
+            return laz::ParLasZipDecompressor::new(source, vlr).map(Decompressor::parallel);
+        }
+        let _ = prefer_parallel;
+        laz::LasZipDecompressor::new(source, vlr).map(Decompressor::sequential)
+    }
+
+    pub(crate) fn is_parallel(&self) -> bool {
+        match self {
+            #[cfg(feature = "parallel")]
+            Decompressor::parallel(_) => true,
+            Decompressor::sequential(_) => false,
+        }
+    }
+
+    pub(crate) fn decompress_many(&mut self, out: &mut [u8]) -> laz::Result<()> {
+        match self {
+            #[cfg(feature = "parallel")]
+            Decompressor::parallel(d) => d.decompress_many(out),
+            Decompressor::sequential(d) => d.decompress_many(out).map_err(Into::into),
+        }
+    }
+}
+
 /// A decompressor that can be either single or multi-threaded.
 ///
 /// The choice is done at creation time and cannot be changed midway through the
 /// decompression
-pub enum Lazrs_LasZipDecompressor {
-    sequential(laz::LasZipDecompressor<'static, CSource<'static>>),
-    #[cfg(feature = "parallel")]
-    parallel(laz::ParLasZipDecompressor<CSource<'static>>),
+pub struct Lazrs_LasZipDecompressor {
+    decompressor: Decompressor,
+    vlr: laz::LazVlr,
+    cancel_token: Option<Arc<AtomicBool>>,
+    thread_pool: PoolSlot,
+    /// Number of points decompressed so far
+    position: u64,
+}
+
+impl Lazrs_LasZipDecompressor {
+    fn new(decompressor: Decompressor, vlr: laz::LazVlr) -> Self {
+        Self {
+            decompressor,
+            vlr,
+            cancel_token: None,
+            thread_pool: PoolSlot::default(),
+            position: 0,
+        }
+    }
 }
 
 /// Creates a new sequential that decompresses data from the given file
@@ -339,8 +572,7 @@ pub unsafe extern "C" fn lazrs_decompressor_new(
 ) -> Lazrs_Result {
     debug_assert!(!decompressor.is_null());
 
-    let vlr_data =
-        std::slice::from_raw_parts(params.laszip_vlr.data, usize::from(params.laszip_vlr.len));
+    let vlr_data = std::slice::from_raw_parts(params.laszip_vlr.data, params.laszip_vlr.len);
     let vlr = match laz::LazVlr::from_buffer(vlr_data) {
         Ok(vlr) => vlr,
         Err(error) => {
@@ -356,45 +588,14 @@ pub unsafe extern "C" fn lazrs_decompressor_new(
     if let Err(_error) = csource.seek(SeekFrom::Start(params.source_offset)) {
         return LAZRS_IO_ERROR;
     }
-    #[cfg(feature = "parallel")]
-    {
-        if prefer_parallel {
-            match laz::ParLasZipDecompressor::new(csource, vlr) {
-                Ok(d) => {
-                    *decompressor = Box::into_raw(Box::new(Lazrs_LasZipDecompressor::parallel(d)));
-                    Lazrs_Result::LAZRS_OK
-                }
-                Err(error) => {
-                    *decompressor = std::ptr::null_mut::<Lazrs_LasZipDecompressor>();
-                    error.into()
-                }
-            }
-        } else {
-            match laz::LasZipDecompressor::new(csource, vlr) {
-                Ok(d) => {
-                    *decompressor =
-                        Box::into_raw(Box::new(Lazrs_LasZipDecompressor::sequential(d)));
-                    Lazrs_Result::LAZRS_OK
-                }
-                Err(error) => {
-                    *decompressor = std::ptr::null_mut::<Lazrs_LasZipDecompressor>();
-                    error.into()
-                }
-            }
+    match Decompressor::new(csource, vlr.clone(), prefer_parallel) {
+        Ok(d) => {
+            *decompressor = Box::into_raw(Box::new(Lazrs_LasZipDecompressor::new(d, vlr)));
+            Lazrs_Result::LAZRS_OK
         }
-    }
-    #[cfg(not(feature = "parallel"))]
-    {
-        let _ = prefer_parallel;
-        match laz::LasZipDecompressor::new(csource, vlr) {
-            Ok(d) => {
-                *decompressor = Box::into_raw(Box::new(Lazrs_LasZipDecompressor::sequential(d)));
-                Lazrs_Result::LAZRS_OK
-            }
-            Err(error) => {
-                *decompressor = std::ptr::null_mut::<Lazrs_LasZipDecompressor>();
-                error.into()
-            }
+        Err(error) => {
+            *decompressor = std::ptr::null_mut::<Lazrs_LasZipDecompressor>();
+            error.into()
         }
     }
 }
@@ -409,6 +610,34 @@ pub unsafe extern "C" fn lazrs_decompressor_delete(decompressor: *mut Lazrs_LasZ
     }
 }
 
+/// Returns whether the decompressor is multi-threaded
+///
+/// A decompressor created with `prefer_parallel` is single-threaded
+/// if lazrs was not built with the `parallel` feature.
+///
+/// @decompressor: the decompressor, must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_decompressor_is_parallel(
+    decompressor: *const Lazrs_LasZipDecompressor,
+) -> bool {
+    debug_assert!(!decompressor.is_null());
+    (*decompressor).decompressor.is_parallel()
+}
+
+/// Gives a copy of the LASzip VLR the decompressor was created with
+///
+/// @decompressor: the decompressor, must not be NULL
+/// @vlr: will receive the vlr, to be freed with `lazrs_laz_vlr_delete`
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_decompressor_laz_vlr(
+    decompressor: *const Lazrs_LasZipDecompressor,
+    vlr: *mut *mut Lazrs_LazVlr,
+) -> Lazrs_Result {
+    debug_assert!(!decompressor.is_null());
+    debug_assert!(!vlr.is_null());
+    laz_vlr_to_c((*decompressor).vlr.clone(), vlr)
+}
+
 /// Decompresses one point from the input and write its LAS data to the out buffer
 ///
 /// @sequential: the sequential, must not be NULL
@@ -423,11 +652,21 @@ pub unsafe extern "C" fn lazrs_decompressor_decompress_one(
     debug_assert!(!decompressor.is_null());
     debug_assert!(!out.is_null());
     let buf = std::slice::from_raw_parts_mut(out, len);
-    match *decompressor {
+    let Lazrs_LasZipDecompressor {
+        decompressor,
+        thread_pool,
+        position,
+        ..
+    } = &mut *decompressor;
+    let result = thread_pool.install(|| match decompressor {
         #[cfg(feature = "parallel")]
-        Lazrs_LasZipDecompressor::parallel(ref mut d) => d.decompress_many(buf).into(),
-        Lazrs_LasZipDecompressor::sequential(ref mut d) => d.decompress_one(buf).into(),
+        Decompressor::parallel(ref mut d) => d.decompress_many(buf).into(),
+        Decompressor::sequential(ref mut d) => d.decompress_one(buf).into(),
+    });
+    if result == Lazrs_Result::LAZRS_OK {
+        *position += 1;
     }
+    result
 }
 
 /// Decompresses many (one or more) points from the input and write its LAS data to the out buffer
@@ -444,11 +683,72 @@ pub unsafe extern "C" fn lazrs_decompressor_decompress_many(
     debug_assert!(!decompressor.is_null());
     debug_assert!(!out.is_null());
     let buf = std::slice::from_raw_parts_mut(out, len);
-    match *decompressor {
-        #[cfg(feature = "parallel")]
-        Lazrs_LasZipDecompressor::parallel(ref mut d) => d.decompress_many(buf).into(),
-        Lazrs_LasZipDecompressor::sequential(ref mut d) => d.decompress_many(buf).into(),
-    }
+    let Lazrs_LasZipDecompressor {
+        decompressor,
+        vlr,
+        cancel_token,
+        thread_pool,
+        position,
+    } = &mut *decompressor;
+    thread_pool.install(|| {
+        let point_size = vlr.items_size() as usize;
+        let step = points_per_step(vlr, decompressor.is_parallel()) * point_size;
+        run_in_steps(len, step, cancel_token.as_ref(), |range| {
+            let points = &mut buf[range];
+            let result: Lazrs_Result = decompressor.decompress_many(points).into();
+            if result == Lazrs_Result::LAZRS_OK {
+                *position += (points.len() / point_size) as u64;
+            }
+            result
+        })
+    })
+}
+
+/// Attaches a cancel token to the decompressor.
+///
+/// The token is checked between each chunk (or batch of chunks when
+/// the decompressor is multi-threaded) decompressed by `lazrs_decompressor_decompress_many`.
+///
+/// @decompressor: the decompressor, must not be NULL
+/// @token: the token, NULL detaches the current one
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_decompressor_set_cancel_token(
+    decompressor: *mut Lazrs_LasZipDecompressor,
+    token: *const Lazrs_CancelToken,
+) {
+    debug_assert!(!decompressor.is_null());
+    (*decompressor).cancel_token = token.as_ref().map(Lazrs_CancelToken::flag);
+}
+
+/// Returns the number of points decompressed so far
+///
+/// When `lazrs_decompressor_decompress_many` is cancelled, the points
+/// decompressed by the call are at the start of its output buffer, there are as many
+/// as the position moved during the call.
+///
+/// @decompressor: the decompressor, must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_decompressor_position(
+    decompressor: *const Lazrs_LasZipDecompressor,
+) -> u64 {
+    debug_assert!(!decompressor.is_null());
+    (*decompressor).position
+}
+
+/// Makes the decompressor run on the given pool instead of the global one.
+///
+/// This has no effect if the decompressor is single-threaded.
+///
+/// @decompressor: the decompressor, must not be NULL
+/// @pool: the pool, NULL goes back to using the global pool
+#[cfg(feature = "parallel")]
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_decompressor_set_thread_pool(
+    decompressor: *mut Lazrs_LasZipDecompressor,
+    pool: *const Lazrs_ThreadPool,
+) {
+    debug_assert!(!decompressor.is_null());
+    (*decompressor).thread_pool.set(pool.as_ref());
 }
 
 //==================================================================================================
@@ -459,6 +759,8 @@ pub unsafe extern "C" fn lazrs_decompressor_decompress_many(
 pub enum Lazrs_DestType {
     LAZRS_DEST_CFILE,
     LAZRS_DEST_CUSTOM,
+    /// The destination is a filename that lazrs will create (or truncate)
+    LAZRS_DEST_FNAME,
 }
 
 /// Union of possible sources
@@ -480,6 +782,9 @@ pub struct Lazrs_CompressorParams {
 
 pub struct Lazrs_SeqLasZipCompressor {
     compressor: laz::LasZipCompressor<'static, CDest>,
+    cancel_token: Option<Arc<AtomicBool>>,
+    /// Number of points compressed so far
+    position: u64,
 }
 
 #[no_mangle]
@@ -500,14 +805,21 @@ pub unsafe extern "C" fn lazrs_seq_compressor_new_for_point_format(
     };
     let laz_vlr = laz::LazVlr::from_laz_items(items);
 
-    let dest = match params.dest_type {
-        Lazrs_DestType::LAZRS_DEST_CFILE => CDest::CFile(CFile::new_unchecked(params.dest.file)),
-        Lazrs_DestType::LAZRS_DEST_CUSTOM => CDest::Custom(params.dest.custom),
+    let dest = match CDest::from_c_dest(params.dest_type, params.dest) {
+        Ok(dest) => dest,
+        Err(result) => {
+            *c_compressor = std::ptr::null_mut::<Lazrs_SeqLasZipCompressor>();
+            return result;
+        }
     };
 
     match laz::LasZipCompressor::new(dest, laz_vlr) {
         Ok(compressor) => {
-            let compressor = Box::new(Lazrs_SeqLasZipCompressor { compressor });
+            let compressor = Box::new(Lazrs_SeqLasZipCompressor {
+                compressor,
+                cancel_token: None,
+                position: 0,
+            });
             *c_compressor = Box::into_raw(compressor);
             Lazrs_Result::LAZRS_OK
         }
@@ -528,7 +840,7 @@ pub unsafe extern "C" fn lazrs_seq_compressor_laszip_vlr_size(
     let mut data = Vec::<u8>::new();
     (*compressor).compressor.vlr().write_to(&mut data).unwrap();
 
-    return data.len().try_into().unwrap();
+    data.len().try_into().unwrap()
 }
 
 #[no_mangle]
@@ -563,7 +875,11 @@ pub unsafe extern "C" fn lazrs_seq_compressor_compress_one(
     debug_assert!(!compressor.is_null());
     debug_assert!(!data.is_null());
     let slice = std::slice::from_raw_parts(data, size);
-    (*compressor).compressor.compress_one(slice).into()
+    let result = (*compressor).compressor.compress_one(slice).into();
+    if result == Lazrs_Result::LAZRS_OK {
+        (*compressor).position += 1;
+    }
+    result
 }
 
 /// Compresses many points
@@ -576,7 +892,53 @@ pub unsafe extern "C" fn lazrs_seq_compressor_compress_many(
     debug_assert!(!compressor.is_null());
     debug_assert!(!data.is_null());
     let slice = std::slice::from_raw_parts(data, size);
-    (*compressor).compressor.compress_many(slice).into()
+    let Lazrs_SeqLasZipCompressor {
+        compressor,
+        cancel_token,
+        position,
+    } = &mut *compressor;
+    let point_size = compressor.vlr().items_size() as usize;
+    let step = points_per_step(compressor.vlr(), false) * point_size;
+    run_in_steps(size, step, cancel_token.as_ref(), |range| {
+        let points = &slice[range];
+        let result = compressor.compress_many(points).into();
+        if result == Lazrs_Result::LAZRS_OK {
+            *position += (points.len() / point_size) as u64;
+        }
+        result
+    })
+}
+
+/// Attaches a cancel token to the compressor.
+///
+/// The token is checked between each chunk compressed by `lazrs_seq_compressor_compress_many`.
+/// The points compressed before the cancellation are kept, calling
+/// `lazrs_seq_compressor_done` still produces valid LAZ data.
+///
+/// @compressor: the compressor, must not be NULL
+/// @token: the token, NULL detaches the current one
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_seq_compressor_set_cancel_token(
+    compressor: *mut Lazrs_SeqLasZipCompressor,
+    token: *const Lazrs_CancelToken,
+) {
+    debug_assert!(!compressor.is_null());
+    (*compressor).cancel_token = token.as_ref().map(Lazrs_CancelToken::flag);
+}
+
+/// Returns the number of points compressed so far
+///
+/// This is the number of points the LAZ data has once
+/// `lazrs_seq_compressor_done` is called, including when
+/// `lazrs_seq_compressor_compress_many` was cancelled.
+///
+/// @compressor: the compressor, must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_seq_compressor_position(
+    compressor: *const Lazrs_SeqLasZipCompressor,
+) -> u64 {
+    debug_assert!(!compressor.is_null());
+    (*compressor).position
 }
 
 /// Tells the compressor that is it done compressing points
@@ -602,14 +964,152 @@ pub unsafe extern "C" fn lazrs_seq_compressor_delete(compressor: *mut Lazrs_SeqL
 
 //==================================================================================================
 
+/// The compressor actually used by a `Lazrs_LasZipCompressor`
+pub(crate) enum Compressor {
+    sequential(laz::LasZipCompressor<'static, CDest>),
+    #[cfg(feature = "parallel")]
+    parallel(laz::ParLasZipCompressor<CDest>),
+}
+
+impl Compressor {
+    /// Creates the compressor, multi-threaded if possible and preferred
+    pub(crate) fn new(dest: CDest, vlr: laz::LazVlr, prefer_parallel: bool) -> laz::Result<Self> {
+        // The multi-threaded compressor cannot be fed points one by one with variable-size chunks
+        #[cfg(feature = "parallel")]
+        if prefer_parallel && !vlr.uses_variable_size_chunks() {
+            return laz::ParLasZipCompressor::new(dest, vlr).map(Compressor::parallel);
+        }
+        let _ = prefer_parallel;
+        laz::LasZipCompressor::new(dest, vlr).map(Compressor::sequential)
+    }
+
+    /// Creates a compressor that will be given whole chunks through `compress_chunks`,
+    /// so that variable-size chunks can also be compressed by multiple threads
+    pub(crate) fn new_for_chunks(
+        dest: CDest,
+        vlr: laz::LazVlr,
+        prefer_parallel: bool,
+    ) -> laz::Result<Self> {
+        #[cfg(feature = "parallel")]
+        if prefer_parallel {
+            return laz::ParLasZipCompressor::new(dest, vlr).map(Compressor::parallel);
+        }
+        let _ = prefer_parallel;
+        laz::LasZipCompressor::new(dest, vlr).map(Compressor::sequential)
+    }
+
+    pub(crate) fn vlr(&self) -> &laz::LazVlr {
+        match self {
+            Compressor::sequential(compressor) => compressor.vlr(),
+            #[cfg(feature = "parallel")]
+            Compressor::parallel(compressor) => compressor.vlr(),
+        }
+    }
+
+    pub(crate) fn is_parallel(&self) -> bool {
+        match self {
+            Compressor::sequential(_) => false,
+            #[cfg(feature = "parallel")]
+            Compressor::parallel(_) => true,
+        }
+    }
+
+    pub(crate) fn compress_many(&mut self, points: &[u8]) -> std::io::Result<()> {
+        match self {
+            Compressor::sequential(compressor) => compressor.compress_many(points),
+            #[cfg(feature = "parallel")]
+            Compressor::parallel(compressor) => compressor.compress_many(points),
+        }
+    }
+
+    /// Compresses each slice of points as one chunk, the vlr must use variable-size chunks
+    ///
+    /// `last` tells if these are the last chunks, the single-threaded compressor
+    /// leaves the last one for `done` to end, as it would otherwise add an empty chunk.
+    pub(crate) fn compress_chunks(
+        &mut self,
+        chunks: Vec<&[u8]>,
+        last: bool,
+    ) -> std::io::Result<()> {
+        match self {
+            Compressor::sequential(compressor) => {
+                let num_chunks = chunks.len();
+                for (i, chunk) in chunks.into_iter().enumerate() {
+                    compressor.compress_many(chunk)?;
+                    if !(last && i + 1 == num_chunks) {
+                        compressor.finish_current_chunk()?;
+                    }
+                }
+                Ok(())
+            }
+            #[cfg(feature = "parallel")]
+            Compressor::parallel(compressor) => compressor.compress_chunks(chunks),
+        }
+    }
+
+    pub(crate) fn done(&mut self) -> laz::Result<()> {
+        match self {
+            Compressor::sequential(compressor) => compressor.done().map_err(Into::into),
+            #[cfg(feature = "parallel")]
+            Compressor::parallel(compressor) => compressor.done(),
+        }
+    }
+
+    /// Returns the destination, positioned after the chunk table once `done` is called
+    pub(crate) fn into_inner(self) -> CDest {
+        match self {
+            Compressor::sequential(compressor) => compressor.into_inner(),
+            #[cfg(feature = "parallel")]
+            Compressor::parallel(compressor) => compressor.into_inner(),
+        }
+    }
+}
+
 /// A compressor that can be either single or multi-threaded.
 ///
 /// The choice is done at creation time and cannot be changed midway through the
 /// compression
-pub enum Lazrs_LasZipCompressor {
-    sequential(laz::LasZipCompressor<'static, CDest>),
-    #[cfg(feature = "parallel")]
-    parallel(laz::ParLasZipCompressor<CDest>),
+pub struct Lazrs_LasZipCompressor {
+    compressor: Compressor,
+    cancel_token: Option<Arc<AtomicBool>>,
+    thread_pool: PoolSlot,
+    /// Number of points compressed so far
+    position: u64,
+    chunk_bounds: Option<ChunkBoundsTracker>,
+    point_stats: Option<PointStatsTracker>,
+}
+
+impl Lazrs_LasZipCompressor {
+    fn new(compressor: Compressor) -> Self {
+        Self {
+            compressor,
+            cancel_token: None,
+            thread_pool: PoolSlot::default(),
+            position: 0,
+            chunk_bounds: None,
+            point_stats: None,
+        }
+    }
+}
+
+/// Creates the compressor, multi-threaded if possible and preferred
+unsafe fn new_compressor(
+    dest: CDest,
+    laz_vlr: laz::LazVlr,
+    prefer_parallel: bool,
+    c_compressor: *mut *mut Lazrs_LasZipCompressor,
+) -> Lazrs_Result {
+    match Compressor::new(dest, laz_vlr, prefer_parallel) {
+        Ok(compressor) => {
+            let compressor = Box::new(Lazrs_LasZipCompressor::new(compressor));
+            *c_compressor = Box::into_raw(compressor);
+            Lazrs_Result::LAZRS_OK
+        }
+        Err(error) => {
+            *c_compressor = std::ptr::null_mut::<Lazrs_LasZipCompressor>();
+            error.into()
+        }
+    }
 }
 
 #[no_mangle]
@@ -631,52 +1131,40 @@ pub unsafe extern "C" fn lazrs_compressor_new_for_point_format(
     };
     let laz_vlr = laz::LazVlr::from_laz_items(items);
 
-    let dest = match params.dest_type {
-        Lazrs_DestType::LAZRS_DEST_CFILE => CDest::CFile(CFile::new_unchecked(params.dest.file)),
-        Lazrs_DestType::LAZRS_DEST_CUSTOM => CDest::Custom(params.dest.custom),
+    let dest = match CDest::from_c_dest(params.dest_type, params.dest) {
+        Ok(dest) => dest,
+        Err(result) => return result,
     };
+    new_compressor(dest, laz_vlr, prefer_parallel, c_compressor)
+}
 
-    #[cfg(feature = "parallel")]
-    if prefer_parallel {
-        match laz::ParLasZipCompressor::new(dest, laz_vlr) {
-            Ok(compressor) => {
-                let compressor = Box::new(Lazrs_LasZipCompressor::parallel(compressor));
-                *c_compressor = Box::into_raw(compressor);
-                Lazrs_Result::LAZRS_OK
-            }
-            Err(error) => {
-                *c_compressor = std::ptr::null_mut::<Lazrs_LasZipCompressor>();
-                error.into()
-            }
-        }
-    } else {
-        match laz::LasZipCompressor::new(dest, laz_vlr) {
-            Ok(compressor) => {
-                let compressor = Box::new(Lazrs_LasZipCompressor::sequential(compressor));
-                *c_compressor = Box::into_raw(compressor);
-                Lazrs_Result::LAZRS_OK
-            }
-            Err(error) => {
-                *c_compressor = std::ptr::null_mut::<Lazrs_LasZipCompressor>();
-                error.into()
-            }
-        }
-    }
-    #[cfg(not(feature = "parallel"))]
-    {
-        let _ = prefer_parallel;
-        match laz::LasZipCompressor::new(dest, laz_vlr) {
-            Ok(compressor) => {
-                let compressor = Box::new(Lazrs_LasZipCompressor::sequential(compressor));
-                *c_compressor = Box::into_raw(compressor);
-                Lazrs_Result::LAZRS_OK
-            }
-            Err(error) => {
-                *c_compressor = std::ptr::null_mut::<Lazrs_LasZipCompressor>();
-                error.into()
-            }
-        }
+/// Creates a compressor that compresses points as described by the vlr
+///
+/// Compressors for vlrs with variable-size chunks are always single-threaded,
+/// use `lazrs_compressor_finish_current_chunk` to end the chunks.
+///
+/// @dest_type: type of the destination
+/// @dest: where the compressed points are written
+/// @vlr: the vlr, must not be NULL, it is copied
+/// @prefer_parallel: whether to create a multi-threaded compressor if possible
+/// @c_compressor: will receive the compressor, or NULL if an error occurred
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_compressor_new_from_laz_vlr(
+    dest_type: Lazrs_DestType,
+    dest: Lazrs_Dest,
+    vlr: *const Lazrs_LazVlr,
+    prefer_parallel: bool,
+    c_compressor: *mut *mut Lazrs_LasZipCompressor,
+) -> Lazrs_Result {
+    debug_assert!(!vlr.is_null());
+    if c_compressor.is_null() {
+        return Lazrs_Result::LAZRS_OTHER;
     }
+    let dest = match CDest::from_c_dest(dest_type, dest) {
+        Ok(dest) => dest,
+        Err(result) => return result,
+    };
+    new_compressor(dest, (*vlr).vlr.clone(), prefer_parallel, c_compressor)
 }
 
 #[no_mangle]
@@ -687,17 +1175,9 @@ pub unsafe extern "C" fn lazrs_compressor_laszip_vlr_size(
 
     // TODO we should have a data_len() function in laz-rs
     let mut data = Vec::<u8>::new();
-    match &*compressor {
-        Lazrs_LasZipCompressor::sequential(compressor) => {
-            compressor.vlr().write_to(&mut data).unwrap();
-        }
-        #[cfg(feature = "parallel")]
-        Lazrs_LasZipCompressor::parallel(compressor) => {
-            compressor.vlr().write_to(&mut data).unwrap();
-        }
-    }
+    (*compressor).compressor.vlr().write_to(&mut data).unwrap();
 
-    return data.len().try_into().unwrap();
+    data.len().try_into().unwrap()
 }
 
 #[no_mangle]
@@ -711,13 +1191,7 @@ pub unsafe extern "C" fn lazrs_compressor_laszip_vlr_data(
     // TODO having to create this temp data vec is a bit sub optimal
     // but slices don't impl Write
     let mut tmp = Vec::<u8>::new();
-    let r = match &*compressor {
-        Lazrs_LasZipCompressor::sequential(compressor) => {
-            compressor.vlr().write_to(&mut tmp).into()
-        }
-        #[cfg(feature = "parallel")]
-        Lazrs_LasZipCompressor::parallel(compressor) => compressor.vlr().write_to(&mut tmp).into(),
-    };
+    let r = (*compressor).compressor.vlr().write_to(&mut tmp).into();
 
     if r == Lazrs_Result::LAZRS_OK {
         std::slice::from_raw_parts_mut(data, size).copy_from_slice(tmp.as_slice());
@@ -726,6 +1200,20 @@ pub unsafe extern "C" fn lazrs_compressor_laszip_vlr_data(
     r
 }
 
+/// Gives a copy of the LASzip VLR of the compressor
+///
+/// @compressor: the compressor, must not be NULL
+/// @vlr: will receive the vlr, to be freed with `lazrs_laz_vlr_delete`
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_compressor_laz_vlr(
+    compressor: *const Lazrs_LasZipCompressor,
+    vlr: *mut *mut Lazrs_LazVlr,
+) -> Lazrs_Result {
+    debug_assert!(!compressor.is_null());
+    debug_assert!(!vlr.is_null());
+    laz_vlr_to_c((*compressor).compressor.vlr().clone(), vlr)
+}
+
 /// Compresses one point
 ///
 /// @compressor: the compressor, must not be NULL
@@ -740,11 +1228,29 @@ pub unsafe extern "C" fn lazrs_compressor_compress_one(
     debug_assert!(!compressor.is_null());
     debug_assert!(!data.is_null());
     let slice = std::slice::from_raw_parts(data, size);
-    match &mut *compressor {
-        Lazrs_LasZipCompressor::sequential(compressor) => compressor.compress_one(slice).into(),
+    let Lazrs_LasZipCompressor {
+        compressor,
+        thread_pool,
+        position,
+        chunk_bounds,
+        point_stats,
+        ..
+    } = &mut *compressor;
+    let result = thread_pool.install(|| match compressor {
+        Compressor::sequential(compressor) => compressor.compress_one(slice).into(),
         #[cfg(feature = "parallel")]
-        Lazrs_LasZipCompressor::parallel(compressor) => compressor.compress_many(slice).into(),
+        Compressor::parallel(compressor) => compressor.compress_many(slice).into(),
+    });
+    if result == Lazrs_Result::LAZRS_OK {
+        *position += 1;
+        if let Some(tracker) = chunk_bounds {
+            tracker.add_points(slice);
+        }
+        if let Some(tracker) = point_stats {
+            tracker.add_points(slice);
+        }
     }
+    result
 }
 
 /// Compresses many points
@@ -761,11 +1267,109 @@ pub unsafe extern "C" fn lazrs_compressor_compress_many(
     debug_assert!(!compressor.is_null());
     debug_assert!(!data.is_null());
     let slice = std::slice::from_raw_parts(data, size);
-    match &mut *compressor {
-        Lazrs_LasZipCompressor::sequential(compressor) => compressor.compress_many(slice).into(),
-        #[cfg(feature = "parallel")]
-        Lazrs_LasZipCompressor::parallel(compressor) => compressor.compress_many(slice).into(),
+    let Lazrs_LasZipCompressor {
+        compressor,
+        cancel_token,
+        thread_pool,
+        position,
+        chunk_bounds,
+        point_stats,
+    } = &mut *compressor;
+    thread_pool.install(|| {
+        let point_size = compressor.vlr().items_size() as usize;
+        let step = points_per_step(compressor.vlr(), compressor.is_parallel()) * point_size;
+        run_in_steps(size, step, cancel_token.as_ref(), |range| {
+            let points = &slice[range];
+            if let Err(error) = compressor.compress_many(points) {
+                return error.into();
+            }
+            if let Some(tracker) = chunk_bounds {
+                tracker.add_points(points);
+            }
+            if let Some(tracker) = point_stats {
+                tracker.add_points(points);
+            }
+            *position += (points.len() / point_size) as u64;
+            Lazrs_Result::LAZRS_OK
+        })
+    })
+}
+
+/// Attaches a cancel token to the compressor.
+///
+/// The token is checked between each chunk (or batch of chunks when
+/// the compressor is multi-threaded) compressed by `lazrs_compressor_compress_many`.
+/// The points compressed before the cancellation are kept, calling
+/// `lazrs_compressor_done` still produces valid LAZ data.
+///
+/// @compressor: the compressor, must not be NULL
+/// @token: the token, NULL detaches the current one
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_compressor_set_cancel_token(
+    compressor: *mut Lazrs_LasZipCompressor,
+    token: *const Lazrs_CancelToken,
+) {
+    debug_assert!(!compressor.is_null());
+    (*compressor).cancel_token = token.as_ref().map(Lazrs_CancelToken::flag);
+}
+
+/// Returns the number of points compressed so far
+///
+/// This is the number of points the LAZ data has once
+/// `lazrs_compressor_done` is called, including when
+/// `lazrs_compressor_compress_many` was cancelled.
+///
+/// @compressor: the compressor, must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_compressor_position(
+    compressor: *const Lazrs_LasZipCompressor,
+) -> u64 {
+    debug_assert!(!compressor.is_null());
+    (*compressor).position
+}
+
+/// Makes the compressor run on the given pool instead of the global one.
+///
+/// This has no effect if the compressor is single-threaded.
+///
+/// @compressor: the compressor, must not be NULL
+/// @pool: the pool, NULL goes back to using the global pool
+#[cfg(feature = "parallel")]
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_compressor_set_thread_pool(
+    compressor: *mut Lazrs_LasZipCompressor,
+    pool: *const Lazrs_ThreadPool,
+) {
+    debug_assert!(!compressor.is_null());
+    (*compressor).thread_pool.set(pool.as_ref());
+}
+
+/// Ends the current chunk, the next points compressed will go in a new one
+///
+/// Only valid for compressors using variable-size chunks,
+/// LAZRS_OTHER is returned otherwise.
+///
+/// @compressor: the compressor, must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_compressor_finish_current_chunk(
+    compressor: *mut Lazrs_LasZipCompressor,
+) -> Lazrs_Result {
+    debug_assert!(!compressor.is_null());
+    let Lazrs_LasZipCompressor {
+        compressor,
+        chunk_bounds,
+        ..
+    } = &mut *compressor;
+    let result = match compressor {
+        Compressor::sequential(compressor) if compressor.vlr().uses_variable_size_chunks() => {
+            compressor.finish_current_chunk().into()
+        }
+        _ => Lazrs_Result::LAZRS_OTHER,
+    };
+    if let (Lazrs_Result::LAZRS_OK, Some(tracker)) = (result, chunk_bounds) {
+        tracker.finish_chunk();
     }
+    result
 }
 
 /// Tells the compressor that is it done compressing points
@@ -776,11 +1380,17 @@ pub unsafe extern "C" fn lazrs_compressor_done(
     compressor: *mut Lazrs_LasZipCompressor,
 ) -> Lazrs_Result {
     debug_assert!(!compressor.is_null());
-    match &mut *compressor {
-        Lazrs_LasZipCompressor::sequential(compressor) => compressor.done().into(),
-        #[cfg(feature = "parallel")]
-        Lazrs_LasZipCompressor::parallel(compressor) => compressor.done().into(),
+    let Lazrs_LasZipCompressor {
+        compressor,
+        thread_pool,
+        chunk_bounds,
+        ..
+    } = &mut *compressor;
+    let result = thread_pool.install(|| compressor.done().into());
+    if let (Lazrs_Result::LAZRS_OK, Some(tracker)) = (result, chunk_bounds) {
+        tracker.done();
     }
+    result
 }
 
 /// Deletes the compressor
@@ -792,3 +1402,17 @@ pub unsafe extern "C" fn lazrs_compressor_delete(compressor: *mut Lazrs_LasZipCo
         let _ = Box::from_raw(compressor);
     }
 }
+
+/// Returns whether the compressor is multi-threaded
+///
+/// A compressor created with `prefer_parallel` is single-threaded
+/// if lazrs was not built with the `parallel` feature.
+///
+/// @compressor: the compressor, must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_compressor_is_parallel(
+    compressor: *const Lazrs_LasZipCompressor,
+) -> bool {
+    debug_assert!(!compressor.is_null());
+    (*compressor).compressor.is_parallel()
+}
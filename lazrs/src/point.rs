@@ -0,0 +1,642 @@
+//! Typed access to the fields of LAS point records, and conversion of points between formats.
+
+use std::convert::TryInto;
+
+use laz::LazItemType;
+
+use crate::las::point_format_size;
+use crate::Lazrs_Result;
+
+/// Size of the wave packet fields (formats 4, 5, 9 and 10)
+const WAVE_PACKET_SIZE: usize = 29;
+
+/// Unit of the scan angle of the formats >= 6, in degrees
+pub(crate) const SCAN_ANGLE_UNIT: f32 = 0.006;
+
+/// Classification legacy formats use for overlap points,
+/// formats >= 6 have a flag instead
+const OVERLAP_CLASSIFICATION: u8 = 12;
+const UNCLASSIFIED: u8 = 1;
+const NEVER_CLASSIFIED: u8 = 0;
+
+/// Where the fields of a point format are
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct PointFormat {
+    /// Whether this is one of the formats >= 6
+    pub(crate) is_extended: bool,
+    pub(crate) gps_time_offset: Option<usize>,
+    pub(crate) rgb_offset: Option<usize>,
+    pub(crate) nir_offset: Option<usize>,
+    pub(crate) wave_packet_offset: Option<usize>,
+    /// Size of the fields, without the extra bytes
+    pub(crate) size: usize,
+}
+
+impl PointFormat {
+    pub(crate) fn new(id: u8) -> Option<Self> {
+        let size = point_format_size(id)? as usize;
+        let (gps_time_offset, rgb_offset, nir_offset, wave_packet_offset) = match id {
+            0 => (None, None, None, None),
+            1 => (Some(20), None, None, None),
+            2 => (None, Some(20), None, None),
+            3 => (Some(20), Some(28), None, None),
+            4 => (Some(20), None, None, Some(28)),
+            5 => (Some(20), Some(28), None, Some(34)),
+            6 => (Some(22), None, None, None),
+            7 => (Some(22), Some(30), None, None),
+            8 => (Some(22), Some(30), Some(36), None),
+            9 => (Some(22), None, None, Some(30)),
+            10 => (Some(22), Some(30), Some(36), Some(38)),
+            _ => return None,
+        };
+        Some(Self {
+            is_extended: id >= 6,
+            gps_time_offset,
+            rgb_offset,
+            nir_offset,
+            wave_packet_offset,
+            size,
+        })
+    }
+
+    /// Gets the format of the points compressed with the items of the VLR,
+    /// with the size of the points (extra bytes included)
+    ///
+    /// LAZRS_OTHER is returned if the items are not the ones of the format.
+    pub(crate) fn of_vlr(id: u8, vlr: &laz::LazVlr) -> Result<(Self, usize), Lazrs_Result> {
+        let format = Self::new(id).ok_or(Lazrs_Result::LAZRS_UNSUPPORTED_POINT_FORMAT)?;
+        // laz has no item for the wave packets
+        if format.wave_packet_offset.is_some() {
+            return Err(Lazrs_Result::LAZRS_OTHER);
+        }
+        let mut expected = Vec::with_capacity(3);
+        if format.is_extended {
+            expected.push(LazItemType::Point14);
+            if format.nir_offset.is_some() {
+                expected.push(LazItemType::RGBNIR14);
+            } else if format.rgb_offset.is_some() {
+                expected.push(LazItemType::RGB14);
+            }
+        } else {
+            expected.push(LazItemType::Point10);
+            if format.gps_time_offset.is_some() {
+                expected.push(LazItemType::GpsTime);
+            }
+            if format.rgb_offset.is_some() {
+                expected.push(LazItemType::RGB12);
+            }
+        }
+        let fields = vlr
+            .items()
+            .iter()
+            .map(|item| item.item_type())
+            .filter(|item_type| {
+                !matches!(item_type, LazItemType::Byte(_) | LazItemType::Byte14(_))
+            });
+        if !fields.eq(expected) {
+            return Err(Lazrs_Result::LAZRS_OTHER);
+        }
+        Ok((format, vlr.items_size() as usize))
+    }
+}
+
+/// The wave packet of a point (formats 4, 5, 9 and 10)
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Lazrs_WavePacket {
+    pub(crate) descriptor_index: u8,
+    pub(crate) byte_offset: u64,
+    pub(crate) packet_size: u32,
+    pub(crate) return_point_location: f32,
+    pub(crate) x_t: f32,
+    pub(crate) y_t: f32,
+    pub(crate) z_t: f32,
+}
+
+/// All the fields a point can have, whatever its format
+///
+/// Fields the format does not have are 0 when read, and ignored when written.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Lazrs_Point {
+    /// The coordinates, as stored (before applying the scales and offsets of the header)
+    pub(crate) x: i32,
+    pub(crate) y: i32,
+    pub(crate) z: i32,
+    pub(crate) intensity: u16,
+    /// 3 bits in formats 0 - 5, 4 bits in formats >= 6
+    pub(crate) return_number: u8,
+    /// 3 bits in formats 0 - 5, 4 bits in formats >= 6
+    pub(crate) number_of_returns: u8,
+    pub(crate) scan_direction_flag: bool,
+    pub(crate) edge_of_flight_line: bool,
+    /// 5 bits in formats 0 - 5, 8 bits in formats >= 6
+    pub(crate) classification: u8,
+    pub(crate) synthetic: bool,
+    pub(crate) key_point: bool,
+    pub(crate) withheld: bool,
+    /// Formats >= 6 only
+    pub(crate) overlap: bool,
+    /// Formats >= 6 only, 2 bits
+    pub(crate) scanner_channel: u8,
+    pub(crate) user_data: u8,
+    /// In degrees, a whole number between -90 and 90 in formats 0 - 5
+    /// and a multiple of 0.006 in formats >= 6
+    pub(crate) scan_angle: f32,
+    pub(crate) point_source_id: u16,
+    pub(crate) gps_time: f64,
+    pub(crate) red: u16,
+    pub(crate) green: u16,
+    pub(crate) blue: u16,
+    pub(crate) nir: u16,
+    pub(crate) wave_packet: Lazrs_WavePacket,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn set_u16_at(data: &mut [u8], offset: usize, value: u16) {
+    data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn i32_at(data: &[u8], offset: usize) -> i32 {
+    i32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn f32_at(data: &[u8], offset: usize) -> f32 {
+    f32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn bit(byte: u8, n: u8) -> bool {
+    byte & (1 << n) != 0
+}
+
+impl Lazrs_WavePacket {
+    fn read_from(data: &[u8]) -> Self {
+        Self {
+            descriptor_index: data[0],
+            byte_offset: u64::from_le_bytes(data[1..9].try_into().unwrap()),
+            packet_size: u32::from_le_bytes(data[9..13].try_into().unwrap()),
+            return_point_location: f32_at(data, 13),
+            x_t: f32_at(data, 17),
+            y_t: f32_at(data, 21),
+            z_t: f32_at(data, 25),
+        }
+    }
+
+    fn write_to(&self, data: &mut [u8]) {
+        data[0] = self.descriptor_index;
+        data[1..9].copy_from_slice(&self.byte_offset.to_le_bytes());
+        data[9..13].copy_from_slice(&self.packet_size.to_le_bytes());
+        data[13..17].copy_from_slice(&self.return_point_location.to_le_bytes());
+        data[17..21].copy_from_slice(&self.x_t.to_le_bytes());
+        data[21..25].copy_from_slice(&self.y_t.to_le_bytes());
+        data[25..29].copy_from_slice(&self.z_t.to_le_bytes());
+    }
+}
+
+impl Lazrs_Point {
+    /// Reads the point from its record, which must be at least `format.size` bytes
+    pub(crate) fn read_from(format: &PointFormat, data: &[u8]) -> Self {
+        let mut point = Lazrs_Point {
+            x: i32_at(data, 0),
+            y: i32_at(data, 4),
+            z: i32_at(data, 8),
+            intensity: u16_at(data, 12),
+            user_data: data[17],
+            ..Default::default()
+        };
+        if format.is_extended {
+            point.return_number = data[14] & 0x0F;
+            point.number_of_returns = data[14] >> 4;
+            point.synthetic = bit(data[15], 0);
+            point.key_point = bit(data[15], 1);
+            point.withheld = bit(data[15], 2);
+            point.overlap = bit(data[15], 3);
+            point.scanner_channel = (data[15] >> 4) & 0x03;
+            point.scan_direction_flag = bit(data[15], 6);
+            point.edge_of_flight_line = bit(data[15], 7);
+            point.classification = data[16];
+            point.scan_angle = f32::from(u16_at(data, 18) as i16) * SCAN_ANGLE_UNIT;
+            point.point_source_id = u16_at(data, 20);
+        } else {
+            point.return_number = data[14] & 0x07;
+            point.number_of_returns = (data[14] >> 3) & 0x07;
+            point.scan_direction_flag = bit(data[14], 6);
+            point.edge_of_flight_line = bit(data[14], 7);
+            point.classification = data[15] & 0x1F;
+            point.synthetic = bit(data[15], 5);
+            point.key_point = bit(data[15], 6);
+            point.withheld = bit(data[15], 7);
+            point.scan_angle = f32::from(data[16] as i8);
+            point.point_source_id = u16_at(data, 18);
+        }
+        if let Some(offset) = format.gps_time_offset {
+            point.gps_time = f64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+        }
+        if let Some(offset) = format.rgb_offset {
+            point.red = u16_at(data, offset);
+            point.green = u16_at(data, offset + 2);
+            point.blue = u16_at(data, offset + 4);
+        }
+        if let Some(offset) = format.nir_offset {
+            point.nir = u16_at(data, offset);
+        }
+        if let Some(offset) = format.wave_packet_offset {
+            point.wave_packet =
+                Lazrs_WavePacket::read_from(&data[offset..offset + WAVE_PACKET_SIZE]);
+        }
+        point
+    }
+
+    /// Writes the point in the record, which must be at least `format.size` bytes
+    ///
+    /// Bit fields are truncated to their size, the scan angle is rounded to
+    /// what the format can store.
+    pub(crate) fn write_to(&self, format: &PointFormat, data: &mut [u8]) {
+        data[0..4].copy_from_slice(&self.x.to_le_bytes());
+        data[4..8].copy_from_slice(&self.y.to_le_bytes());
+        data[8..12].copy_from_slice(&self.z.to_le_bytes());
+        set_u16_at(data, 12, self.intensity);
+        data[17] = self.user_data;
+        if format.is_extended {
+            data[14] = (self.return_number & 0x0F) | (self.number_of_returns << 4);
+            data[15] = u8::from(self.synthetic)
+                | u8::from(self.key_point) << 1
+                | u8::from(self.withheld) << 2
+                | u8::from(self.overlap) << 3
+                | (self.scanner_channel & 0x03) << 4
+                | u8::from(self.scan_direction_flag) << 6
+                | u8::from(self.edge_of_flight_line) << 7;
+            data[16] = self.classification;
+            let scan_angle = (self.scan_angle / SCAN_ANGLE_UNIT).round() as i16;
+            set_u16_at(data, 18, scan_angle as u16);
+            set_u16_at(data, 20, self.point_source_id);
+        } else {
+            data[14] = (self.return_number & 0x07)
+                | (self.number_of_returns & 0x07) << 3
+                | u8::from(self.scan_direction_flag) << 6
+                | u8::from(self.edge_of_flight_line) << 7;
+            data[15] = (self.classification & 0x1F)
+                | u8::from(self.synthetic) << 5
+                | u8::from(self.key_point) << 6
+                | u8::from(self.withheld) << 7;
+            data[16] = self.scan_angle.round() as i8 as u8;
+            set_u16_at(data, 18, self.point_source_id);
+        }
+        if let Some(offset) = format.gps_time_offset {
+            data[offset..offset + 8].copy_from_slice(&self.gps_time.to_le_bytes());
+        }
+        if let Some(offset) = format.rgb_offset {
+            set_u16_at(data, offset, self.red);
+            set_u16_at(data, offset + 2, self.green);
+            set_u16_at(data, offset + 4, self.blue);
+        }
+        if let Some(offset) = format.nir_offset {
+            set_u16_at(data, offset, self.nir);
+        }
+        if let Some(offset) = format.wave_packet_offset {
+            self.wave_packet
+                .write_to(&mut data[offset..offset + WAVE_PACKET_SIZE]);
+        }
+    }
+}
+
+/// Converts the points from one format to another, the extra bytes are copied as is.
+///
+/// Fields the source format does not have are set to 0.
+/// Going from legacy formats to formats >= 6, points classified as overlap
+/// get the overlap flag (and become unclassified).
+/// Going the other way, overlap points are classified as overlap,
+/// return numbers are clamped to 7, scan angles to [-90, 90],
+/// and classifications that do not fit in 5 bits become 0 (never classified).
+pub(crate) fn convert_points(
+    src_format: &PointFormat,
+    src: &[u8],
+    dst_format: &PointFormat,
+    dst: &mut [u8],
+    num_extra_bytes: usize,
+) {
+    let src_size = src_format.size + num_extra_bytes;
+    let dst_size = dst_format.size + num_extra_bytes;
+    for (src_point, dst_point) in src
+        .chunks_exact(src_size)
+        .zip(dst.chunks_exact_mut(dst_size))
+    {
+        let mut point = Lazrs_Point::read_from(src_format, src_point);
+        if !src_format.is_extended
+            && dst_format.is_extended
+            && point.classification == OVERLAP_CLASSIFICATION
+        {
+            point.classification = UNCLASSIFIED;
+            point.overlap = true;
+        } else if src_format.is_extended && !dst_format.is_extended {
+            point.return_number = point.return_number.min(7);
+            point.number_of_returns = point.number_of_returns.min(7);
+            point.scan_angle = point.scan_angle.clamp(-90.0, 90.0);
+            if point.overlap {
+                point.classification = OVERLAP_CLASSIFICATION;
+            } else if point.classification > 0x1F {
+                point.classification = NEVER_CLASSIFIED;
+            }
+        }
+        point.write_to(dst_format, dst_point);
+        dst_point[dst_format.size..].copy_from_slice(&src_point[src_format.size..]);
+    }
+}
+
+/// A view over a buffer of LAS point records, to read and write their fields
+///
+/// The view does not own the buffer, which must outlive it.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Lazrs_PointView {
+    data: *mut u8,
+    len: usize,
+    point_format_id: u8,
+    point_size: u16,
+}
+
+impl Lazrs_PointView {
+    /// Returns the format and the start of the record of the point
+    unsafe fn record(&self, index: usize) -> Option<(PointFormat, *mut u8)> {
+        let format = PointFormat::new(self.point_format_id)?;
+        let point_size = usize::from(self.point_size);
+        if index >= self.len / point_size {
+            return None;
+        }
+        Some((format, self.data.add(index * point_size)))
+    }
+}
+
+/// Creates a view over LAS point records
+///
+/// @point_format_id: the point format of the records
+/// @point_size: size of a record (from the LAS header), extra bytes included
+/// @data: the records, must not be NULL
+/// @len: size of the buffer
+/// @view: will receive the view, must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_point_view_new(
+    point_format_id: u8,
+    point_size: u16,
+    data: *mut u8,
+    len: usize,
+    view: *mut Lazrs_PointView,
+) -> Lazrs_Result {
+    debug_assert!(!data.is_null());
+    debug_assert!(!view.is_null());
+    let format = match PointFormat::new(point_format_id) {
+        Some(format) => format,
+        None => return Lazrs_Result::LAZRS_UNSUPPORTED_POINT_FORMAT,
+    };
+    if usize::from(point_size) < format.size {
+        return Lazrs_Result::LAZRS_OTHER;
+    }
+    *view = Lazrs_PointView {
+        data,
+        len,
+        point_format_id,
+        point_size,
+    };
+    Lazrs_Result::LAZRS_OK
+}
+
+/// Returns the number of points in the view
+///
+/// @view: must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_point_view_num_points(view: *const Lazrs_PointView) -> usize {
+    debug_assert!(!view.is_null());
+    (*view).len / usize::from((*view).point_size)
+}
+
+/// Reads the fields of a point
+///
+/// @view: must not be NULL
+/// @index: index of the point, LAZRS_OTHER is returned if it is out of bounds
+/// @point: will receive the fields
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_point_view_get(
+    view: *const Lazrs_PointView,
+    index: usize,
+    point: *mut Lazrs_Point,
+) -> Lazrs_Result {
+    debug_assert!(!view.is_null());
+    debug_assert!(!point.is_null());
+    match (*view).record(index) {
+        Some((format, data)) => {
+            let data = std::slice::from_raw_parts(data, usize::from((*view).point_size));
+            *point = Lazrs_Point::read_from(&format, data);
+            Lazrs_Result::LAZRS_OK
+        }
+        None => Lazrs_Result::LAZRS_OTHER,
+    }
+}
+
+/// Writes the fields of a point, its extra bytes are left untouched
+///
+/// Bit fields are truncated to the number of bits the format has for them,
+/// and the scan angle is rounded to what the format can store.
+///
+/// @view: must not be NULL
+/// @index: index of the point, LAZRS_OTHER is returned if it is out of bounds
+/// @point: the fields
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_point_view_set(
+    view: *const Lazrs_PointView,
+    index: usize,
+    point: *const Lazrs_Point,
+) -> Lazrs_Result {
+    debug_assert!(!view.is_null());
+    debug_assert!(!point.is_null());
+    match (*view).record(index) {
+        Some((format, data)) => {
+            let data = std::slice::from_raw_parts_mut(data, usize::from((*view).point_size));
+            (*point).write_to(&format, data);
+            Lazrs_Result::LAZRS_OK
+        }
+        None => Lazrs_Result::LAZRS_OTHER,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic bytes, so that tests do not need a random crate
+    fn pseudo_random_bytes(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed | 1;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn vlr_of(point_format_id: u8, num_extra_bytes: u16) -> laz::LazVlr {
+        laz::LazVlrBuilder::default()
+            .with_point_format(point_format_id, num_extra_bytes)
+            .unwrap()
+            .build()
+    }
+
+    #[test]
+    fn formats_match_the_items_they_are_compressed_with() {
+        for id in [0, 1, 2, 3, 6, 7, 8] {
+            let (format, point_size) = PointFormat::of_vlr(id, &vlr_of(id, 3)).unwrap();
+            assert_eq!(point_size, format.size + 3);
+        }
+        assert!(matches!(
+            PointFormat::of_vlr(0, &vlr_of(1, 0)),
+            Err(Lazrs_Result::LAZRS_OTHER)
+        ));
+        assert!(matches!(
+            PointFormat::of_vlr(7, &vlr_of(8, 0)),
+            Err(Lazrs_Result::LAZRS_OTHER)
+        ));
+        assert!(matches!(
+            PointFormat::of_vlr(6, &vlr_of(1, 10)),
+            Err(Lazrs_Result::LAZRS_OTHER)
+        ));
+        assert!(matches!(
+            PointFormat::of_vlr(11, &vlr_of(1, 0)),
+            Err(Lazrs_Result::LAZRS_UNSUPPORTED_POINT_FORMAT)
+        ));
+    }
+
+    #[test]
+    fn records_are_read_and_written_back_unchanged() {
+        for id in 0..=10 {
+            let format = PointFormat::new(id).unwrap();
+            for seed in 0..50 {
+                let mut record = pseudo_random_bytes(format.size, seed);
+                // Floating point fields are only copied, but keep them finite to be safe
+                if let Some(offset) = format.gps_time_offset {
+                    record[offset..offset + 8].copy_from_slice(&(seed as f64).to_le_bytes());
+                }
+                if let Some(offset) = format.wave_packet_offset {
+                    for field in 0..4 {
+                        let start = offset + 13 + 4 * field;
+                        record[start..start + 4].copy_from_slice(&1.5f32.to_le_bytes());
+                    }
+                }
+
+                let point = Lazrs_Point::read_from(&format, &record);
+                let mut written = vec![0u8; format.size];
+                point.write_to(&format, &mut written);
+                assert_eq!(written, record, "format {}", id);
+            }
+        }
+    }
+
+    #[test]
+    fn fields_are_at_their_place() {
+        let format = PointFormat::new(8).unwrap();
+        let point = Lazrs_Point {
+            x: -1,
+            return_number: 15,
+            number_of_returns: 3,
+            overlap: true,
+            scanner_channel: 2,
+            classification: 200,
+            scan_angle: -0.6,
+            gps_time: 1.25,
+            red: 1,
+            nir: 0xBEEF,
+            ..Default::default()
+        };
+        let mut record = vec![0u8; format.size];
+        point.write_to(&format, &mut record);
+        assert_eq!(&record[0..4], &(-1i32).to_le_bytes());
+        assert_eq!(record[14], 0x3F);
+        assert_eq!(record[15], 0x28);
+        assert_eq!(record[16], 200);
+        assert_eq!(&record[18..20], &(-100i16).to_le_bytes());
+        assert_eq!(&record[22..30], &1.25f64.to_le_bytes());
+        assert_eq!(&record[30..32], &1u16.to_le_bytes());
+        assert_eq!(&record[36..38], &0xBEEFu16.to_le_bytes());
+    }
+
+    #[test]
+    fn bit_fields_are_truncated() {
+        let format = PointFormat::new(1).unwrap();
+        let point = Lazrs_Point {
+            return_number: 9,
+            number_of_returns: 10,
+            classification: 0xFF,
+            ..Default::default()
+        };
+        let mut record = vec![0u8; format.size];
+        point.write_to(&format, &mut record);
+        let read = Lazrs_Point::read_from(&format, &record);
+        assert_eq!(read.return_number, 1);
+        assert_eq!(read.number_of_returns, 2);
+        assert_eq!(read.classification, 0x1F);
+        assert!(!read.synthetic);
+    }
+
+    #[test]
+    fn overlap_is_converted_between_legacy_and_extended_formats() {
+        let (legacy, extended) = (PointFormat::new(1).unwrap(), PointFormat::new(6).unwrap());
+        let num_extra_bytes = 2;
+        let mut legacy_record = vec![0u8; legacy.size + num_extra_bytes];
+        Lazrs_Point {
+            classification: OVERLAP_CLASSIFICATION,
+            gps_time: 3.0,
+            ..Default::default()
+        }
+        .write_to(&legacy, &mut legacy_record);
+        legacy_record[legacy.size..].copy_from_slice(&[7, 8]);
+
+        let mut extended_record = vec![0u8; extended.size + num_extra_bytes];
+        convert_points(
+            &legacy,
+            &legacy_record,
+            &extended,
+            &mut extended_record,
+            num_extra_bytes,
+        );
+        let point = Lazrs_Point::read_from(&extended, &extended_record);
+        assert!(point.overlap);
+        assert_eq!(point.classification, UNCLASSIFIED);
+        assert_eq!(point.gps_time, 3.0);
+        assert_eq!(&extended_record[extended.size..], &[7, 8]);
+
+        let mut back = vec![0u8; legacy_record.len()];
+        convert_points(
+            &extended,
+            &extended_record,
+            &legacy,
+            &mut back,
+            num_extra_bytes,
+        );
+        assert_eq!(back, legacy_record);
+    }
+
+    #[test]
+    fn extended_fields_are_clamped_for_legacy_formats() {
+        let (extended, legacy) = (PointFormat::new(6).unwrap(), PointFormat::new(1).unwrap());
+        let mut extended_record = vec![0u8; extended.size];
+        Lazrs_Point {
+            return_number: 12,
+            number_of_returns: 15,
+            classification: 64,
+            scan_angle: -150.0,
+            ..Default::default()
+        }
+        .write_to(&extended, &mut extended_record);
+
+        let mut legacy_record = vec![0u8; legacy.size];
+        convert_points(&extended, &extended_record, &legacy, &mut legacy_record, 0);
+        let point = Lazrs_Point::read_from(&legacy, &legacy_record);
+        assert_eq!(point.return_number, 7);
+        assert_eq!(point.number_of_returns, 7);
+        assert_eq!(point.classification, NEVER_CLASSIFIED);
+        assert_eq!(point.scan_angle, -90.0);
+    }
+}
@@ -0,0 +1,204 @@
+//! Statistics of the points, computed while compressing, to fill the LAS header
+//! without reading the points again.
+
+use crate::chunk_bounds::Lazrs_ChunkBounds;
+use crate::point::{Lazrs_Point, PointFormat};
+use crate::{Lazrs_LasZipCompressor, Lazrs_Result};
+
+/// Statistics of the points given to a compressor
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Lazrs_PointStats {
+    /// Number of points, and their extents
+    pub(crate) bounds: Lazrs_ChunkBounds,
+    /// Number of points of each return number, from 1 to 15,
+    /// points with a return number of 0 are not counted
+    pub(crate) number_of_points_by_return: [u64; 15],
+    /// Number of points of each classification
+    pub(crate) number_of_points_by_classification: [u64; 256],
+}
+
+impl Default for Lazrs_PointStats {
+    fn default() -> Self {
+        Self {
+            bounds: Lazrs_ChunkBounds::default(),
+            number_of_points_by_return: [0; 15],
+            number_of_points_by_classification: [0; 256],
+        }
+    }
+}
+
+impl Lazrs_PointStats {
+    fn add(&mut self, point: &Lazrs_Point) {
+        self.bounds.add(point);
+        if let Some(count) = usize::from(point.return_number)
+            .checked_sub(1)
+            .and_then(|i| self.number_of_points_by_return.get_mut(i))
+        {
+            *count += 1;
+        }
+        self.number_of_points_by_classification[usize::from(point.classification)] += 1;
+    }
+}
+
+/// Follows the points given to a compressor to compute their statistics
+pub(crate) struct PointStatsTracker {
+    format: PointFormat,
+    point_size: usize,
+    stats: Lazrs_PointStats,
+}
+
+impl PointStatsTracker {
+    pub(crate) fn add_points(&mut self, records: &[u8]) {
+        for record in records.chunks_exact(self.point_size) {
+            self.stats
+                .add(&Lazrs_Point::read_from(&self.format, record));
+        }
+    }
+}
+
+/// Makes the compressor compute the statistics of the points it compresses
+///
+/// This must be called before any point is compressed.
+/// The statistics are available through `lazrs_compressor_point_stats`.
+///
+/// @compressor: the compressor, must not be NULL
+/// @point_format_id: the point format of the points, LAZRS_OTHER is returned
+///                   if the compressor was not created for this format
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_compressor_track_point_stats(
+    compressor: *mut Lazrs_LasZipCompressor,
+    point_format_id: u8,
+) -> Lazrs_Result {
+    debug_assert!(!compressor.is_null());
+    let compressor = &mut *compressor;
+    let vlr = compressor.compressor.vlr();
+    let (format, point_size) = match PointFormat::of_vlr(point_format_id, vlr) {
+        Ok(format) => format,
+        Err(result) => return result,
+    };
+    compressor.point_stats = Some(PointStatsTracker {
+        format,
+        point_size,
+        stats: Lazrs_PointStats::default(),
+    });
+    Lazrs_Result::LAZRS_OK
+}
+
+/// Gets the statistics of the points compressed so far,
+/// usually once `lazrs_compressor_done` has been called
+///
+/// LAZRS_OTHER is returned if `lazrs_compressor_track_point_stats` was not called.
+///
+/// @compressor: the compressor, must not be NULL
+/// @stats: will receive the statistics
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_compressor_point_stats(
+    compressor: *const Lazrs_LasZipCompressor,
+    stats: *mut Lazrs_PointStats,
+) -> Lazrs_Result {
+    debug_assert!(!compressor.is_null());
+    debug_assert!(!stats.is_null());
+    match &(*compressor).point_stats {
+        Some(tracker) => {
+            *stats = tracker.stats;
+            Lazrs_Result::LAZRS_OK
+        }
+        None => Lazrs_Result::LAZRS_OTHER,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        lazrs_compressor_compress_many, lazrs_compressor_compress_one, lazrs_compressor_delete,
+        lazrs_compressor_done, lazrs_compressor_new_for_point_format, Lazrs_CompressorParams,
+        Lazrs_Dest, Lazrs_DestType,
+    };
+
+    const NUM_POINTS: usize = 30;
+
+    /// Points of format 1, whose return number is `i % 4` and classification `i % 3`
+    fn points() -> Vec<u8> {
+        let format = PointFormat::new(1).unwrap();
+        let mut records = vec![0u8; NUM_POINTS * format.size];
+        for (i, record) in records.chunks_exact_mut(format.size).enumerate() {
+            let point = Lazrs_Point {
+                x: i as i32 - 10,
+                y: 2 * i as i32,
+                z: 100,
+                return_number: (i % 4) as u8,
+                number_of_returns: 3,
+                classification: (i % 3) as u8,
+                gps_time: 1000.0 + i as f64 * 0.5,
+                ..Default::default()
+            };
+            point.write_to(&format, record);
+        }
+        records
+    }
+
+    #[test]
+    fn points_are_counted_by_return_and_classification() {
+        let points = points();
+        let point_size = points.len() / NUM_POINTS;
+        let mut stats = Lazrs_PointStats::default();
+        unsafe {
+            let fh = libc::tmpfile();
+            assert!(!fh.is_null());
+            let params = Lazrs_CompressorParams {
+                dest_type: Lazrs_DestType::LAZRS_DEST_CFILE,
+                dest: Lazrs_Dest { file: fh },
+                point_format_id: 1,
+                num_extra_bytes: 0,
+            };
+            let mut compressor = std::ptr::null_mut();
+            assert_eq!(
+                lazrs_compressor_new_for_point_format(params, false, &mut compressor),
+                Lazrs_Result::LAZRS_OK
+            );
+            assert_eq!(
+                lazrs_compressor_point_stats(compressor, &mut stats),
+                Lazrs_Result::LAZRS_OTHER
+            );
+            assert_eq!(
+                lazrs_compressor_track_point_stats(compressor, 3),
+                Lazrs_Result::LAZRS_OTHER
+            );
+            assert_eq!(
+                lazrs_compressor_track_point_stats(compressor, 1),
+                Lazrs_Result::LAZRS_OK
+            );
+
+            let result = lazrs_compressor_compress_one(compressor, points.as_ptr(), point_size);
+            assert_eq!(result, Lazrs_Result::LAZRS_OK);
+            let rest = &points[point_size..];
+            let result = lazrs_compressor_compress_many(compressor, rest.as_ptr(), rest.len());
+            assert_eq!(result, Lazrs_Result::LAZRS_OK);
+            assert_eq!(lazrs_compressor_done(compressor), Lazrs_Result::LAZRS_OK);
+            assert_eq!(
+                lazrs_compressor_point_stats(compressor, &mut stats),
+                Lazrs_Result::LAZRS_OK
+            );
+            lazrs_compressor_delete(compressor);
+            libc::fclose(fh);
+        }
+
+        // The points with a return number of 0 are not counted
+        let mut by_return = [0u64; 15];
+        by_return[..3].copy_from_slice(&[8, 7, 7]);
+        assert_eq!(stats.number_of_points_by_return, by_return);
+        let mut by_classification = [0u64; 256];
+        by_classification[..3].copy_from_slice(&[10, 10, 10]);
+        assert_eq!(stats.number_of_points_by_classification, by_classification);
+
+        let bounds = stats.bounds;
+        assert_eq!(bounds.point_count, NUM_POINTS as u64);
+        assert_eq!((bounds.min_x, bounds.max_x), (-10, 19));
+        assert_eq!((bounds.min_y, bounds.max_y), (0, 58));
+        assert_eq!((bounds.min_z, bounds.max_z), (100, 100));
+        assert_eq!((bounds.min_gps_time, bounds.max_gps_time), (1000.0, 1014.5));
+    }
+}
@@ -0,0 +1,384 @@
+//! Spatial queries that only decompress the chunks that may contain matching points.
+
+use std::convert::TryInto;
+use std::ffi::c_void;
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::chunk_bounds::{
+    Lazrs_ChunkBounds, Lazrs_ChunkBoundsTable, CHUNK_BOUNDS_USER_ID, LAZRS_CHUNK_BOUNDS_RECORD_ID,
+};
+use crate::chunks::{decompress_chunk, ChunkLayout};
+use crate::filter::Lazrs_Bounds;
+use crate::io::CSource;
+use crate::las::LasMetadata;
+use crate::transcode::read_laz_metadata;
+use crate::{Lazrs_Result, Lazrs_Source, Lazrs_SourceType};
+
+/// Called with the points found by a query, `num_points` LAS records are at `points`.
+///
+/// The points are only valid during the call.
+/// Returning false stops the query.
+pub type Lazrs_PointsCallback =
+    unsafe extern "C" fn(user_data: *mut c_void, points: *const u8, num_points: u64) -> bool;
+
+/// Options of `lazrs_query_bbox`
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Lazrs_QueryOptions {
+    /// Bounds of the chunks of the file, NULL to use the ones stored in the file
+    /// (see `LAZRS_CHUNK_BOUNDS_RECORD_ID`). When the file has none,
+    /// all the chunks are decompressed.
+    chunk_bounds: *const Lazrs_ChunkBoundsTable,
+    /// Whether to decompress the chunks using multiple threads, if possible
+    prefer_parallel: bool,
+    callback: Lazrs_PointsCallback,
+    /// Given to the callback
+    user_data: *mut c_void,
+}
+
+/// The transform from the coordinates stored in the points to the actual ones
+#[derive(Copy, Clone)]
+struct Transform {
+    scales: [f64; 3],
+    offsets: [f64; 3],
+}
+
+impl Transform {
+    fn apply(&self, i: usize, value: i32) -> f64 {
+        f64::from(value) * self.scales[i] + self.offsets[i]
+    }
+
+    fn contains(&self, bounds: &Lazrs_Bounds, record: &[u8]) -> bool {
+        let coordinate = |i: usize| {
+            self.apply(
+                i,
+                i32::from_le_bytes(record[4 * i..4 * i + 4].try_into().unwrap()),
+            )
+        };
+        bounds.contains(coordinate(0), coordinate(1), coordinate(2))
+    }
+
+    fn intersects(&self, bounds: &Lazrs_Bounds, chunk: &Lazrs_ChunkBounds) -> bool {
+        if chunk.point_count == 0 {
+            return false;
+        }
+        // Scales can be negative
+        let range = |i: usize, min: i32, max: i32| {
+            let (a, b) = (self.apply(i, min), self.apply(i, max));
+            (a.min(b), a.max(b))
+        };
+        let (min_x, max_x) = range(0, chunk.min_x, chunk.max_x);
+        let (min_y, max_y) = range(1, chunk.min_y, chunk.max_y);
+        let (min_z, max_z) = range(2, chunk.min_z, chunk.max_z);
+        min_x <= bounds.max_x
+            && max_x >= bounds.min_x
+            && min_y <= bounds.max_y
+            && max_y >= bounds.min_y
+            && min_z <= bounds.max_z
+            && max_z >= bounds.min_z
+    }
+}
+
+/// Finds the chunk bounds stored in the VLRs or EVLRs of the file
+fn stored_chunk_bounds(metadata: &LasMetadata) -> Option<Lazrs_ChunkBoundsTable> {
+    metadata
+        .vlrs
+        .iter()
+        .chain(metadata.evlrs.iter())
+        .find(|vlr| vlr.is(CHUNK_BOUNDS_USER_ID, LAZRS_CHUNK_BOUNDS_RECORD_ID))
+        .and_then(|vlr| Lazrs_ChunkBoundsTable::from_vlr_data(&vlr.data))
+}
+
+/// A chunk to decompress
+struct Chunk {
+    data: Vec<u8>,
+    point_count: u64,
+}
+
+/// Decompresses the chunk, and only keeps the points in the bounds
+fn query_chunk(
+    vlr: &laz::LazVlr,
+    chunk: &Chunk,
+    transform: &Transform,
+    bounds: &Lazrs_Bounds,
+) -> laz::Result<Vec<u8>> {
+    let mut points = vec![];
+    decompress_chunk(vlr, &chunk.data, chunk.point_count, |point| {
+        if transform.contains(bounds, point) {
+            points.extend_from_slice(point);
+        }
+    })?;
+    Ok(points)
+}
+
+/// Queries a batch of chunks
+fn query_chunks(
+    vlr: &laz::LazVlr,
+    chunks: &[Chunk],
+    transform: &Transform,
+    bounds: &Lazrs_Bounds,
+    parallel: bool,
+) -> laz::Result<Vec<Vec<u8>>> {
+    #[cfg(feature = "parallel")]
+    if parallel {
+        use rayon::prelude::*;
+        return chunks
+            .par_iter()
+            .map(|chunk| query_chunk(vlr, chunk, transform, bounds))
+            .collect();
+    }
+    let _ = parallel;
+    chunks
+        .iter()
+        .map(|chunk| query_chunk(vlr, chunk, transform, bounds))
+        .collect()
+}
+
+/// Number of chunks handled at once, read from the source before being decompressed
+/// or compressed before being written
+pub(crate) fn batch_size(parallel: bool) -> usize {
+    #[cfg(feature = "parallel")]
+    if parallel {
+        return 2 * rayon::current_num_threads();
+    }
+    let _ = parallel;
+    1
+}
+
+unsafe fn query_bbox(
+    mut src: CSource<'static>,
+    bounds: &Lazrs_Bounds,
+    options: &Lazrs_QueryOptions,
+) -> Result<(), Lazrs_Result> {
+    let (metadata, vlr) = read_laz_metadata(&mut src)?;
+    let layout = ChunkLayout::read_from(&mut src, &vlr)?;
+    // The byte counts of the table are used to allocate the chunks, they must fit in the file
+    let source_end = src.seek(SeekFrom::End(0))?;
+    if !layout.is_consistent(source_end) {
+        return Err(Lazrs_Result::LAZRS_OTHER);
+    }
+    let point_counts = layout.point_counts(&vlr, metadata.header.number_of_points());
+    let offsets = layout.chunk_offsets();
+    let transform = Transform {
+        scales: metadata.header.scales(),
+        offsets: metadata.header.offsets(),
+    };
+
+    let stored_bounds;
+    let chunk_bounds = match options.chunk_bounds.as_ref() {
+        Some(table) => Some(table),
+        None => {
+            stored_bounds = stored_chunk_bounds(&metadata);
+            stored_bounds.as_ref()
+        }
+    };
+    let selected: Vec<usize> = match chunk_bounds {
+        Some(table) if table.chunks.len() != point_counts.len() => {
+            return Err(Lazrs_Result::LAZRS_OTHER)
+        }
+        Some(table) => (0..point_counts.len())
+            .filter(|&i| transform.intersects(bounds, &table.chunks[i]))
+            .collect(),
+        None => (0..point_counts.len()).collect(),
+    };
+
+    let entries = layout.table.as_ref();
+    for batch in selected.chunks(batch_size(options.prefer_parallel)) {
+        let mut chunks = Vec::with_capacity(batch.len());
+        for &i in batch {
+            src.seek(SeekFrom::Start(offsets[i]))?;
+            let mut data = vec![0u8; entries[i].byte_count as usize];
+            src.read_exact(&mut data)?;
+            chunks.push(Chunk {
+                data,
+                point_count: point_counts[i],
+            });
+        }
+        let results = query_chunks(&vlr, &chunks, &transform, bounds, options.prefer_parallel)?;
+        for points in results.iter().filter(|points| !points.is_empty()) {
+            let num_points = points.len() / vlr.items_size() as usize;
+            if !(options.callback)(options.user_data, points.as_ptr(), num_points as u64) {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Finds the points of a LAZ file that are in a box
+///
+/// Only the chunks whose bounds intersect the box are decompressed,
+/// using the chunk table of the file, then the coordinates
+/// of their points are compared to the box.
+/// The matching points of each chunk are given to the callback, in the order of the file.
+///
+/// The file is read from the current position of the source.
+/// LAZRS_OTHER is returned if the source is not compressed, if the chunk table
+/// does not match the size of the file, or if the chunk bounds do not have
+/// as many chunks as the file.
+///
+/// @source_type: type of the source
+/// @source: where the LAZ file is read from
+/// @bounds: the box, in the coordinates of the file (scales and offsets applied)
+/// @options: how to find and decompress the chunks, and where the points go
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_query_bbox(
+    source_type: Lazrs_SourceType,
+    source: Lazrs_Source,
+    bounds: Lazrs_Bounds,
+    options: Lazrs_QueryOptions,
+) -> Lazrs_Result {
+    let src = match CSource::from_c_source(source_type, source) {
+        Ok(src) => src,
+        Err(result) => return result,
+    };
+    match query_bbox(src, &bounds, &options) {
+        Ok(()) => Lazrs_Result::LAZRS_OK,
+        Err(result) => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    use crate::Lazrs_Buffer;
+
+    const NUM_POINTS: usize = 40;
+    const CHUNK_SIZE: usize = 10;
+    const POINT_SIZE: usize = 20;
+
+    /// A LAZ 1.2 file of format 0 whose point i is at (i / 2, 0, 0),
+    /// in chunks of 10 points
+    fn laz_file() -> (Vec<u8>, laz::LazVlr) {
+        let mut header = vec![0u8; 227];
+        header[..4].copy_from_slice(b"LASF");
+        header[24..26].copy_from_slice(&[1, 2]);
+        header[94..96].copy_from_slice(&227u16.to_le_bytes());
+        header[96..100].copy_from_slice(&227u32.to_le_bytes());
+        header[105..107].copy_from_slice(&(POINT_SIZE as u16).to_le_bytes());
+        header[107..111].copy_from_slice(&(NUM_POINTS as u32).to_le_bytes());
+        for i in 0..3 {
+            header[131 + 8 * i..139 + 8 * i].copy_from_slice(&0.5f64.to_le_bytes());
+        }
+        let mut metadata = LasMetadata::read_from(&mut Cursor::new(header)).unwrap();
+
+        let items = laz::LazItemRecordBuilder::default_for_point_format_id(0, 0).unwrap();
+        let vlr = laz::LazVlrBuilder::new(items)
+            .with_fixed_chunk_size(CHUNK_SIZE as u32)
+            .build();
+        let mut points = vec![0u8; NUM_POINTS * POINT_SIZE];
+        for (i, point) in points.chunks_exact_mut(POINT_SIZE).enumerate() {
+            point[..4].copy_from_slice(&(i as i32).to_le_bytes());
+        }
+
+        metadata.set_laszip_vlr(Some(&vlr)).unwrap();
+        metadata.header.set_point_format(0, true);
+        let mut dst = Cursor::new(vec![]);
+        metadata.write_head_to(&mut dst).unwrap();
+        laz::compress_buffer(&mut dst, &points, vlr.clone()).unwrap();
+        (dst.into_inner(), vlr)
+    }
+
+    /// The actual bounds of the chunks of `laz_file`
+    fn chunk_bounds() -> Lazrs_ChunkBoundsTable {
+        let chunks = (0..NUM_POINTS / CHUNK_SIZE)
+            .map(|i| Lazrs_ChunkBounds {
+                point_count: CHUNK_SIZE as u64,
+                min_x: (i * CHUNK_SIZE) as i32,
+                max_x: ((i + 1) * CHUNK_SIZE - 1) as i32,
+                ..Default::default()
+            })
+            .collect();
+        Lazrs_ChunkBoundsTable { chunks }
+    }
+
+    /// The x of the points given to each call of the callback
+    type Found = Vec<Vec<i32>>;
+
+    unsafe extern "C" fn collect(
+        user_data: *mut c_void,
+        points: *const u8,
+        num_points: u64,
+    ) -> bool {
+        let points = std::slice::from_raw_parts(points, num_points as usize * POINT_SIZE);
+        (*(user_data as *mut Found)).push(
+            points
+                .chunks_exact(POINT_SIZE)
+                .map(|point| i32::from_le_bytes(point[..4].try_into().unwrap()))
+                .collect(),
+        );
+        true
+    }
+
+    fn query(
+        laz: &[u8],
+        min_x: f64,
+        max_x: f64,
+        chunk_bounds: Option<&Lazrs_ChunkBoundsTable>,
+    ) -> (Lazrs_Result, Found) {
+        let mut found = Found::new();
+        let bounds = Lazrs_Bounds {
+            min_x,
+            max_x,
+            ..Default::default()
+        };
+        let options = Lazrs_QueryOptions {
+            chunk_bounds: chunk_bounds.map_or(std::ptr::null(), |table| table),
+            prefer_parallel: false,
+            callback: collect,
+            user_data: &mut found as *mut Found as *mut c_void,
+        };
+        let source = Lazrs_Source {
+            buffer: Lazrs_Buffer {
+                data: laz.as_ptr(),
+                len: laz.len(),
+            },
+        };
+        let result = unsafe {
+            lazrs_query_bbox(
+                Lazrs_SourceType::LAZRS_SOURCE_BUFFER,
+                source,
+                bounds,
+                options,
+            )
+        };
+        (result, found)
+    }
+
+    #[test]
+    fn matching_points_are_given_by_chunk() {
+        let (laz, _) = laz_file();
+        let expected = vec![(12..20).collect::<Vec<_>>(), (20..=25).collect()];
+        // The scale is 0.5
+        assert_eq!(
+            query(&laz, 6.0, 12.5, Some(&chunk_bounds())),
+            (Lazrs_Result::LAZRS_OK, expected.clone())
+        );
+        // Without bounds all the chunks are decompressed, with the same result
+        assert_eq!(
+            query(&laz, 6.0, 12.5, None),
+            (Lazrs_Result::LAZRS_OK, expected)
+        );
+    }
+
+    #[test]
+    fn only_intersecting_chunks_are_decompressed() {
+        let (laz, _) = laz_file();
+        // The points of chunk 2 match, but its bounds say they do not
+        let mut table = chunk_bounds();
+        table.chunks[2].min_x = 1000;
+        table.chunks[2].max_x = 1009;
+        assert_eq!(
+            query(&laz, 6.0, 12.5, Some(&table)),
+            (Lazrs_Result::LAZRS_OK, vec![(12..20).collect()])
+        );
+
+        table.chunks.pop();
+        assert_eq!(
+            query(&laz, 6.0, 12.5, Some(&table)).0,
+            Lazrs_Result::LAZRS_OTHER
+        );
+    }
+}
@@ -0,0 +1,314 @@
+//! Decompression of truncated or damaged LAZ data.
+
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::chunks::{decompress_chunk, is_layered, scan_chunk, ChunkLayout};
+use crate::io::CSource;
+use crate::{Lazrs_DecompressorParams, Lazrs_Result};
+
+/// Result of `lazrs_decompress_recover`
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Lazrs_RecoveryReport {
+    /// Whether the chunk table was missing or damaged,
+    /// and the chunks were found by scanning the data
+    chunk_table_rebuilt: bool,
+    /// Number of chunks found
+    num_chunks: u64,
+    /// Number of chunks whose points could not all be decompressed
+    num_bad_chunks: u64,
+    /// Number of points written in the output buffer
+    num_points_recovered: u64,
+    /// Number of points that could not be recovered,
+    /// among the ones that would have fit in the output buffer
+    num_points_lost: u64,
+}
+
+impl Lazrs_RecoveryReport {
+    fn new() -> Self {
+        Self {
+            chunk_table_rebuilt: false,
+            num_chunks: 0,
+            num_bad_chunks: 0,
+            num_points_recovered: 0,
+            num_points_lost: 0,
+        }
+    }
+}
+
+/// The output buffer, filled point by point
+struct Output<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> Output<'a> {
+    fn is_full(&self) -> bool {
+        self.pos == self.buf.len()
+    }
+
+    /// Copies as much of the points as fits, returns the number of points copied
+    fn push(&mut self, points: &[u8], point_size: usize) -> u64 {
+        let n = points.len().min(self.buf.len() - self.pos) / point_size * point_size;
+        self.buf[self.pos..self.pos + n].copy_from_slice(&points[..n]);
+        self.pos += n;
+        (n / point_size) as u64
+    }
+}
+
+/// Decompresses the chunks listed by the table, skipping the ones that fail,
+/// the points decompressed from a chunk before it failed are kept.
+///
+/// The table must be consistent with the source (see `ChunkLayout::is_consistent`),
+/// so that the byte counts can be used as the size of the buffers.
+fn recover_with_table<R: Read + Seek>(
+    mut src: R,
+    vlr: &laz::LazVlr,
+    layout: &ChunkLayout,
+    num_points: u64,
+    out: &mut Output,
+    report: &mut Lazrs_RecoveryReport,
+) -> std::io::Result<()> {
+    let point_size = vlr.items_size() as usize;
+    let offsets = layout.chunk_offsets();
+    let point_counts = layout.point_counts(vlr, num_points);
+    let mut points = Vec::<u8>::new();
+    for (i, entry) in layout.table.as_ref().iter().enumerate() {
+        if out.is_full() {
+            break;
+        }
+        report.num_chunks += 1;
+
+        let mut data = vec![0u8; entry.byte_count as usize];
+        src.seek(SeekFrom::Start(offsets[i]))?;
+        src.read_exact(&mut data)?;
+
+        points.clear();
+        let result = decompress_chunk(vlr, &data, point_counts[i], |p| points.extend_from_slice(p));
+        report.num_points_recovered += out.push(&points, point_size);
+        if result.is_err() {
+            report.num_bad_chunks += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Finds and decompresses the chunks one after the other,
+/// stops at the first one that cannot be decompressed as its end is unknown
+fn recover_by_scanning<R: Read + Seek + Send>(
+    mut src: R,
+    vlr: &laz::LazVlr,
+    data_start: u64,
+    num_points: u64,
+    out: &mut Output,
+    report: &mut Lazrs_RecoveryReport,
+) -> laz::Result<()> {
+    if vlr.uses_variable_size_chunks() && !is_layered(vlr) {
+        return Err(laz::LasZipError::MissingChunkTable);
+    }
+    let point_size = vlr.items_size() as usize;
+    report.chunk_table_rebuilt = true;
+    src.seek(SeekFrom::Start(data_start))?;
+
+    let mut remaining = num_points;
+    while remaining > 0 && !out.is_full() {
+        let max_points = if vlr.uses_variable_size_chunks() {
+            remaining
+        } else {
+            remaining.min(u64::from(vlr.chunk_size()))
+        };
+
+        let mut decoded = 0u64;
+        let result = scan_chunk(&mut src, vlr, max_points, |p| {
+            decoded += 1;
+            report.num_points_recovered += out.push(p, point_size);
+        });
+        report.num_chunks += 1;
+        remaining -= decoded.min(remaining);
+        if result.is_err() {
+            report.num_bad_chunks += 1;
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Decompresses all the points that can be recovered from truncated or damaged LAZ data
+///
+/// If the chunk table is valid, the chunks that cannot be decompressed are skipped.
+/// If it is missing or does not match the data, the chunks are found by decompressing
+/// them one after the other, until the end of the data or the first damaged chunk.
+/// The points decompressed before the damage are kept.
+/// Without the chunk table, chunks of variable size can only be found for
+/// point formats >= 6.
+///
+/// @params: where the LAZ data is, `source_offset` must be the start of the point data
+/// @num_points: number of points the LAZ data should have (from the LAS header)
+/// @out: buffer that will receive the recovered points
+/// @len: size of the buffer
+/// @report: will receive what was recovered and what was lost, must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_decompress_recover(
+    params: Lazrs_DecompressorParams,
+    num_points: u64,
+    out: *mut u8,
+    len: libc::size_t,
+    report: *mut Lazrs_RecoveryReport,
+) -> Lazrs_Result {
+    debug_assert!(!out.is_null());
+    debug_assert!(!report.is_null());
+    let report = &mut *report;
+    *report = Lazrs_RecoveryReport::new();
+
+    let vlr_data = std::slice::from_raw_parts(params.laszip_vlr.data, params.laszip_vlr.len);
+    let vlr = match laz::LazVlr::from_buffer(vlr_data) {
+        Ok(vlr) => vlr,
+        Err(error) => return error.into(),
+    };
+
+    let mut csource = match CSource::from_c_source(params.source_type, params.source) {
+        Ok(v) => v,
+        Err(result) => return result,
+    };
+
+    let point_size = vlr.items_size() as usize;
+    let mut output = Output {
+        buf: std::slice::from_raw_parts_mut(out, len),
+        pos: 0,
+    };
+
+    let source_end = match csource.seek(SeekFrom::End(0)) {
+        Ok(end) => end,
+        Err(_) => return Lazrs_Result::LAZRS_IO_ERROR,
+    };
+    if csource.seek(SeekFrom::Start(params.source_offset)).is_err() {
+        return Lazrs_Result::LAZRS_IO_ERROR;
+    }
+    let result = match ChunkLayout::read_from(&mut csource, &vlr) {
+        Ok(layout) if layout.is_consistent(source_end) => {
+            recover_with_table(&mut csource, &vlr, &layout, num_points, &mut output, report).into()
+        }
+        _ => {
+            let data_start = params.source_offset + laz::laszip::ChunkTable::OFFSET_SIZE as u64;
+            recover_by_scanning(
+                &mut csource,
+                &vlr,
+                data_start,
+                num_points,
+                &mut output,
+                report,
+            )
+            .into()
+        }
+    };
+
+    let expected = num_points.min((len / point_size) as u64);
+    report.num_points_lost = expected.saturating_sub(report.num_points_recovered);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    use crate::chunks::test_data::{self, points, vlr, POINT_SIZE};
+
+    /// Compresses 25 points in 3 chunks, returns the data and the position of the
+    /// middle of the second chunk
+    fn compressed(vlr: &laz::LazVlr) -> (Vec<u8>, u64) {
+        let data = test_data::compressed(vlr, 25);
+        let layout = ChunkLayout::read_from(Cursor::new(&data), vlr).unwrap();
+        let middle = layout.chunk_offsets()[1] + layout.table.as_ref()[1].byte_count / 2;
+        (data, middle)
+    }
+
+    /// Checks the recovered points are the original ones, except for the end of
+    /// the second chunk that could not be decompressed
+    fn check_recovered(out: &[u8], report: &Lazrs_RecoveryReport, with_last_chunk: bool) {
+        let original = points(25);
+        let recovered = report.num_points_recovered as usize;
+        assert_eq!(report.num_bad_chunks, 1);
+        assert_eq!(report.num_points_lost, 25 - report.num_points_recovered);
+
+        // Some of the points of the damaged chunk were decompressed before the damage
+        let tail = if with_last_chunk { 5 } else { 0 };
+        let from_second_chunk = recovered - 10 - tail;
+        assert!(from_second_chunk > 0 && from_second_chunk < 10);
+        let head = (10 + from_second_chunk) * POINT_SIZE;
+        assert_eq!(out[..head], original[..head]);
+        assert_eq!(
+            out[head..recovered * POINT_SIZE],
+            original[(25 - tail) * POINT_SIZE..]
+        );
+    }
+
+    fn recover(data: &[u8], vlr: &laz::LazVlr) -> (Vec<u8>, Lazrs_RecoveryReport) {
+        let mut buf = vec![0u8; 25 * POINT_SIZE];
+        let mut report = Lazrs_RecoveryReport::new();
+        let mut vlr_data = vec![];
+        vlr.write_to(&mut vlr_data).unwrap();
+        let params = Lazrs_DecompressorParams {
+            source_type: crate::Lazrs_SourceType::LAZRS_SOURCE_BUFFER,
+            source: crate::Lazrs_Source {
+                buffer: crate::Lazrs_Buffer {
+                    data: data.as_ptr(),
+                    len: data.len(),
+                },
+            },
+            source_offset: 0,
+            laszip_vlr: crate::Lazrs_Buffer {
+                data: vlr_data.as_ptr(),
+                len: vlr_data.len(),
+            },
+        };
+        let result = unsafe {
+            lazrs_decompress_recover(params, 25, buf.as_mut_ptr(), buf.len(), &mut report)
+        };
+        assert_eq!(result, Lazrs_Result::LAZRS_OK);
+        (buf, report)
+    }
+
+    #[test]
+    fn damaged_chunk_keeps_its_first_points_with_the_table() {
+        let vlr = vlr();
+        let (mut data, middle) = compressed(&vlr);
+
+        // Remove the end of the second chunk, and update the table accordingly
+        let layout = ChunkLayout::read_from(Cursor::new(&data), &vlr).unwrap();
+        let table_offset = layout.table_offset.unwrap();
+        let removed = layout.chunk_offsets()[2] - middle;
+        data.drain(middle as usize..(middle + removed) as usize);
+        data.truncate((table_offset - removed) as usize);
+        data[..8].copy_from_slice(&(table_offset - removed).to_le_bytes());
+        let mut table = laz::laszip::ChunkTable::with_capacity(3);
+        for (i, entry) in layout.table.as_ref().iter().enumerate() {
+            let mut entry = *entry;
+            if i == 1 {
+                entry.byte_count -= removed;
+            }
+            table.push(entry);
+        }
+        table.write_to(&mut data, &vlr).unwrap();
+        let layout = ChunkLayout::read_from(Cursor::new(&data), &vlr).unwrap();
+        assert!(layout.is_consistent(data.len() as u64));
+
+        let (out, report) = recover(&data, &vlr);
+        assert!(!report.chunk_table_rebuilt);
+        assert_eq!(report.num_chunks, 3);
+        check_recovered(&out, &report, true);
+    }
+
+    #[test]
+    fn damaged_chunk_keeps_its_first_points_when_scanning() {
+        let vlr = vlr();
+        let (mut data, middle) = compressed(&vlr);
+        // The data is cut in the middle of the second chunk, the table is lost
+        data.truncate(middle as usize);
+
+        let (out, report) = recover(&data, &vlr);
+        assert!(report.chunk_table_rebuilt);
+        assert_eq!(report.num_chunks, 2);
+        check_recovered(&out, &report, false);
+    }
+}
@@ -0,0 +1,145 @@
+#[cfg(feature = "parallel")]
+use std::sync::Arc;
+
+#[cfg(feature = "parallel")]
+use crate::Lazrs_Result;
+
+/// A pool of threads that multi-threaded decompressors / compressors can run on,
+/// instead of the global one.
+#[cfg(feature = "parallel")]
+pub struct Lazrs_ThreadPool(Arc<rayon::ThreadPool>);
+
+/// The thread pool a (de)compressor was told to use, if any.
+#[derive(Default)]
+pub(crate) struct PoolSlot(#[cfg(feature = "parallel")] Option<Arc<rayon::ThreadPool>>);
+
+impl PoolSlot {
+    #[cfg(feature = "parallel")]
+    pub(crate) fn set(&mut self, pool: Option<&Lazrs_ThreadPool>) {
+        self.0 = pool.map(|pool| Arc::clone(&pool.0));
+    }
+
+    /// Runs `f` in the pool, or in the caller's context if no pool was set.
+    pub(crate) fn install<R, F>(&self, f: F) -> R
+    where
+        R: Send,
+        F: FnOnce() -> R + Send,
+    {
+        #[cfg(feature = "parallel")]
+        if let Some(pool) = &self.0 {
+            return pool.install(f);
+        }
+        f()
+    }
+}
+
+/// Creates a new thread pool
+///
+/// @num_threads: number of threads in the pool, 0 lets the pool choose
+///               (usually the number of CPUs)
+/// @pool: will receive the pool, or NULL if an error occurred
+#[cfg(feature = "parallel")]
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_thread_pool_new(
+    num_threads: usize,
+    pool: *mut *mut Lazrs_ThreadPool,
+) -> Lazrs_Result {
+    debug_assert!(!pool.is_null());
+    match rayon::ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build()
+    {
+        Ok(p) => {
+            *pool = Box::into_raw(Box::new(Lazrs_ThreadPool(Arc::new(p))));
+            Lazrs_Result::LAZRS_OK
+        }
+        Err(_) => {
+            *pool = std::ptr::null_mut::<Lazrs_ThreadPool>();
+            Lazrs_Result::LAZRS_OTHER
+        }
+    }
+}
+
+/// Frees the thread pool
+///
+/// The threads are stopped once the pool is deleted and
+/// no (de)compressor it was given to uses it anymore.
+///
+/// @pool can be NULL (no-op)
+#[cfg(feature = "parallel")]
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_thread_pool_delete(pool: *mut Lazrs_ThreadPool) {
+    if !pool.is_null() {
+        let _ = Box::from_raw(pool);
+    }
+}
+
+/// Returns the number of threads of the pool
+///
+/// @pool: must not be NULL
+#[cfg(feature = "parallel")]
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_thread_pool_num_threads(pool: *const Lazrs_ThreadPool) -> usize {
+    debug_assert!(!pool.is_null());
+    (*pool).0.current_num_threads()
+}
+
+/// Sets the number of threads of the global pool used by the multi-threaded
+/// (de)compressors that were not given a pool.
+///
+/// This must be called before any multi-threaded work is done,
+/// LAZRS_OTHER is returned if the global pool is already running.
+#[cfg(feature = "parallel")]
+#[no_mangle]
+pub extern "C" fn lazrs_set_num_threads(num_threads: usize) -> Lazrs_Result {
+    match rayon::ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build_global()
+    {
+        Ok(_) => Lazrs_Result::LAZRS_OK,
+        Err(_) => Lazrs_Result::LAZRS_OTHER,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn without_a_pool_f_runs_in_the_callers_context() {
+        let slot = PoolSlot::default();
+        let thread = std::thread::current().id();
+        assert_eq!(slot.install(|| std::thread::current().id()), thread);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn with_a_pool_f_runs_in_it() {
+        let mut pool = std::ptr::null_mut();
+        unsafe {
+            assert_eq!(lazrs_thread_pool_new(3, &mut pool), Lazrs_Result::LAZRS_OK);
+            assert_eq!(lazrs_thread_pool_num_threads(pool), 3);
+        }
+
+        let mut slot = PoolSlot::default();
+        slot.set(unsafe { pool.as_ref() });
+        // The pool is kept alive by the slot
+        unsafe { lazrs_thread_pool_delete(pool) };
+        let (index, num_threads) =
+            slot.install(|| (rayon::current_thread_index(), rayon::current_num_threads()));
+        assert!(index.is_some());
+        assert_eq!(num_threads, 3);
+
+        let items = laz::LazItemRecordBuilder::default_for_point_format_id(0, 0).unwrap();
+        let vlr = laz::LazVlrBuilder::new(items)
+            .with_fixed_chunk_size(10)
+            .build();
+        assert_eq!(
+            slot.install(|| crate::cancel::points_per_step(&vlr, true)),
+            30
+        );
+
+        slot.set(None);
+        assert!(slot.install(rayon::current_thread_index).is_none());
+    }
+}
@@ -0,0 +1,1041 @@
+//! Conversions of whole LAS / LAZ files.
+
+use std::io::{Read, Seek, Write};
+
+use crate::cancel::{points_per_step, DEFAULT_POINTS_PER_STEP};
+use crate::compatibility::Compatibility;
+use crate::io::{CDest, CSource};
+use crate::las::{LasMetadata, GLOBAL_ENCODING_WKT};
+use crate::point::{convert_points, PointFormat};
+use crate::{
+    Compressor, Decompressor, Lazrs_Dest, Lazrs_DestType, Lazrs_Result, Lazrs_Source,
+    Lazrs_SourceType,
+};
+
+/// Options of `lazrs_las_to_laz`
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Lazrs_LasToLazOptions {
+    /// Number of points per chunk, 0 to use the default chunk size
+    chunk_size: u32,
+    /// Whether to compress using multiple threads, if possible
+    prefer_parallel: bool,
+    /// Whether to write the points of formats 6 to 8 in LASzip's compatibility mode,
+    /// as points of formats 1 and 3 that older LASzip readers can read
+    compatibility_mode: bool,
+}
+
+/// Options of `lazrs_rechunk`
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Lazrs_RechunkOptions {
+    /// Number of points per chunk, 0 to use the default chunk size,
+    /// ignored if `chunk_sizes` is not NULL
+    chunk_size: u32,
+    /// Number of points of each chunk, to write variable-size chunks, can be NULL.
+    /// The sizes must not be 0, and must add up to the number of points of the file.
+    chunk_sizes: *const u64,
+    /// Number of elements in `chunk_sizes`
+    num_chunk_sizes: usize,
+    /// Whether to decompress and compress using multiple threads, if possible
+    prefer_parallel: bool,
+}
+
+/// Where the points of a file are read from
+pub(crate) enum PointReader {
+    Las(CSource<'static>),
+    Laz(Box<Decompressor>),
+}
+
+impl PointReader {
+    pub(crate) fn read(&mut self, points: &mut [u8]) -> Result<(), Lazrs_Result> {
+        match self {
+            PointReader::Las(src) => src.read_exact(points)?,
+            PointReader::Laz(decompressor) => decompressor.decompress_many(points)?,
+        }
+        Ok(())
+    }
+}
+
+/// Where the points of a file are written to
+enum PointWriter {
+    Las(CDest),
+    Laz(Box<Compressor>),
+}
+
+impl PointWriter {
+    fn write(&mut self, points: &[u8]) -> Result<(), Lazrs_Result> {
+        match self {
+            PointWriter::Las(dest) => dest.write_all(points)?,
+            PointWriter::Laz(compressor) => compressor.compress_many(points)?,
+        }
+        Ok(())
+    }
+
+    /// Writes what remains, returns the destination positioned after the points
+    fn finish(self) -> Result<CDest, Lazrs_Result> {
+        match self {
+            PointWriter::Las(dest) => Ok(dest),
+            PointWriter::Laz(mut compressor) => {
+                compressor.done()?;
+                Ok(compressor.into_inner())
+            }
+        }
+    }
+}
+
+pub(crate) unsafe fn open(
+    source_type: Lazrs_SourceType,
+    source: Lazrs_Source,
+    dest_type: Lazrs_DestType,
+    dest: Lazrs_Dest,
+) -> Result<(CSource<'static>, CDest), Lazrs_Result> {
+    let csource = CSource::from_c_source(source_type, source)?;
+    let cdest = CDest::from_c_dest(dest_type, dest)?;
+    Ok((csource, cdest))
+}
+
+/// Reads the metadata of a LAZ file, and its LASzip VLR
+pub(crate) fn read_laz_metadata<R: Read + Seek>(
+    src: &mut R,
+) -> Result<(LasMetadata, laz::LazVlr), Lazrs_Result> {
+    let metadata = LasMetadata::read_from(src)?;
+    match metadata.laszip_vlr() {
+        Some(vlr) => Ok((metadata, vlr?)),
+        None => Err(Lazrs_Result::LAZRS_OTHER),
+    }
+}
+
+fn laz_to_las(
+    mut src: CSource<'static>,
+    mut dest: CDest,
+    prefer_parallel: bool,
+) -> Result<(), Lazrs_Result> {
+    let (mut metadata, vlr) = read_laz_metadata(&mut src)?;
+    let compatibility = Compatibility::undo(&mut metadata);
+    let num_points = metadata.header.number_of_points();
+    let point_size = vlr.items_size() as usize;
+
+    metadata.set_laszip_vlr(None)?;
+    let point_format_id = metadata.header.point_format_id();
+    metadata.header.set_point_format(point_format_id, false);
+
+    let start = dest.stream_position()?;
+    metadata.write_head_to(&mut dest)?;
+
+    let mut decompressor = Decompressor::new(src, vlr.clone(), prefer_parallel)?;
+    let step = points_per_step(&vlr, decompressor.is_parallel()) as u64;
+    let mut points = vec![0u8; step.min(num_points) as usize * point_size];
+    let native_point_size = compatibility
+        .as_ref()
+        .map_or(0, |compatibility| compatibility.native_point_size);
+    let mut native_points = vec![0u8; step.min(num_points) as usize * native_point_size];
+    let mut remaining = num_points;
+    while remaining > 0 {
+        let n = step.min(remaining) as usize;
+        decompressor.decompress_many(&mut points[..n * point_size])?;
+        match &compatibility {
+            Some(compatibility) => {
+                let native_points = &mut native_points[..n * native_point_size];
+                compatibility.to_native(&points[..n * point_size], native_points);
+                dest.write_all(native_points)?;
+            }
+            None => dest.write_all(&points[..n * point_size])?,
+        }
+        remaining -= n as u64;
+    }
+
+    metadata.write_evlrs_to(&mut dest, start)?;
+    Ok(())
+}
+
+fn las_to_laz(
+    mut src: CSource<'static>,
+    mut dest: CDest,
+    options: &Lazrs_LasToLazOptions,
+) -> Result<(), Lazrs_Result> {
+    let mut metadata = LasMetadata::read_from(&mut src)?;
+    if metadata.header.is_compressed() || metadata.laszip_vlr().is_some() {
+        return Err(Lazrs_Result::LAZRS_OTHER);
+    }
+    let compatibility = if options.compatibility_mode {
+        Compatibility::enter(&mut metadata)?
+    } else {
+        None
+    };
+    let point_format_id = metadata.header.point_format_id();
+    let num_extra_bytes = metadata
+        .header
+        .num_extra_bytes()
+        .ok_or(Lazrs_Result::LAZRS_UNSUPPORTED_POINT_FORMAT)?;
+    let mut builder =
+        laz::LazVlrBuilder::default().with_point_format(point_format_id, num_extra_bytes)?;
+    if options.chunk_size != 0 {
+        builder = builder.with_fixed_chunk_size(options.chunk_size);
+    }
+    let vlr = builder.build();
+    let num_points = metadata.header.number_of_points();
+    let point_size = vlr.items_size() as usize;
+
+    metadata.set_laszip_vlr(Some(&vlr))?;
+    metadata.header.set_point_format(point_format_id, true);
+
+    let start = dest.stream_position()?;
+    metadata.write_head_to(&mut dest)?;
+
+    let mut compressor = Compressor::new(dest, vlr.clone(), options.prefer_parallel)?;
+    let step = points_per_step(&vlr, compressor.is_parallel()) as u64;
+    let mut points = vec![0u8; step.min(num_points) as usize * point_size];
+    let native_point_size = compatibility
+        .as_ref()
+        .map_or(0, |compatibility| compatibility.native_point_size);
+    let mut native_points = vec![0u8; step.min(num_points) as usize * native_point_size];
+    let mut remaining = num_points;
+    while remaining > 0 {
+        let n = step.min(remaining) as usize;
+        match &compatibility {
+            Some(compatibility) => {
+                let native_points = &mut native_points[..n * native_point_size];
+                src.read_exact(native_points)?;
+                compatibility.to_legacy(native_points, &mut points[..n * point_size]);
+            }
+            None => src.read_exact(&mut points[..n * point_size])?,
+        }
+        compressor.compress_many(&points[..n * point_size])?;
+        remaining -= n as u64;
+    }
+    compressor.done()?;
+
+    let mut dest = compressor.into_inner();
+    metadata.write_evlrs_to(&mut dest, start)?;
+    Ok(())
+}
+
+fn rechunk(
+    mut src: CSource<'static>,
+    mut dest: CDest,
+    chunk_size: u32,
+    chunk_sizes: Option<&[u64]>,
+    prefer_parallel: bool,
+) -> Result<(), Lazrs_Result> {
+    let (mut metadata, src_vlr) = read_laz_metadata(&mut src)?;
+    let num_points = metadata.header.number_of_points();
+
+    let builder = laz::LazVlrBuilder::new(src_vlr.items().clone());
+    let vlr = match chunk_sizes {
+        Some(sizes) => {
+            // Chunks cannot be empty
+            if sizes.contains(&0) {
+                return Err(Lazrs_Result::LAZRS_OTHER);
+            }
+            let total = sizes
+                .iter()
+                .try_fold(0u64, |total, &size| total.checked_add(size));
+            if total != Some(num_points) {
+                return Err(Lazrs_Result::LAZRS_OTHER);
+            }
+            builder.with_variable_chunk_size().build()
+        }
+        None if chunk_size != 0 => builder.with_fixed_chunk_size(chunk_size).build(),
+        None => builder.build(),
+    };
+    let point_size = vlr.items_size() as usize;
+
+    metadata.set_laszip_vlr(Some(&vlr))?;
+    let start = dest.stream_position()?;
+    metadata.write_head_to(&mut dest)?;
+
+    let mut decompressor = Decompressor::new(src, src_vlr.clone(), prefer_parallel)?;
+    let mut compressor = Compressor::new_for_chunks(dest, vlr.clone(), prefer_parallel)?;
+    let step = points_per_step(&src_vlr, decompressor.is_parallel())
+        .max(points_per_step(&vlr, compressor.is_parallel())) as u64;
+    let mut points = Vec::<u8>::new();
+    match chunk_sizes {
+        None => {
+            let mut remaining = num_points;
+            points.resize(step.min(num_points) as usize * point_size, 0);
+            while remaining > 0 {
+                let n = step.min(remaining) as usize;
+                decompressor.decompress_many(&mut points[..n * point_size])?;
+                compressor.compress_many(&points[..n * point_size])?;
+                remaining -= n as u64;
+            }
+        }
+        Some(sizes) => {
+            let mut sizes = sizes.iter().peekable();
+            while sizes.peek().is_some() {
+                // Whole chunks are given to the compressor, as many as fit in a step
+                let mut batch = Vec::<usize>::new();
+                let mut n = 0u64;
+                while let Some(size) = sizes.next_if(|_| n < step) {
+                    batch.push(*size as usize);
+                    n += size;
+                }
+                points.resize(n as usize * point_size, 0);
+                decompressor.decompress_many(&mut points)?;
+
+                let mut rest = points.as_slice();
+                let chunks = batch
+                    .iter()
+                    .map(|count| {
+                        let (chunk, r) = rest.split_at(count * point_size);
+                        rest = r;
+                        chunk
+                    })
+                    .collect();
+                compressor.compress_chunks(chunks, sizes.peek().is_none())?;
+            }
+        }
+    }
+    compressor.done()?;
+
+    let mut dest = compressor.into_inner();
+    metadata.write_evlrs_to(&mut dest, start)?;
+    Ok(())
+}
+
+fn convert_point_format(
+    mut src: CSource<'static>,
+    mut dest: CDest,
+    target_format_id: u8,
+    prefer_parallel: bool,
+) -> Result<(), Lazrs_Result> {
+    let mut metadata = LasMetadata::read_from(&mut src)?;
+    let src_vlr = metadata.laszip_vlr().transpose()?;
+    // The points of a file in compatibility mode are converted from their LAS 1.4 format
+    let compatibility = match src_vlr {
+        Some(_) => Compatibility::undo(&mut metadata),
+        None => None,
+    };
+    let src_format = PointFormat::new(metadata.header.point_format_id())
+        .ok_or(Lazrs_Result::LAZRS_UNSUPPORTED_POINT_FORMAT)?;
+    let dst_format =
+        PointFormat::new(target_format_id).ok_or(Lazrs_Result::LAZRS_UNSUPPORTED_POINT_FORMAT)?;
+    let num_extra_bytes = metadata
+        .header
+        .num_extra_bytes()
+        .ok_or(Lazrs_Result::LAZRS_UNSUPPORTED_POINT_FORMAT)?;
+    let num_points = metadata.header.number_of_points();
+    let by_return = metadata.header.number_of_points_by_return();
+
+    let dst_vlr = match &src_vlr {
+        Some(src_vlr) => {
+            let builder = laz::LazVlrBuilder::default()
+                .with_point_format(target_format_id, num_extra_bytes)?;
+            if src_vlr.uses_variable_size_chunks() {
+                Some(builder.build())
+            } else {
+                Some(builder.with_fixed_chunk_size(src_vlr.chunk_size()).build())
+            }
+        }
+        None => None,
+    };
+
+    if dst_format.is_extended {
+        metadata.header.upgrade_to_1_4()?;
+        let global_encoding = metadata.header.global_encoding();
+        metadata
+            .header
+            .set_global_encoding(global_encoding | GLOBAL_ENCODING_WKT);
+    }
+    metadata
+        .header
+        .set_point_format(target_format_id, dst_vlr.is_some());
+    metadata
+        .header
+        .set_point_size(dst_format.size as u16 + num_extra_bytes);
+    metadata.header.set_number_of_points(num_points, &by_return);
+    metadata.set_laszip_vlr(dst_vlr.as_ref())?;
+
+    let start = dest.stream_position()?;
+    metadata.write_head_to(&mut dest)?;
+
+    let mut step = DEFAULT_POINTS_PER_STEP;
+    let mut reader = match src_vlr {
+        Some(vlr) => {
+            let decompressor = Decompressor::new(src, vlr.clone(), prefer_parallel)?;
+            step = step.max(points_per_step(&vlr, decompressor.is_parallel()));
+            PointReader::Laz(Box::new(decompressor))
+        }
+        None => PointReader::Las(src),
+    };
+    let mut writer = match dst_vlr {
+        Some(vlr) => {
+            let compressor = Compressor::new(dest, vlr.clone(), prefer_parallel)?;
+            step = step.max(points_per_step(&vlr, compressor.is_parallel()));
+            PointWriter::Laz(Box::new(compressor))
+        }
+        None => PointWriter::Las(dest),
+    };
+
+    let num_extra_bytes = usize::from(num_extra_bytes);
+    let src_point_size = src_format.size + num_extra_bytes;
+    let dst_point_size = dst_format.size + num_extra_bytes;
+    let step = step as u64;
+    let mut src_points = vec![0u8; step.min(num_points) as usize * src_point_size];
+    let mut dst_points = vec![0u8; step.min(num_points) as usize * dst_point_size];
+    let legacy_point_size = compatibility
+        .as_ref()
+        .map_or(0, |compatibility| compatibility.legacy_point_size);
+    let mut legacy_points = vec![0u8; step.min(num_points) as usize * legacy_point_size];
+    let mut remaining = num_points;
+    while remaining > 0 {
+        let n = step.min(remaining) as usize;
+        match &compatibility {
+            Some(compatibility) => {
+                let legacy_points = &mut legacy_points[..n * legacy_point_size];
+                reader.read(legacy_points)?;
+                compatibility.to_native(legacy_points, &mut src_points[..n * src_point_size]);
+            }
+            None => reader.read(&mut src_points[..n * src_point_size])?,
+        }
+        convert_points(
+            &src_format,
+            &src_points[..n * src_point_size],
+            &dst_format,
+            &mut dst_points[..n * dst_point_size],
+            num_extra_bytes,
+        );
+        writer.write(&dst_points[..n * dst_point_size])?;
+        remaining -= n as u64;
+    }
+
+    let mut dest = writer.finish()?;
+    metadata.write_evlrs_to(&mut dest, start)?;
+    Ok(())
+}
+
+/// Converts a LAZ file to an uncompressed LAS file
+///
+/// The header, VLRs and EVLRs are copied, without the LASzip VLR,
+/// and the points are decompressed.
+/// Files written in LASzip's compatibility mode get back their point format (6 to 8),
+/// LAS 1.4 header and extra bytes (see `Lazrs_CompatibilityMode` for the other functions).
+///
+/// The file is read from the current position of the source,
+/// and written at the current position of the destination.
+///
+/// @source_type: type of the source
+/// @source: where the LAZ file is read from
+/// @dest_type: type of the destination
+/// @dest: where the LAS file is written
+/// @prefer_parallel: whether to decompress using multiple threads, if possible
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_laz_to_las(
+    source_type: Lazrs_SourceType,
+    source: Lazrs_Source,
+    dest_type: Lazrs_DestType,
+    dest: Lazrs_Dest,
+    prefer_parallel: bool,
+) -> Lazrs_Result {
+    let (csource, cdest) = match open(source_type, source, dest_type, dest) {
+        Ok(v) => v,
+        Err(result) => return result,
+    };
+    match laz_to_las(csource, cdest, prefer_parallel) {
+        Ok(()) => Lazrs_Result::LAZRS_OK,
+        Err(result) => result,
+    }
+}
+
+/// Converts an uncompressed LAS file to a LAZ file
+///
+/// The header, VLRs and EVLRs are copied, the LASzip VLR matching the
+/// point format and number of extra bytes of the file is added,
+/// and the points are compressed.
+///
+/// In compatibility mode, points of formats 6 to 8 are compressed as points
+/// of formats 1 or 3, with the fields that do not fit in extra bytes,
+/// and the header becomes a LAS 1.2 one whose LAS 1.4 fields are kept in a VLR,
+/// like LASzip does.
+/// The EVLRs then become VLRs, LAZRS_OTHER is returned if one is too large for that.
+/// Points of other formats are compressed as usual.
+///
+/// The file is read from the current position of the source,
+/// and written at the current position of the destination.
+/// LAZRS_OTHER is returned if the source is already compressed.
+///
+/// @source_type: type of the source
+/// @source: where the LAS file is read from
+/// @dest_type: type of the destination
+/// @dest: where the LAZ file is written
+/// @options: how to compress the points
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_las_to_laz(
+    source_type: Lazrs_SourceType,
+    source: Lazrs_Source,
+    dest_type: Lazrs_DestType,
+    dest: Lazrs_Dest,
+    options: Lazrs_LasToLazOptions,
+) -> Lazrs_Result {
+    let (csource, cdest) = match open(source_type, source, dest_type, dest) {
+        Ok(v) => v,
+        Err(result) => return result,
+    };
+    match las_to_laz(csource, cdest, &options) {
+        Ok(()) => Lazrs_Result::LAZRS_OK,
+        Err(result) => result,
+    }
+}
+
+/// Rewrites a LAZ file with a new chunk size, or with chunks of variable size
+///
+/// The header, VLRs (with an updated LASzip VLR) and EVLRs are copied,
+/// the points are decompressed and compressed again in the new chunks.
+///
+/// The file is read from the current position of the source,
+/// and written at the current position of the destination.
+/// LAZRS_OTHER is returned if the source is not compressed,
+/// or if the `chunk_sizes` of the options have a 0 or do not add up to its number of points.
+///
+/// @source_type: type of the source
+/// @source: where the LAZ file is read from
+/// @dest_type: type of the destination
+/// @dest: where the new LAZ file is written
+/// @options: the new chunks
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_rechunk(
+    source_type: Lazrs_SourceType,
+    source: Lazrs_Source,
+    dest_type: Lazrs_DestType,
+    dest: Lazrs_Dest,
+    options: Lazrs_RechunkOptions,
+) -> Lazrs_Result {
+    let chunk_sizes = if options.chunk_sizes.is_null() {
+        None
+    } else {
+        Some(std::slice::from_raw_parts(
+            options.chunk_sizes,
+            options.num_chunk_sizes,
+        ))
+    };
+    let (csource, cdest) = match open(source_type, source, dest_type, dest) {
+        Ok(v) => v,
+        Err(result) => return result,
+    };
+    match rechunk(
+        csource,
+        cdest,
+        options.chunk_size,
+        chunk_sizes,
+        options.prefer_parallel,
+    ) {
+        Ok(()) => Lazrs_Result::LAZRS_OK,
+        Err(result) => result,
+    }
+}
+
+/// Converts the points of a LAS or LAZ file to another point format
+///
+/// The fields are mapped between the formats: the return numbers,
+/// classification and flags are repacked, the scan angle rank
+/// becomes a scan angle (and back), and fields the source format does not
+/// have (GPS time, RGB, NIR, wave packet) are set to 0.
+/// Points of formats 0 - 5 classified as overlap (12) get the overlap flag
+/// in formats >= 6, and the other way around.
+/// The extra bytes are kept.
+///
+/// The header is updated, and upgraded to LAS 1.4 with the WKT bit of
+/// the global encoding set for formats >= 6.
+/// The VLRs and EVLRs are copied.
+/// A LAZ file stays compressed, with fixed-size chunks.
+/// The points of a LAZ file in LASzip's compatibility mode are converted
+/// from their LAS 1.4 format, the converted file is not in compatibility mode.
+///
+/// The file is read from the current position of the source,
+/// and written at the current position of the destination.
+///
+/// @source_type: type of the source
+/// @source: where the LAS / LAZ file is read from
+/// @dest_type: type of the destination
+/// @dest: where the converted file is written
+/// @target_format_id: the point format of the converted file
+/// @prefer_parallel: whether to use multiple threads for LAZ files, if possible
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_convert_point_format(
+    source_type: Lazrs_SourceType,
+    source: Lazrs_Source,
+    dest_type: Lazrs_DestType,
+    dest: Lazrs_Dest,
+    target_format_id: u8,
+    prefer_parallel: bool,
+) -> Lazrs_Result {
+    let (csource, cdest) = match open(source_type, source, dest_type, dest) {
+        Ok(v) => v,
+        Err(result) => return result,
+    };
+    match convert_point_format(csource, cdest, target_format_id, prefer_parallel) {
+        Ok(()) => Lazrs_Result::LAZRS_OK,
+        Err(result) => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read, SeekFrom};
+
+    use crate::copc::{lazrs_las_to_copc, Lazrs_LasToCopcOptions};
+    use crate::io::CFile;
+    use crate::las::Vlr;
+    use crate::point::Lazrs_Point;
+    use crate::Lazrs_Buffer;
+
+    /// Size of the LAS 1.4 header
+    const HEADER_SIZE: usize = 375;
+    /// Format 0 with 3 extra bytes
+    const RECORD_LENGTH: usize = 23;
+
+    /// Records of format 0 that are all different, with 3 extra bytes
+    fn records(count: usize) -> Vec<u8> {
+        (0..count * RECORD_LENGTH)
+            .map(|i| (i * 7 + i / RECORD_LENGTH) as u8)
+            .collect()
+    }
+
+    /// A LAS 1.4 file with a VLR before the points and an EVLR after them
+    fn las_file(point_format_id: u8, record_length: usize, points: &[u8]) -> Vec<u8> {
+        let vlr = Vlr::new("test", 1, "a vlr", vec![1, 2, 3, 4]);
+        let mut evlr = Vlr::new("test", 2, "an evlr", vec![5; 10]);
+        evlr.extended = true;
+        let num_points = (points.len() / record_length) as u64;
+        let offset_to_point_data = HEADER_SIZE as u64 + vlr.size();
+
+        let mut data = vec![0u8; HEADER_SIZE];
+        data[..4].copy_from_slice(b"LASF");
+        data[24..26].copy_from_slice(&[1, 4]);
+        data[94..96].copy_from_slice(&(HEADER_SIZE as u16).to_le_bytes());
+        data[96..100].copy_from_slice(&(offset_to_point_data as u32).to_le_bytes());
+        data[100..104].copy_from_slice(&1u32.to_le_bytes());
+        data[104] = point_format_id;
+        data[105..107].copy_from_slice(&(record_length as u16).to_le_bytes());
+        if point_format_id < 6 {
+            data[107..111].copy_from_slice(&(num_points as u32).to_le_bytes());
+        }
+        let evlrs_start = offset_to_point_data + points.len() as u64;
+        data[235..243].copy_from_slice(&evlrs_start.to_le_bytes());
+        data[243..247].copy_from_slice(&1u32.to_le_bytes());
+        data[247..255].copy_from_slice(&num_points.to_le_bytes());
+
+        vlr.write_to(&mut data).unwrap();
+        data.extend_from_slice(points);
+        evlr.write_to(&mut data).unwrap();
+        data
+    }
+
+    /// Compresses the LAS file with laz directly
+    fn laz_file(las: &[u8], vlr: laz::LazVlr) -> Vec<u8> {
+        let mut src = Cursor::new(las);
+        let mut metadata = LasMetadata::read_from(&mut src).unwrap();
+        let point_size = vlr.items_size() as usize;
+        let points = &las[src.position() as usize..]
+            [..metadata.header.number_of_points() as usize * point_size];
+
+        metadata.set_laszip_vlr(Some(&vlr)).unwrap();
+        let point_format_id = metadata.header.point_format_id();
+        metadata.header.set_point_format(point_format_id, true);
+        let mut dst = Cursor::new(vec![]);
+        metadata.write_head_to(&mut dst).unwrap();
+        laz::compress_buffer(&mut dst, points, vlr).unwrap();
+        metadata.write_evlrs_to(&mut dst, 0).unwrap();
+        dst.into_inner()
+    }
+
+    fn buffer_source(data: &[u8]) -> Lazrs_Source {
+        Lazrs_Source {
+            buffer: Lazrs_Buffer {
+                data: data.as_ptr(),
+                len: data.len(),
+            },
+        }
+    }
+
+    /// Calls `f` with a temporary file as destination,
+    /// returns its result and what was written to the file
+    fn written_to_file(f: impl FnOnce(Lazrs_Dest) -> Lazrs_Result) -> (Lazrs_Result, Vec<u8>) {
+        unsafe {
+            let fh = libc::tmpfile();
+            assert!(!fh.is_null());
+            let result = f(Lazrs_Dest { file: fh });
+            let mut file = CFile::new_unchecked(fh);
+            let mut data = vec![];
+            file.seek(SeekFrom::Start(0)).unwrap();
+            file.read_to_end(&mut data).unwrap();
+            libc::fclose(fh);
+            (result, data)
+        }
+    }
+
+    fn laz_to_las_c(laz: &[u8], prefer_parallel: bool) -> (Lazrs_Result, Vec<u8>) {
+        written_to_file(|dest| unsafe {
+            lazrs_laz_to_las(
+                Lazrs_SourceType::LAZRS_SOURCE_BUFFER,
+                buffer_source(laz),
+                Lazrs_DestType::LAZRS_DEST_CFILE,
+                dest,
+                prefer_parallel,
+            )
+        })
+    }
+
+    #[test]
+    fn laz_to_las_gives_the_original_file() {
+        let las = las_file(0, RECORD_LENGTH, &records(2500));
+        let items = laz::LazItemRecordBuilder::default_for_point_format_id(0, 3).unwrap();
+        let laz = laz_file(
+            &las,
+            laz::LazVlrBuilder::new(items)
+                .with_fixed_chunk_size(1000)
+                .build(),
+        );
+        assert_ne!(laz, las);
+
+        for prefer_parallel in [false, true] {
+            let (result, decompressed) = laz_to_las_c(&laz, prefer_parallel);
+            assert_eq!(result, Lazrs_Result::LAZRS_OK);
+            assert_eq!(decompressed, las);
+        }
+    }
+
+    #[test]
+    fn las_file_is_not_decompressed() {
+        let las = las_file(0, RECORD_LENGTH, &records(10));
+        let (result, _) = laz_to_las_c(&las, false);
+        assert_eq!(result, Lazrs_Result::LAZRS_OTHER);
+    }
+
+    fn las_to_laz_with_options_c(
+        las: &[u8],
+        options: Lazrs_LasToLazOptions,
+    ) -> (Lazrs_Result, Vec<u8>) {
+        written_to_file(|dest| unsafe {
+            lazrs_las_to_laz(
+                Lazrs_SourceType::LAZRS_SOURCE_BUFFER,
+                buffer_source(las),
+                Lazrs_DestType::LAZRS_DEST_CFILE,
+                dest,
+                options,
+            )
+        })
+    }
+
+    fn las_to_laz_c(las: &[u8], chunk_size: u32, prefer_parallel: bool) -> (Lazrs_Result, Vec<u8>) {
+        las_to_laz_with_options_c(
+            las,
+            Lazrs_LasToLazOptions {
+                chunk_size,
+                prefer_parallel,
+                compatibility_mode: false,
+            },
+        )
+    }
+
+    #[test]
+    fn las_to_laz_to_las_gives_the_same_records() {
+        let las = las_file(0, RECORD_LENGTH, &records(2500));
+        for prefer_parallel in [false, true] {
+            let (result, laz) = las_to_laz_c(&las, 1000, prefer_parallel);
+            assert_eq!(result, Lazrs_Result::LAZRS_OK);
+
+            let metadata = LasMetadata::read_from(&mut Cursor::new(&laz)).unwrap();
+            assert!(metadata.header.is_compressed());
+            assert_eq!(metadata.header.point_format_id(), 0);
+            assert_eq!(metadata.evlrs.len(), 1);
+            let vlr = metadata.laszip_vlr().unwrap().unwrap();
+            assert_eq!(vlr.chunk_size(), 1000);
+            assert_eq!(vlr.items_size(), RECORD_LENGTH as u64);
+
+            let (result, decompressed) = laz_to_las_c(&laz, prefer_parallel);
+            assert_eq!(result, Lazrs_Result::LAZRS_OK);
+            assert_eq!(decompressed, las);
+        }
+    }
+
+    #[test]
+    fn laz_file_is_not_compressed_again() {
+        let las = las_file(0, RECORD_LENGTH, &records(10));
+        let (_, laz) = las_to_laz_c(&las, 0, false);
+        let (result, _) = las_to_laz_c(&laz, 0, false);
+        assert_eq!(result, Lazrs_Result::LAZRS_OTHER);
+    }
+
+    fn rechunk_c(
+        laz: &[u8],
+        chunk_size: u32,
+        chunk_sizes: &[u64],
+        prefer_parallel: bool,
+    ) -> (Lazrs_Result, Vec<u8>) {
+        written_to_file(|dest| unsafe {
+            lazrs_rechunk(
+                Lazrs_SourceType::LAZRS_SOURCE_BUFFER,
+                buffer_source(laz),
+                Lazrs_DestType::LAZRS_DEST_CFILE,
+                dest,
+                Lazrs_RechunkOptions {
+                    chunk_size,
+                    chunk_sizes: if chunk_sizes.is_empty() {
+                        std::ptr::null()
+                    } else {
+                        chunk_sizes.as_ptr()
+                    },
+                    num_chunk_sizes: chunk_sizes.len(),
+                    prefer_parallel,
+                },
+            )
+        })
+    }
+
+    /// Returns the LASzip VLR and the chunk table of a LAZ file
+    fn chunks_of(laz: &[u8]) -> (laz::LazVlr, Vec<laz::laszip::ChunkTableEntry>) {
+        let mut src = Cursor::new(laz);
+        let metadata = LasMetadata::read_from(&mut src).unwrap();
+        let vlr = metadata.laszip_vlr().unwrap().unwrap();
+        let table = laz::laszip::ChunkTable::read_from(&mut src, &vlr).unwrap();
+        (vlr, table.as_ref().to_vec())
+    }
+
+    #[test]
+    fn rechunk_keeps_the_points() {
+        let las = las_file(0, RECORD_LENGTH, &records(2500));
+        let (_, laz) = las_to_laz_c(&las, 1000, false);
+
+        let (result, fixed) = rechunk_c(&laz, 300, &[], false);
+        assert_eq!(result, Lazrs_Result::LAZRS_OK);
+        let (vlr, table) = chunks_of(&fixed);
+        assert_eq!(vlr.chunk_size(), 300);
+        assert_eq!(table.len(), 9);
+        assert_eq!(laz_to_las_c(&fixed, false).1, las);
+
+        let (result, variable) = rechunk_c(&laz, 0, &[1000, 1, 1499], false);
+        assert_eq!(result, Lazrs_Result::LAZRS_OK);
+        let (vlr, table) = chunks_of(&variable);
+        assert!(vlr.uses_variable_size_chunks());
+        assert_eq!(
+            table.iter().map(|e| e.point_count).collect::<Vec<_>>(),
+            vec![1000, 1, 1499]
+        );
+        assert_eq!(laz_to_las_c(&variable, false).1, las);
+    }
+
+    #[test]
+    fn rechunk_rejects_bad_chunk_sizes() {
+        let las = las_file(0, RECORD_LENGTH, &records(10));
+        let (_, laz) = las_to_laz_c(&las, 0, false);
+        for sizes in [&[5, 0, 5][..], &[5, 4], &[5, 6], &[u64::MAX, 11]] {
+            let (result, _) = rechunk_c(&laz, 0, sizes, false);
+            assert_eq!(result, Lazrs_Result::LAZRS_OTHER);
+        }
+        let (result, _) = rechunk_c(&las, 5, &[], false);
+        assert_eq!(result, Lazrs_Result::LAZRS_OTHER);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_rechunk_writes_variable_size_chunks() {
+        let las = las_file(0, RECORD_LENGTH, &records(2500));
+        let (_, laz) = las_to_laz_c(&las, 1000, true);
+
+        let sizes = [700, 1, 1000, 799];
+        let (result, variable) = rechunk_c(&laz, 0, &sizes, true);
+        assert_eq!(result, Lazrs_Result::LAZRS_OK);
+        let (vlr, table) = chunks_of(&variable);
+        assert!(vlr.uses_variable_size_chunks());
+        assert_eq!(
+            table.iter().map(|e| e.point_count).collect::<Vec<_>>(),
+            sizes
+        );
+        assert_eq!(laz_to_las_c(&variable, true).1, las);
+    }
+
+    fn convert_c(file: &[u8], target_format_id: u8) -> (Lazrs_Result, Vec<u8>) {
+        written_to_file(|dest| unsafe {
+            lazrs_convert_point_format(
+                Lazrs_SourceType::LAZRS_SOURCE_BUFFER,
+                buffer_source(file),
+                Lazrs_DestType::LAZRS_DEST_CFILE,
+                dest,
+                target_format_id,
+                false,
+            )
+        })
+    }
+
+    /// Points of format 3 with 3 extra bytes
+    fn format_3_records(count: usize) -> Vec<u8> {
+        let format = PointFormat::new(3).unwrap();
+        let mut records = vec![0u8; count * (format.size + 3)];
+        for (i, record) in records.chunks_exact_mut(format.size + 3).enumerate() {
+            let point = Lazrs_Point {
+                x: i as i32 * 10,
+                y: -(i as i32),
+                z: 1000 + i as i32,
+                intensity: i as u16 * 3,
+                return_number: (i % 3) as u8 + 1,
+                number_of_returns: 3,
+                scan_direction_flag: i % 2 == 0,
+                classification: (i % 10) as u8,
+                key_point: i % 5 == 0,
+                user_data: i as u8,
+                scan_angle: (i % 20) as f32,
+                point_source_id: 7,
+                gps_time: 1000.0 + i as f64 * 0.25,
+                red: i as u16,
+                green: 2 * i as u16,
+                blue: 3 * i as u16,
+                ..Default::default()
+            };
+            point.write_to(&format, record);
+            record[format.size..].copy_from_slice(&[i as u8, 1, 2]);
+        }
+        records
+    }
+
+    /// Returns the header and the records of a LAS file
+    fn read_las(las: &[u8]) -> (LasMetadata, Vec<u8>) {
+        let mut src = Cursor::new(las);
+        let metadata = LasMetadata::read_from(&mut src).unwrap();
+        let size =
+            metadata.header.number_of_points() as usize * usize::from(metadata.header.point_size());
+        let start = src.position() as usize;
+        (metadata, las[start..start + size].to_vec())
+    }
+
+    fn shared_fields(point: &Lazrs_Point) -> impl PartialEq + std::fmt::Debug {
+        (
+            (point.x, point.y, point.z, point.intensity),
+            (point.return_number, point.number_of_returns),
+            (
+                point.scan_direction_flag,
+                point.classification,
+                point.key_point,
+            ),
+            (
+                point.user_data,
+                point.scan_angle.round() as i32,
+                point.point_source_id,
+            ),
+            (point.gps_time.to_bits(), point.red, point.green, point.blue),
+        )
+    }
+
+    fn check_converted(original: &[u8], converted: &[u8]) {
+        let (src_format, dst_format) = (PointFormat::new(3).unwrap(), PointFormat::new(7).unwrap());
+        let src_records = original.chunks_exact(src_format.size + 3);
+        let dst_records = converted.chunks_exact(dst_format.size + 3);
+        assert_eq!(src_records.len(), dst_records.len());
+        for (src, dst) in src_records.zip(dst_records) {
+            assert_eq!(
+                shared_fields(&Lazrs_Point::read_from(&src_format, src)),
+                shared_fields(&Lazrs_Point::read_from(&dst_format, dst))
+            );
+            assert_eq!(src[src_format.size..], dst[dst_format.size..]);
+        }
+    }
+
+    #[test]
+    fn converted_points_keep_their_fields() {
+        let records = format_3_records(100);
+        let las = las_file(3, 37, &records);
+
+        let (result, converted) = convert_c(&las, 7);
+        assert_eq!(result, Lazrs_Result::LAZRS_OK);
+        let (metadata, converted_records) = read_las(&converted);
+        assert_eq!(metadata.header.point_format_id(), 7);
+        assert_eq!(metadata.header.point_size(), 36 + 3);
+        assert_eq!(metadata.header.number_of_points(), 100);
+        assert_ne!(metadata.header.global_encoding() & GLOBAL_ENCODING_WKT, 0);
+        check_converted(&records, &converted_records);
+
+        // And back
+        let (result, back) = convert_c(&converted, 3);
+        assert_eq!(result, Lazrs_Result::LAZRS_OK);
+        assert_eq!(read_las(&back).1, records);
+    }
+
+    #[test]
+    fn converted_laz_stays_compressed() {
+        let records = format_3_records(100);
+        let (_, laz) = las_to_laz_c(&las_file(3, 37, &records), 30, false);
+
+        let (result, converted) = convert_c(&laz, 7);
+        assert_eq!(result, Lazrs_Result::LAZRS_OK);
+        let metadata = LasMetadata::read_from(&mut Cursor::new(&converted)).unwrap();
+        assert!(metadata.header.is_compressed());
+        assert_eq!(metadata.laszip_vlr().unwrap().unwrap().chunk_size(), 30);
+        assert_ne!(metadata.header.global_encoding() & GLOBAL_ENCODING_WKT, 0);
+
+        let (result, las) = laz_to_las_c(&converted, false);
+        assert_eq!(result, Lazrs_Result::LAZRS_OK);
+        check_converted(&records, &read_las(&las).1);
+    }
+
+    #[test]
+    fn laz_in_compatibility_mode_is_converted_from_its_las_1_4_format() {
+        let records = format_3_records(100);
+        let (_, las_7) = convert_c(&las_file(3, 37, &records), 7);
+        let options = Lazrs_LasToLazOptions {
+            chunk_size: 30,
+            prefer_parallel: false,
+            compatibility_mode: true,
+        };
+        let (result, laz) = las_to_laz_with_options_c(&las_7, options);
+        assert_eq!(result, Lazrs_Result::LAZRS_OK);
+        let metadata = LasMetadata::read_from(&mut Cursor::new(&laz)).unwrap();
+        assert_eq!(metadata.header.point_format_id(), 3);
+
+        let (result, converted) = convert_c(&laz, 3);
+        assert_eq!(result, Lazrs_Result::LAZRS_OK);
+        let (result, las_3) = laz_to_las_c(&converted, false);
+        assert_eq!(result, Lazrs_Result::LAZRS_OK);
+        let (metadata, converted_records) = read_las(&las_3);
+        assert_eq!(metadata.header.point_size(), 34 + 3);
+        assert!(!metadata
+            .vlrs
+            .iter()
+            .any(|vlr| vlr.is("lascompatible", 22204)));
+        assert_eq!(converted_records, records);
+    }
+
+    #[test]
+    fn laz_in_compatibility_mode_is_written_to_copc_in_its_las_1_4_format() {
+        let records = format_3_records(100);
+        let (_, las_7) = convert_c(&las_file(3, 37, &records), 7);
+        let options = Lazrs_LasToLazOptions {
+            chunk_size: 30,
+            prefer_parallel: false,
+            compatibility_mode: true,
+        };
+        let (result, laz) = las_to_laz_with_options_c(&las_7, options);
+        assert_eq!(result, Lazrs_Result::LAZRS_OK);
+
+        let (result, copc) = written_to_file(|dest| unsafe {
+            lazrs_las_to_copc(
+                Lazrs_SourceType::LAZRS_SOURCE_BUFFER,
+                buffer_source(&laz),
+                Lazrs_DestType::LAZRS_DEST_CFILE,
+                dest,
+                Lazrs_LasToCopcOptions {
+                    spacing: 0.0,
+                    prefer_parallel: false,
+                },
+            )
+        });
+        assert_eq!(result, Lazrs_Result::LAZRS_OK);
+        let (result, las) = laz_to_las_c(&copc, false);
+        assert_eq!(result, Lazrs_Result::LAZRS_OK);
+        let (metadata, copc_records) = read_las(&las);
+        assert_eq!(metadata.header.point_format_id(), 7);
+        assert!(!metadata
+            .vlrs
+            .iter()
+            .any(|vlr| vlr.is("lascompatible", 22204)));
+        // The points are in the order of the octree
+        let point_size = usize::from(metadata.header.point_size());
+        let mut copc_records: Vec<&[u8]> = copc_records.chunks_exact(point_size).collect();
+        let las_7_records = read_las(&las_7).1;
+        let mut las_7_records: Vec<&[u8]> = las_7_records.chunks_exact(point_size).collect();
+        copc_records.sort();
+        las_7_records.sort();
+        assert_eq!(copc_records, las_7_records);
+    }
+}
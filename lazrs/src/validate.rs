@@ -0,0 +1,310 @@
+//! Verification of the integrity of LAZ point data.
+
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::chunks::{chunk_end, decompress_chunk, is_layered, layered_point_count, ChunkLayout};
+use crate::io::CSource;
+use crate::query::batch_size;
+use crate::{Lazrs_DecompressorParams, Lazrs_Result};
+
+/// What made the validation fail
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Lazrs_ValidationError {
+    /// No problem was found
+    LAZRS_VALIDATION_OK,
+    /// The chunk table could not be found or read
+    LAZRS_VALIDATION_MISSING_CHUNK_TABLE,
+    /// The chunks sizes of the table do not match where the table is
+    LAZRS_VALIDATION_CHUNK_TABLE_MISMATCH,
+    /// The number of points in the chunks does not match the expected number of points
+    LAZRS_VALIDATION_POINT_COUNT_MISMATCH,
+    /// A chunk goes past the end of the source
+    LAZRS_VALIDATION_TRUNCATED_CHUNK,
+    /// A chunk could not be decompressed
+    LAZRS_VALIDATION_CORRUPTED_CHUNK,
+}
+
+/// Result of `lazrs_validate`
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Lazrs_ValidationReport {
+    /// Whether no problem was found
+    is_valid: bool,
+    /// Number of chunks in the chunk table
+    num_chunks: u64,
+    /// Number of points that were successfully decompressed
+    num_points: u64,
+    /// Index of the first chunk with a problem, -1 if the problem is not tied to a chunk
+    first_bad_chunk: i64,
+    /// Position in the source of the first chunk with a problem
+    first_bad_chunk_offset: u64,
+    error: Lazrs_ValidationError,
+}
+
+impl Lazrs_ValidationReport {
+    fn new() -> Self {
+        Self {
+            is_valid: true,
+            num_chunks: 0,
+            num_points: 0,
+            first_bad_chunk: -1,
+            first_bad_chunk_offset: 0,
+            error: Lazrs_ValidationError::LAZRS_VALIDATION_OK,
+        }
+    }
+
+    fn fail(&mut self, error: Lazrs_ValidationError, chunk: Option<(usize, u64)>) {
+        self.is_valid = false;
+        self.error = error;
+        if let Some((index, offset)) = chunk {
+            self.first_bad_chunk = index as i64;
+            self.first_bad_chunk_offset = offset;
+        }
+    }
+}
+
+/// A chunk read from the source, ready to be checked
+struct Chunk {
+    index: usize,
+    offset: u64,
+    point_count: u64,
+    data: Vec<u8>,
+}
+
+/// Decompresses the chunk, returns the problem found, if any
+fn check_chunk(vlr: &laz::LazVlr, chunk: &Chunk) -> Option<Lazrs_ValidationError> {
+    if is_layered(vlr) && chunk.point_count > 0 {
+        match layered_point_count(vlr, &chunk.data) {
+            Some(count) if u64::from(count) == chunk.point_count => {}
+            Some(_) => return Some(Lazrs_ValidationError::LAZRS_VALIDATION_POINT_COUNT_MISMATCH),
+            None => return Some(Lazrs_ValidationError::LAZRS_VALIDATION_CORRUPTED_CHUNK),
+        }
+    }
+    // The decoder reads exactly the bytes of the chunk, if some are left
+    // the chunk has more points than expected
+    match decompress_chunk(vlr, &chunk.data, chunk.point_count, |_| {}) {
+        Ok(read) if read == chunk.data.len() as u64 => None,
+        Ok(_) => Some(Lazrs_ValidationError::LAZRS_VALIDATION_POINT_COUNT_MISMATCH),
+        Err(_) => Some(Lazrs_ValidationError::LAZRS_VALIDATION_CORRUPTED_CHUNK),
+    }
+}
+
+/// Checks a batch of chunks, returns the first problem found, if any
+fn check_chunks(
+    vlr: &laz::LazVlr,
+    chunks: &[Chunk],
+    parallel: bool,
+) -> Option<(usize, Lazrs_ValidationError)> {
+    #[cfg(feature = "parallel")]
+    if parallel {
+        use rayon::prelude::*;
+        return chunks
+            .par_iter()
+            .enumerate()
+            .filter_map(|(i, chunk)| check_chunk(vlr, chunk).map(|error| (i, error)))
+            .min_by_key(|(i, _)| *i);
+    }
+    let _ = parallel;
+    chunks
+        .iter()
+        .enumerate()
+        .find_map(|(i, chunk)| check_chunk(vlr, chunk).map(|error| (i, error)))
+}
+
+fn validate<R: Read + Seek>(
+    mut src: R,
+    vlr: &laz::LazVlr,
+    num_points: u64,
+    parallel: bool,
+    report: &mut Lazrs_ValidationReport,
+) -> std::io::Result<()> {
+    let layout = match ChunkLayout::read_from(&mut src, vlr) {
+        Ok(layout) => layout,
+        Err(_) => {
+            report.fail(
+                Lazrs_ValidationError::LAZRS_VALIDATION_MISSING_CHUNK_TABLE,
+                None,
+            );
+            return Ok(());
+        }
+    };
+    let entries = layout.table.as_ref();
+    let offsets = layout.chunk_offsets();
+    let point_counts = layout.point_counts(vlr, num_points);
+    report.num_chunks = entries.len() as u64;
+
+    let source_end = src.seek(SeekFrom::End(0))?;
+    let data_end = layout.table_offset.unwrap_or(source_end);
+
+    let mut batch = Vec::<Chunk>::new();
+    let batch_size = batch_size(parallel);
+    for (index, entry) in entries.iter().enumerate() {
+        let offset = offsets[index];
+        let point_count = point_counts[index];
+        if point_count == 0 {
+            report.fail(
+                Lazrs_ValidationError::LAZRS_VALIDATION_POINT_COUNT_MISMATCH,
+                Some((index, offset)),
+            );
+            break;
+        }
+        // Checked before reading the chunk, so that corrupt byte counts
+        // are never used as the size of a buffer
+        let error = match chunk_end(offset, entry) {
+            None => Some(Lazrs_ValidationError::LAZRS_VALIDATION_TRUNCATED_CHUNK),
+            Some(end) if end > source_end => {
+                Some(Lazrs_ValidationError::LAZRS_VALIDATION_TRUNCATED_CHUNK)
+            }
+            Some(end) if end > data_end => {
+                Some(Lazrs_ValidationError::LAZRS_VALIDATION_CHUNK_TABLE_MISMATCH)
+            }
+            Some(_) => None,
+        };
+        if let Some(error) = error {
+            report.fail(error, Some((index, offset)));
+            break;
+        }
+
+        let mut data = vec![0u8; entry.byte_count as usize];
+        src.seek(SeekFrom::Start(offset))?;
+        src.read_exact(&mut data)?;
+        batch.push(Chunk {
+            index,
+            offset,
+            point_count,
+            data,
+        });
+
+        if batch.len() == batch_size || index + 1 == entries.len() {
+            let bad_chunk = check_chunks(vlr, &batch, parallel);
+            let num_good = bad_chunk.map_or(batch.len(), |(i, _)| i);
+            report.num_points += batch[..num_good]
+                .iter()
+                .map(|chunk| chunk.point_count)
+                .sum::<u64>();
+            if let Some((i, error)) = bad_chunk {
+                report.fail(error, Some((batch[i].index, batch[i].offset)));
+                return Ok(());
+            }
+            batch.clear();
+        }
+    }
+
+    if !report.is_valid {
+        return Ok(());
+    }
+    if point_counts.iter().sum::<u64>() != num_points {
+        report.fail(
+            Lazrs_ValidationError::LAZRS_VALIDATION_POINT_COUNT_MISMATCH,
+            None,
+        );
+    } else if let Some(table_offset) = layout.table_offset {
+        let chunks_end = entries.iter().try_fold(layout.data_start, chunk_end);
+        if chunks_end != Some(table_offset) {
+            report.fail(
+                Lazrs_ValidationError::LAZRS_VALIDATION_CHUNK_TABLE_MISMATCH,
+                None,
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Checks that all the chunks of the LAZ data can be decompressed and that
+/// they are consistent with the chunk table.
+///
+/// Points are decompressed chunk by chunk and discarded.
+/// LAZ data has no checksums, so damaged bytes that still decode to points
+/// of the right count and size cannot be detected.
+///
+/// The returned result is LAZRS_OK if the validation could be run,
+/// whether problems were found is told by the report.
+///
+/// @params: where the LAZ data is, `source_offset` must be the start of the point data
+/// @num_points: number of points the LAZ data should have (from the LAS header)
+/// @prefer_parallel: whether to decompress the chunks using multiple threads, if possible
+/// @report: will receive the result of the validation, must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_validate(
+    params: Lazrs_DecompressorParams,
+    num_points: u64,
+    prefer_parallel: bool,
+    report: *mut Lazrs_ValidationReport,
+) -> Lazrs_Result {
+    debug_assert!(!report.is_null());
+    *report = Lazrs_ValidationReport::new();
+
+    let vlr_data = std::slice::from_raw_parts(params.laszip_vlr.data, params.laszip_vlr.len);
+    let vlr = match laz::LazVlr::from_buffer(vlr_data) {
+        Ok(vlr) => vlr,
+        Err(error) => return error.into(),
+    };
+
+    let mut csource = match CSource::from_c_source(params.source_type, params.source) {
+        Ok(v) => v,
+        Err(result) => return result,
+    };
+    if csource.seek(SeekFrom::Start(params.source_offset)).is_err() {
+        return Lazrs_Result::LAZRS_IO_ERROR;
+    }
+
+    validate(csource, &vlr, num_points, prefer_parallel, &mut *report).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    use crate::chunks::test_data::{compressed, set_byte_count, vlr};
+
+    fn run(data: &[u8], vlr: &laz::LazVlr, num_points: u64) -> Lazrs_ValidationReport {
+        let mut report = Lazrs_ValidationReport::new();
+        validate(Cursor::new(data), vlr, num_points, false, &mut report).unwrap();
+        report
+    }
+
+    #[test]
+    fn valid_data_passes() {
+        let vlr = vlr();
+        let report = run(&compressed(&vlr, 25), &vlr, 25);
+        assert!(report.is_valid);
+        assert_eq!(report.error, Lazrs_ValidationError::LAZRS_VALIDATION_OK);
+        assert_eq!(report.num_chunks, 3);
+        assert_eq!(report.num_points, 25);
+        assert_eq!(report.first_bad_chunk, -1);
+    }
+
+    #[test]
+    fn wrong_number_of_points_is_reported() {
+        let vlr = vlr();
+        // The last chunk has 5 points, the decoder does not read all its bytes for 4
+        let report = run(&compressed(&vlr, 25), &vlr, 24);
+        assert!(!report.is_valid);
+        assert_eq!(
+            report.error,
+            Lazrs_ValidationError::LAZRS_VALIDATION_POINT_COUNT_MISMATCH
+        );
+        assert_eq!(report.first_bad_chunk, 2);
+    }
+
+    #[test]
+    fn chunk_overlapping_the_table_is_a_mismatch() {
+        let vlr = vlr();
+        let mut data = compressed(&vlr, 25);
+        let last_chunk_size = {
+            let layout = ChunkLayout::read_from(Cursor::new(&data), &vlr).unwrap();
+            layout.table.as_ref()[2].byte_count
+        };
+        set_byte_count(&mut data, &vlr, 2, last_chunk_size + 1);
+
+        let report = run(&data, &vlr, 25);
+        assert_eq!(
+            report.error,
+            Lazrs_ValidationError::LAZRS_VALIDATION_CHUNK_TABLE_MISMATCH
+        );
+        assert_eq!(report.first_bad_chunk, 2);
+        assert_eq!(report.num_points, 20);
+    }
+}
@@ -0,0 +1,766 @@
+//! Access to the content of the LASzip VLR.
+
+use std::convert::TryInto;
+
+use crate::capabilities::{
+    item_versions, LAZRS_ITEM_BYTE, LAZRS_ITEM_BYTE14, LAZRS_ITEM_GPS_TIME, LAZRS_ITEM_POINT10,
+    LAZRS_ITEM_POINT14, LAZRS_ITEM_RGB12, LAZRS_ITEM_RGB14, LAZRS_ITEM_RGBNIR14,
+};
+use crate::{Lazrs_Buffer, Lazrs_Result};
+
+// Offsets of the fields in the record data of the LASzip VLR,
+// laz does not give access to all of them.
+const COMPRESSOR_OFFSET: usize = 0;
+const CODER_OFFSET: usize = 2;
+const VERSION_MAJOR_OFFSET: usize = 4;
+const VERSION_MINOR_OFFSET: usize = 5;
+const VERSION_REVISION_OFFSET: usize = 6;
+const OPTIONS_OFFSET: usize = 8;
+
+/// Chunk size written in the VLR when the chunks have a variable number of points
+const VARIABLE_CHUNK_SIZE: u32 = u32::MAX;
+
+/// How the compressed points are organized
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Lazrs_CompressorType {
+    LAZRS_COMPRESSOR_NONE,
+    /// Only one chunk with all the points
+    LAZRS_COMPRESSOR_POINTWISE,
+    /// Points are compressed in chunks
+    LAZRS_COMPRESSOR_POINTWISE_CHUNKED,
+    /// Points are compressed in chunks, with their fields in separate layers
+    /// (point formats >= 6)
+    LAZRS_COMPRESSOR_LAYERED_CHUNKED,
+}
+
+/// Version of LASzip that wrote the VLR
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Lazrs_LaszipVersion {
+    major: u8,
+    minor: u8,
+    revision: u16,
+}
+
+/// Description of one item (group of fields) of the compressed points
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Lazrs_LazItem {
+    /// One of the LAZRS_ITEM_* codes
+    item_type: u16,
+    /// Size in bytes of the item in the LAS point
+    size: u16,
+    /// Version of the compression used for the item
+    version: u16,
+}
+
+/// A parsed LASzip VLR
+pub struct Lazrs_LazVlr {
+    pub(crate) vlr: laz::LazVlr,
+    /// The record data of the vlr, the fields laz keeps private are read from it
+    data: Vec<u8>,
+}
+
+impl Lazrs_LazVlr {
+    pub(crate) fn new(vlr: laz::LazVlr) -> std::io::Result<Self> {
+        let mut data = Vec::<u8>::new();
+        vlr.write_to(&mut data)?;
+        Ok(Self { vlr, data })
+    }
+
+    fn u16_at(&self, offset: usize) -> u16 {
+        u16::from_le_bytes(self.data[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn u32_at(&self, offset: usize) -> u32 {
+        u32::from_le_bytes(self.data[offset..offset + 4].try_into().unwrap())
+    }
+}
+
+/// Writes the vlr in `out` as a new handle, or NULL if it could not be serialized
+pub(crate) unsafe fn laz_vlr_to_c(vlr: laz::LazVlr, out: *mut *mut Lazrs_LazVlr) -> Lazrs_Result {
+    match Lazrs_LazVlr::new(vlr) {
+        Ok(vlr) => {
+            *out = Box::into_raw(Box::new(vlr));
+            Lazrs_Result::LAZRS_OK
+        }
+        Err(_) => {
+            *out = std::ptr::null_mut::<Lazrs_LazVlr>();
+            Lazrs_Result::LAZRS_IO_ERROR
+        }
+    }
+}
+
+/// Parses the record data of a LASzip VLR
+///
+/// If an error occurs, the returned result will be something other that LAZRS_OK
+/// and the vlr will be set to NULL.
+///
+/// @record_data: the record data of the VLR (without the VLR header)
+/// @vlr: will receive the parsed vlr
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_laz_vlr_new(
+    record_data: Lazrs_Buffer,
+    vlr: *mut *mut Lazrs_LazVlr,
+) -> Lazrs_Result {
+    debug_assert!(!vlr.is_null());
+    let data = std::slice::from_raw_parts(record_data.data, record_data.len);
+    match laz::LazVlr::from_buffer(data) {
+        Ok(v) => laz_vlr_to_c(v, vlr),
+        Err(error) => {
+            *vlr = std::ptr::null_mut::<Lazrs_LazVlr>();
+            error.into()
+        }
+    }
+}
+
+/// Frees the vlr
+///
+/// @vlr can be NULL (no-op)
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_laz_vlr_delete(vlr: *mut Lazrs_LazVlr) {
+    if !vlr.is_null() {
+        let _ = Box::from_raw(vlr);
+    }
+}
+
+/// Returns how the points are organized in the compressed data
+///
+/// @vlr: must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_laz_vlr_compressor_type(
+    vlr: *const Lazrs_LazVlr,
+) -> Lazrs_CompressorType {
+    debug_assert!(!vlr.is_null());
+    // laz refuses vlrs with an unknown compressor, so all values are covered
+    match (*vlr).u16_at(COMPRESSOR_OFFSET) {
+        0 => Lazrs_CompressorType::LAZRS_COMPRESSOR_NONE,
+        1 => Lazrs_CompressorType::LAZRS_COMPRESSOR_POINTWISE,
+        2 => Lazrs_CompressorType::LAZRS_COMPRESSOR_POINTWISE_CHUNKED,
+        _ => Lazrs_CompressorType::LAZRS_COMPRESSOR_LAYERED_CHUNKED,
+    }
+}
+
+/// Returns the coder used, 0 (arithmetic coder) is the only one that exists
+///
+/// @vlr: must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_laz_vlr_coder(vlr: *const Lazrs_LazVlr) -> u16 {
+    debug_assert!(!vlr.is_null());
+    (*vlr).u16_at(CODER_OFFSET)
+}
+
+/// Returns the version of LASzip that wrote the vlr
+///
+/// @vlr: must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_laz_vlr_laszip_version(
+    vlr: *const Lazrs_LazVlr,
+) -> Lazrs_LaszipVersion {
+    debug_assert!(!vlr.is_null());
+    let vlr = &*vlr;
+    Lazrs_LaszipVersion {
+        major: vlr.data[VERSION_MAJOR_OFFSET],
+        minor: vlr.data[VERSION_MINOR_OFFSET],
+        revision: vlr.u16_at(VERSION_REVISION_OFFSET),
+    }
+}
+
+/// Returns the option bits of the vlr
+///
+/// @vlr: must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_laz_vlr_options(vlr: *const Lazrs_LazVlr) -> u32 {
+    debug_assert!(!vlr.is_null());
+    (*vlr).u32_at(OPTIONS_OFFSET)
+}
+
+/// Returns the number of points per chunk,
+/// meaningless if the chunks have a variable size.
+///
+/// @vlr: must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_laz_vlr_chunk_size(vlr: *const Lazrs_LazVlr) -> u32 {
+    debug_assert!(!vlr.is_null());
+    (*vlr).vlr.chunk_size()
+}
+
+/// Returns whether the chunks have a variable number of points
+///
+/// @vlr: must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_laz_vlr_uses_variable_size_chunks(vlr: *const Lazrs_LazVlr) -> bool {
+    debug_assert!(!vlr.is_null());
+    (*vlr).vlr.uses_variable_size_chunks()
+}
+
+/// Returns the size in bytes of one point
+///
+/// @vlr: must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_laz_vlr_items_size(vlr: *const Lazrs_LazVlr) -> u64 {
+    debug_assert!(!vlr.is_null());
+    (*vlr).vlr.items_size()
+}
+
+/// Returns the number of items
+///
+/// @vlr: must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_laz_vlr_num_items(vlr: *const Lazrs_LazVlr) -> usize {
+    debug_assert!(!vlr.is_null());
+    (*vlr).vlr.items().len()
+}
+
+/// Gets the item at the given index
+///
+/// @vlr: must not be NULL
+/// @index: index of the item, LAZRS_OTHER is returned if it is out of bounds
+/// @item: will receive the item
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_laz_vlr_item(
+    vlr: *const Lazrs_LazVlr,
+    index: usize,
+    item: *mut Lazrs_LazItem,
+) -> Lazrs_Result {
+    debug_assert!(!vlr.is_null());
+    debug_assert!(!item.is_null());
+    match (*vlr).vlr.items().get(index) {
+        Some(laz_item) => {
+            *item = Lazrs_LazItem {
+                item_type: laz_item.item_type().into(),
+                size: laz_item.size(),
+                version: laz_item.version(),
+            };
+            Lazrs_Result::LAZRS_OK
+        }
+        None => Lazrs_Result::LAZRS_OTHER,
+    }
+}
+
+/// Returns the size of the record data of the vlr
+///
+/// @vlr: must not be NULL
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_laz_vlr_record_data_size(vlr: *const Lazrs_LazVlr) -> u16 {
+    debug_assert!(!vlr.is_null());
+    (*vlr).data.len().try_into().unwrap()
+}
+
+/// Copies the record data of the vlr
+///
+/// @vlr: must not be NULL
+/// @data: buffer that will receive the record data
+/// @size: size of the buffer, must be at least `lazrs_laz_vlr_record_data_size`
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_laz_vlr_record_data(
+    vlr: *const Lazrs_LazVlr,
+    data: *mut u8,
+    size: usize,
+) -> Lazrs_Result {
+    debug_assert!(!vlr.is_null());
+    debug_assert!(!data.is_null());
+    let record_data = &(*vlr).data;
+    if size < record_data.len() {
+        return Lazrs_Result::LAZRS_OTHER;
+    }
+    std::slice::from_raw_parts_mut(data, record_data.len()).copy_from_slice(record_data);
+    Lazrs_Result::LAZRS_OK
+}
+
+//==================================================================================================
+
+/// Builds the list of items of a LASzip VLR, one item at a time
+///
+/// Unlike `lazrs_compressor_new_for_point_format`, this allows choosing
+/// the version of each item and the size of the extra bytes.
+pub struct Lazrs_LazItemsBuilder {
+    items: Vec<Lazrs_LazItem>,
+}
+
+/// Returns the size the item must have, `None` for the extra bytes whose size is free
+fn fixed_item_size(item_type: u16) -> Option<u16> {
+    match item_type {
+        LAZRS_ITEM_POINT10 => Some(20),
+        LAZRS_ITEM_GPS_TIME => Some(8),
+        LAZRS_ITEM_RGB12 | LAZRS_ITEM_RGB14 => Some(6),
+        LAZRS_ITEM_POINT14 => Some(30),
+        LAZRS_ITEM_RGBNIR14 => Some(8),
+        _ => None,
+    }
+}
+
+/// Returns the compressor of the items,
+/// `None` if they are not in the order of the fields of a point format
+///
+/// The compressor and the items of LAS 1.4 points cannot be mixed with
+/// the ones of the older point formats.
+fn items_compressor(items: &[Lazrs_LazItem]) -> Option<u16> {
+    let (compressor, order): (u16, &[u16]) = match items.first()?.item_type {
+        LAZRS_ITEM_POINT10 => (
+            2,
+            &[
+                LAZRS_ITEM_POINT10,
+                LAZRS_ITEM_GPS_TIME,
+                LAZRS_ITEM_RGB12,
+                LAZRS_ITEM_BYTE,
+            ],
+        ),
+        LAZRS_ITEM_POINT14 => (
+            3,
+            &[LAZRS_ITEM_POINT14, LAZRS_ITEM_RGB14, LAZRS_ITEM_BYTE14],
+        ),
+        _ => return None,
+    };
+    let mut remaining = order;
+    for item in items {
+        // NIR comes with the colors, in place of them
+        let item_type = match item.item_type {
+            LAZRS_ITEM_RGBNIR14 => LAZRS_ITEM_RGB14,
+            item_type => item_type,
+        };
+        let position = remaining.iter().position(|&t| t == item_type)?;
+        remaining = &remaining[position + 1..];
+    }
+    Some(compressor)
+}
+
+/// Creates a new builder, with no items
+///
+/// The builder must be freed with `lazrs_laz_items_builder_delete`.
+#[no_mangle]
+pub extern "C" fn lazrs_laz_items_builder_new() -> *mut Lazrs_LazItemsBuilder {
+    Box::into_raw(Box::new(Lazrs_LazItemsBuilder { items: vec![] }))
+}
+
+/// Frees the builder
+///
+/// @builder can be NULL (no-op)
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_laz_items_builder_delete(builder: *mut Lazrs_LazItemsBuilder) {
+    if !builder.is_null() {
+        let _ = Box::from_raw(builder);
+    }
+}
+
+/// Adds an item, items must be added in the order their fields appear in the LAS points
+///
+/// @builder: must not be NULL
+/// @item_type: one of the LAZRS_ITEM_* codes
+/// @size: size in bytes of the item, only free for extra bytes
+///        (LAZRS_ITEM_BYTE, LAZRS_ITEM_BYTE14)
+/// @version: compression version of the item, see `lazrs_supported_items`
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_laz_items_builder_add_item(
+    builder: *mut Lazrs_LazItemsBuilder,
+    item_type: u16,
+    size: u16,
+    version: u16,
+) -> Lazrs_Result {
+    debug_assert!(!builder.is_null());
+    let versions = match item_versions(item_type) {
+        Some(versions) => versions,
+        None => return Lazrs_Result::LAZRS_UNKNOWN_LAZ_ITEM,
+    };
+    if version < versions.min_version || version > versions.max_version {
+        return Lazrs_Result::LAZRS_UNKNOWN_LAZ_ITEM_VERSION;
+    }
+    if fixed_item_size(item_type).map_or(size == 0, |expected| expected != size) {
+        return Lazrs_Result::LAZRS_OTHER;
+    }
+    (*builder).items.push(Lazrs_LazItem {
+        item_type,
+        size,
+        version,
+    });
+    Lazrs_Result::LAZRS_OK
+}
+
+/// Creates a LASzip VLR with the items added so far
+///
+/// The builder can be reused afterwards.
+///
+/// @builder: must not be NULL, and must have the items of a point format:
+///           a LAZRS_ITEM_POINT10 followed by LAZRS_ITEM_GPS_TIME, LAZRS_ITEM_RGB12
+///           and LAZRS_ITEM_BYTE, or a LAZRS_ITEM_POINT14 followed by LAZRS_ITEM_RGB14
+///           (or LAZRS_ITEM_RGBNIR14) and LAZRS_ITEM_BYTE14, each optional and in that
+///           order, LAZRS_OTHER is returned otherwise
+/// @chunk_size: number of points per chunk, ignored if `variable` is true
+/// @variable: whether the chunks have a variable number of points
+/// @vlr: will receive the vlr, or NULL if an error occurred
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_laz_items_builder_build_vlr(
+    builder: *const Lazrs_LazItemsBuilder,
+    chunk_size: u32,
+    variable: bool,
+    vlr: *mut *mut Lazrs_LazVlr,
+) -> Lazrs_Result {
+    debug_assert!(!builder.is_null());
+    debug_assert!(!vlr.is_null());
+    *vlr = std::ptr::null_mut::<Lazrs_LazVlr>();
+
+    let items = &(*builder).items;
+    let compressor = match items_compressor(items) {
+        Some(compressor) => compressor,
+        None => return Lazrs_Result::LAZRS_OTHER,
+    };
+    let chunk_size = match (variable, chunk_size) {
+        (true, _) => VARIABLE_CHUNK_SIZE,
+        (false, 0) | (false, VARIABLE_CHUNK_SIZE) => return Lazrs_Result::LAZRS_OTHER,
+        (false, chunk_size) => chunk_size,
+    };
+
+    // laz only creates items with their default version,
+    // so we write the record data and let laz parse it.
+    let mut data = Vec::<u8>::with_capacity(34 + 6 * items.len());
+    data.extend_from_slice(&compressor.to_le_bytes());
+    data.extend_from_slice(&0u16.to_le_bytes()); // coder
+    data.extend_from_slice(&[2, 2, 0, 0]); // laszip version
+    data.extend_from_slice(&0u32.to_le_bytes()); // options
+    data.extend_from_slice(&chunk_size.to_le_bytes());
+    data.extend_from_slice(&(-1i64).to_le_bytes()); // number of special evlrs
+    data.extend_from_slice(&(-1i64).to_le_bytes()); // offset to special evlrs
+    data.extend_from_slice(&(items.len() as u16).to_le_bytes());
+    for item in items {
+        data.extend_from_slice(&item.item_type.to_le_bytes());
+        data.extend_from_slice(&item.size.to_le_bytes());
+        data.extend_from_slice(&item.version.to_le_bytes());
+    }
+
+    match laz::LazVlr::from_buffer(&data) {
+        Ok(v) => {
+            *vlr = Box::into_raw(Box::new(Lazrs_LazVlr { vlr: v, data }));
+            Lazrs_Result::LAZRS_OK
+        }
+        Err(error) => error.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    use crate::io::CFile;
+    use crate::{
+        lazrs_compressor_compress_many, lazrs_compressor_delete, lazrs_compressor_done,
+        lazrs_compressor_new_from_laz_vlr, lazrs_decompressor_decompress_many,
+        lazrs_decompressor_delete, lazrs_decompressor_new, Lazrs_DecompressorParams, Lazrs_Dest,
+        Lazrs_DestType, Lazrs_Source, Lazrs_SourceType,
+    };
+
+    fn record_data(vlr: &laz::LazVlr) -> Vec<u8> {
+        let mut data = vec![];
+        vlr.write_to(&mut data).unwrap();
+        data
+    }
+
+    /// Parses the record data with the C API
+    fn parse(data: &[u8]) -> (Lazrs_Result, *mut Lazrs_LazVlr) {
+        let mut vlr = std::ptr::null_mut();
+        let buffer = Lazrs_Buffer {
+            data: data.as_ptr(),
+            len: data.len(),
+        };
+        let result = unsafe { lazrs_laz_vlr_new(buffer, &mut vlr) };
+        (result, vlr)
+    }
+
+    #[test]
+    fn fields_of_a_pointwise_vlr() {
+        let items = laz::LazItemRecordBuilder::default_for_point_format_id(1, 3).unwrap();
+        let data = record_data(
+            &laz::LazVlrBuilder::new(items)
+                .with_fixed_chunk_size(1000)
+                .build(),
+        );
+        let (result, vlr) = parse(&data);
+        assert_eq!(result, Lazrs_Result::LAZRS_OK);
+        unsafe {
+            assert_eq!(
+                lazrs_laz_vlr_compressor_type(vlr),
+                Lazrs_CompressorType::LAZRS_COMPRESSOR_POINTWISE_CHUNKED
+            );
+            assert_eq!(lazrs_laz_vlr_coder(vlr), 0);
+            assert_eq!(lazrs_laz_vlr_chunk_size(vlr), 1000);
+            assert!(!lazrs_laz_vlr_uses_variable_size_chunks(vlr));
+            assert_eq!(lazrs_laz_vlr_items_size(vlr), 28 + 3);
+
+            assert_eq!(lazrs_laz_vlr_num_items(vlr), 3);
+            let mut item = Lazrs_LazItem {
+                item_type: 0,
+                size: 0,
+                version: 0,
+            };
+            let expected = [
+                (LAZRS_ITEM_POINT10, 20),
+                (LAZRS_ITEM_GPS_TIME, 8),
+                (LAZRS_ITEM_BYTE, 3),
+            ];
+            for (i, &(item_type, size)) in expected.iter().enumerate() {
+                assert_eq!(
+                    lazrs_laz_vlr_item(vlr, i, &mut item),
+                    Lazrs_Result::LAZRS_OK
+                );
+                assert_eq!(
+                    (item.item_type, item.size, item.version),
+                    (item_type, size, 2)
+                );
+            }
+            assert_eq!(
+                lazrs_laz_vlr_item(vlr, 3, &mut item),
+                Lazrs_Result::LAZRS_OTHER
+            );
+            lazrs_laz_vlr_delete(vlr);
+        }
+    }
+
+    #[test]
+    fn fields_of_a_layered_vlr() {
+        let items = laz::LazItemRecordBuilder::default_for_point_format_id(7, 0).unwrap();
+        let data = record_data(
+            &laz::LazVlrBuilder::new(items)
+                .with_variable_chunk_size()
+                .build(),
+        );
+        let (result, vlr) = parse(&data);
+        assert_eq!(result, Lazrs_Result::LAZRS_OK);
+        unsafe {
+            assert_eq!(
+                lazrs_laz_vlr_compressor_type(vlr),
+                Lazrs_CompressorType::LAZRS_COMPRESSOR_LAYERED_CHUNKED
+            );
+            assert!(lazrs_laz_vlr_uses_variable_size_chunks(vlr));
+            assert_eq!(lazrs_laz_vlr_items_size(vlr), 36);
+            lazrs_laz_vlr_delete(vlr);
+        }
+    }
+
+    #[test]
+    fn record_data_is_given_back_unchanged() {
+        let items = laz::LazItemRecordBuilder::default_for_point_format_id(3, 0).unwrap();
+        let data = record_data(&laz::LazVlr::from_laz_items(items));
+        let (_, vlr) = parse(&data);
+        unsafe {
+            assert_eq!(usize::from(lazrs_laz_vlr_record_data_size(vlr)), data.len());
+            let mut copy = vec![0u8; data.len()];
+            assert_eq!(
+                lazrs_laz_vlr_record_data(vlr, copy.as_mut_ptr(), copy.len() - 1),
+                Lazrs_Result::LAZRS_OTHER
+            );
+            assert_eq!(
+                lazrs_laz_vlr_record_data(vlr, copy.as_mut_ptr(), copy.len()),
+                Lazrs_Result::LAZRS_OK
+            );
+            assert_eq!(copy, data);
+            lazrs_laz_vlr_delete(vlr);
+        }
+    }
+
+    #[test]
+    fn invalid_record_data_gives_no_vlr() {
+        let (result, vlr) = parse(&[0u8; 10]);
+        assert_ne!(result, Lazrs_Result::LAZRS_OK);
+        assert!(vlr.is_null());
+    }
+
+    /// Builds a vlr with the C API, `items` are (type, size, version)
+    fn build(items: &[(u16, u16, u16)], chunk_size: u32) -> (Lazrs_Result, *mut Lazrs_LazVlr) {
+        let builder = lazrs_laz_items_builder_new();
+        let mut vlr = std::ptr::null_mut();
+        unsafe {
+            for &(item_type, size, version) in items {
+                assert_eq!(
+                    lazrs_laz_items_builder_add_item(builder, item_type, size, version),
+                    Lazrs_Result::LAZRS_OK
+                );
+            }
+            let result = lazrs_laz_items_builder_build_vlr(builder, chunk_size, false, &mut vlr);
+            lazrs_laz_items_builder_delete(builder);
+            (result, vlr)
+        }
+    }
+
+    #[test]
+    fn items_are_checked_when_added() {
+        let builder = lazrs_laz_items_builder_new();
+        unsafe {
+            assert_eq!(
+                lazrs_laz_items_builder_add_item(builder, 42, 20, 2),
+                Lazrs_Result::LAZRS_UNKNOWN_LAZ_ITEM
+            );
+            assert_eq!(
+                lazrs_laz_items_builder_add_item(builder, LAZRS_ITEM_POINT10, 20, 3),
+                Lazrs_Result::LAZRS_UNKNOWN_LAZ_ITEM_VERSION
+            );
+            assert_eq!(
+                lazrs_laz_items_builder_add_item(builder, LAZRS_ITEM_POINT10, 30, 2),
+                Lazrs_Result::LAZRS_OTHER
+            );
+            assert_eq!(
+                lazrs_laz_items_builder_add_item(builder, LAZRS_ITEM_BYTE, 0, 2),
+                Lazrs_Result::LAZRS_OTHER
+            );
+            lazrs_laz_items_builder_delete(builder);
+        }
+    }
+
+    #[test]
+    fn incoherent_items_give_no_vlr() {
+        let out_of_order = [
+            (LAZRS_ITEM_POINT10, 20, 2),
+            (LAZRS_ITEM_BYTE, 3, 2),
+            (LAZRS_ITEM_GPS_TIME, 8, 2),
+        ];
+        let mixed = [(LAZRS_ITEM_POINT10, 20, 2), (LAZRS_ITEM_RGB14, 6, 3)];
+        let no_point = [(LAZRS_ITEM_GPS_TIME, 8, 2)];
+        for items in [&out_of_order[..], &mixed, &no_point, &[]] {
+            let (result, vlr) = build(items, 100);
+            assert_eq!(result, Lazrs_Result::LAZRS_OTHER);
+            assert!(vlr.is_null());
+        }
+
+        let (result, vlr) = build(&[(LAZRS_ITEM_POINT10, 20, 2)], 0);
+        assert_eq!(result, Lazrs_Result::LAZRS_OTHER);
+        assert!(vlr.is_null());
+    }
+
+    /// Records of format 1 followed by extra bytes, the coordinates and GPS times
+    /// are increasing as laz overflows on arbitrary ones in debug builds
+    fn points(count: usize, point_size: usize) -> Vec<u8> {
+        (0..count)
+            .flat_map(|i| {
+                let mut point = vec![0u8; point_size];
+                for (k, coordinate) in point[..12].chunks_exact_mut(4).enumerate() {
+                    coordinate.copy_from_slice(&((i * (k + 1)) as i32).to_le_bytes());
+                }
+                point[12..20]
+                    .iter_mut()
+                    .enumerate()
+                    .for_each(|(j, b)| *b = (i * 7 + j) as u8);
+                point[20..28].copy_from_slice(&(i as f64 * 0.5).to_le_bytes());
+                point[28..]
+                    .iter_mut()
+                    .enumerate()
+                    .for_each(|(j, b)| *b = (i + j) as u8);
+                point
+            })
+            .collect()
+    }
+
+    /// Compresses the points with a compressor created from the vlr,
+    /// and decompresses them back
+    unsafe fn round_trip(vlr: *const Lazrs_LazVlr, points: &[u8]) -> Vec<u8> {
+        let fh = libc::tmpfile();
+        assert!(!fh.is_null());
+        let mut compressor = std::ptr::null_mut();
+        assert_eq!(
+            lazrs_compressor_new_from_laz_vlr(
+                Lazrs_DestType::LAZRS_DEST_CFILE,
+                Lazrs_Dest { file: fh },
+                vlr,
+                false,
+                &mut compressor,
+            ),
+            Lazrs_Result::LAZRS_OK
+        );
+        assert_eq!(
+            lazrs_compressor_compress_many(compressor, points.as_ptr(), points.len()),
+            Lazrs_Result::LAZRS_OK
+        );
+        assert_eq!(lazrs_compressor_done(compressor), Lazrs_Result::LAZRS_OK);
+        lazrs_compressor_delete(compressor);
+
+        libc::rewind(fh);
+        let mut compressed = vec![];
+        CFile::new_unchecked(fh)
+            .read_to_end(&mut compressed)
+            .unwrap();
+        libc::fclose(fh);
+
+        let vlr_data = &(*vlr).data;
+        let params = Lazrs_DecompressorParams {
+            source_type: Lazrs_SourceType::LAZRS_SOURCE_BUFFER,
+            source: Lazrs_Source {
+                buffer: Lazrs_Buffer {
+                    data: compressed.as_ptr(),
+                    len: compressed.len(),
+                },
+            },
+            source_offset: 0,
+            laszip_vlr: Lazrs_Buffer {
+                data: vlr_data.as_ptr(),
+                len: vlr_data.len(),
+            },
+        };
+        let mut decompressor = std::ptr::null_mut();
+        assert_eq!(
+            lazrs_decompressor_new(params, false, &mut decompressor),
+            Lazrs_Result::LAZRS_OK
+        );
+        let mut decompressed = vec![0u8; points.len()];
+        assert_eq!(
+            lazrs_decompressor_decompress_many(
+                decompressor,
+                decompressed.as_mut_ptr(),
+                decompressed.len()
+            ),
+            Lazrs_Result::LAZRS_OK
+        );
+        lazrs_decompressor_delete(decompressor);
+        decompressed
+    }
+
+    #[test]
+    fn built_vlr_compresses_and_decompresses() {
+        const POINT_SIZE: usize = 20 + 8 + 3;
+        let (result, vlr) = build(
+            &[
+                (LAZRS_ITEM_POINT10, 20, 2),
+                (LAZRS_ITEM_GPS_TIME, 8, 2),
+                (LAZRS_ITEM_BYTE, 3, 2),
+            ],
+            10,
+        );
+        assert_eq!(result, Lazrs_Result::LAZRS_OK);
+        let points = points(25, POINT_SIZE);
+
+        unsafe {
+            assert_eq!(lazrs_laz_vlr_chunk_size(vlr), 10);
+            assert_eq!(lazrs_laz_vlr_items_size(vlr), POINT_SIZE as u64);
+            assert_eq!(round_trip(vlr, &points), points);
+            lazrs_laz_vlr_delete(vlr);
+        }
+    }
+
+    #[test]
+    fn legacy_v1_items_compress_and_decompress() {
+        const POINT_SIZE: usize = 20 + 8 + 2;
+        let items = [
+            (LAZRS_ITEM_POINT10, 20, 1),
+            (LAZRS_ITEM_GPS_TIME, 8, 1),
+            (LAZRS_ITEM_BYTE, 2, 1),
+        ];
+        let (result, vlr) = build(&items, 10);
+        assert_eq!(result, Lazrs_Result::LAZRS_OK);
+        let points = points(25, POINT_SIZE);
+
+        unsafe {
+            let mut item = Lazrs_LazItem {
+                item_type: 0,
+                size: 0,
+                version: 0,
+            };
+            for (i, &expected) in items.iter().enumerate() {
+                assert_eq!(
+                    lazrs_laz_vlr_item(vlr, i, &mut item),
+                    Lazrs_Result::LAZRS_OK
+                );
+                assert_eq!((item.item_type, item.size, item.version), expected);
+            }
+            assert_eq!(round_trip(vlr, &points), points);
+            lazrs_laz_vlr_delete(vlr);
+        }
+    }
+}
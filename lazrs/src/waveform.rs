@@ -0,0 +1,554 @@
+//! The waveform data of the points with a wave packet (formats 4, 5, 9 and 10).
+//!
+//! The wave packet of a point (see `Lazrs_WavePacket`) tells which descriptor
+//! its waveform follows, and where its samples are in the waveform data packets.
+//! These are either in an EVLR of the LAS file, or in an external `.wdp` file.
+//!
+//! laz cannot compress or decompress these point formats,
+//! so their points come from uncompressed LAS files.
+
+use std::convert::TryInto;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::io::CSource;
+use crate::las::LasMetadata;
+use crate::point::Lazrs_WavePacket;
+use crate::{Lazrs_Result, Lazrs_Source, Lazrs_SourceType};
+
+/// User id of the Wave Packet Descriptor VLRs
+const WAVEFORM_USER_ID: &str = "LASF_Spec";
+/// Record id of the descriptor of index 1, the one of index i is 99 + i
+const FIRST_DESCRIPTOR_RECORD_ID: u16 = 100;
+const MAX_DESCRIPTORS: usize = 256;
+const DESCRIPTOR_SIZE: usize = 26;
+
+/// Bits of the global encoding of the header telling where the waveform data packets are
+const INTERNAL_WAVEFORM_BIT: u16 = 1 << 1;
+const EXTERNAL_WAVEFORM_BIT: u16 = 1 << 2;
+
+/// Describes how the waveforms of the points using it are sampled
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Lazrs_WavePacketDescriptor {
+    /// 8, 16 or 32 bits can be read
+    bits_per_sample: u8,
+    /// Only 0 (no compression) can be read
+    compression_type: u8,
+    number_of_samples: u32,
+    /// In picoseconds
+    temporal_sample_spacing: u32,
+    /// To convert the samples to volts: offset + gain * sample
+    digitizer_gain: f64,
+    digitizer_offset: f64,
+}
+
+impl Lazrs_WavePacketDescriptor {
+    /// Reads the data of a descriptor VLR, `None` if it is not valid
+    fn from_vlr_data(data: &[u8]) -> Option<Self> {
+        if data.len() < DESCRIPTOR_SIZE {
+            return None;
+        }
+        Some(Self {
+            bits_per_sample: data[0],
+            compression_type: data[1],
+            number_of_samples: u32::from_le_bytes(data[2..6].try_into().unwrap()),
+            temporal_sample_spacing: u32::from_le_bytes(data[6..10].try_into().unwrap()),
+            digitizer_gain: f64::from_le_bytes(data[10..18].try_into().unwrap()),
+            digitizer_offset: f64::from_le_bytes(data[18..26].try_into().unwrap()),
+        })
+    }
+
+    /// Size of a sample in bytes, `None` if the samples cannot be read
+    fn sample_size(&self) -> Option<usize> {
+        match (self.compression_type, self.bits_per_sample) {
+            (0, 8) => Some(1),
+            (0, 16) => Some(2),
+            (0, 32) => Some(4),
+            _ => None,
+        }
+    }
+}
+
+/// The descriptors of a file, indexed by the descriptor index of the wave packets
+type Descriptors = [Option<Lazrs_WavePacketDescriptor>; MAX_DESCRIPTORS];
+
+/// Reads the waveforms of the points of a LAS file
+pub struct Lazrs_WaveformReader {
+    descriptors: Descriptors,
+    /// Where the waveform data packets are read from
+    data: CSource<'static>,
+    /// Position of the start of the waveform data packets in `data`,
+    /// the byte offsets of the wave packets are relative to it
+    data_start: u64,
+}
+
+/// Where the waveform data packets of a file are
+enum WaveformData {
+    Internal(u64),
+    External,
+}
+
+/// Reads the descriptors of the file, and where its waveform data packets are
+/// (`None` if the header does not say)
+fn read_descriptors(
+    src: &mut CSource<'static>,
+) -> Result<(Descriptors, Option<WaveformData>), Lazrs_Result> {
+    let start = src.stream_position()?;
+    let metadata = LasMetadata::read_from(src)?;
+    let mut descriptors = [None; MAX_DESCRIPTORS];
+    for vlr in metadata.vlrs.iter().chain(metadata.evlrs.iter()) {
+        let index = usize::from(vlr.record_id.wrapping_sub(FIRST_DESCRIPTOR_RECORD_ID)) + 1;
+        if index < MAX_DESCRIPTORS && vlr.is(WAVEFORM_USER_ID, vlr.record_id) {
+            descriptors[index] = Lazrs_WavePacketDescriptor::from_vlr_data(&vlr.data);
+        }
+    }
+
+    let global_encoding = metadata.header.global_encoding();
+    let data = if global_encoding & INTERNAL_WAVEFORM_BIT != 0 {
+        metadata
+            .header
+            .start_of_waveform_data()
+            .filter(|&offset| offset != 0)
+            .and_then(|offset| start.checked_add(offset))
+            .map(WaveformData::Internal)
+    } else if global_encoding & EXTERNAL_WAVEFORM_BIT != 0 {
+        Some(WaveformData::External)
+    } else {
+        None
+    };
+    Ok((descriptors, data))
+}
+
+impl Lazrs_WaveformReader {
+    fn read_samples(
+        &mut self,
+        packet: &Lazrs_WavePacket,
+        samples: &mut [u32],
+    ) -> Result<(), Lazrs_Result> {
+        let descriptor = self.descriptors[usize::from(packet.descriptor_index)]
+            .as_ref()
+            .ok_or(Lazrs_Result::LAZRS_OTHER)?;
+        let sample_size = descriptor.sample_size().ok_or(Lazrs_Result::LAZRS_OTHER)?;
+        let num_samples = descriptor.number_of_samples as usize;
+        let size = num_samples
+            .checked_mul(sample_size)
+            .ok_or(Lazrs_Result::LAZRS_OTHER)?;
+        if samples.len() < num_samples || (packet.packet_size as usize) < size {
+            return Err(Lazrs_Result::LAZRS_OTHER);
+        }
+        let position = self
+            .data_start
+            .checked_add(packet.byte_offset)
+            // C sources cannot seek further
+            .filter(|&position| position < i64::MAX as u64)
+            .ok_or(Lazrs_Result::LAZRS_OTHER)?;
+
+        let mut data = vec![0u8; size];
+        self.data.seek(SeekFrom::Start(position))?;
+        self.data.read_exact(&mut data)?;
+        for (sample, bytes) in samples.iter_mut().zip(data.chunks_exact(sample_size)) {
+            *sample = match sample_size {
+                1 => u32::from(bytes[0]),
+                2 => u32::from(u16::from_le_bytes(bytes.try_into().unwrap())),
+                _ => u32::from_le_bytes(bytes.try_into().unwrap()),
+            };
+        }
+        Ok(())
+    }
+}
+
+/// Opens the `.wdp` file next to the LAS file
+unsafe fn open_wdp_file(
+    source_type: Lazrs_SourceType,
+    source: Lazrs_Source,
+) -> Result<CSource<'static>, Lazrs_Result> {
+    if !matches!(source_type, Lazrs_SourceType::LAZRS_SOURCE_FNAME) {
+        return Err(Lazrs_Result::LAZRS_OTHER);
+    }
+    let fname = std::str::from_utf8(std::slice::from_raw_parts(
+        source.buffer.data,
+        source.buffer.len,
+    ))
+    .map_err(|_| Lazrs_Result::LAZRS_IO_ERROR)?;
+    let wdp_fname = Path::new(fname).with_extension("wdp");
+    match std::fs::File::open(wdp_fname) {
+        Ok(file) => Ok(CSource::File(std::io::BufReader::new(file))),
+        Err(_) => Err(Lazrs_Result::LAZRS_IO_ERROR),
+    }
+}
+
+unsafe fn open_reader(
+    source_type: Lazrs_SourceType,
+    source: Lazrs_Source,
+    wdp: Option<(Lazrs_SourceType, Lazrs_Source)>,
+) -> Result<Lazrs_WaveformReader, Lazrs_Result> {
+    let mut src = CSource::from_c_source(source_type, source)?;
+    let (descriptors, data) = read_descriptors(&mut src)?;
+    let (data, data_start) = match (data, wdp) {
+        (_, Some((wdp_type, wdp_source))) => {
+            let mut wdp = CSource::from_c_source(wdp_type, wdp_source)?;
+            let wdp_start = wdp.stream_position()?;
+            (wdp, wdp_start)
+        }
+        (Some(WaveformData::Internal(data_start)), None) => (src, data_start),
+        (Some(WaveformData::External), None) => (open_wdp_file(source_type, source)?, 0),
+        (None, None) => return Err(Lazrs_Result::LAZRS_OTHER),
+    };
+    Ok(Lazrs_WaveformReader {
+        descriptors,
+        data,
+        data_start,
+    })
+}
+
+/// Parses the data of a Wave Packet Descriptor VLR
+///
+/// These VLRs have the user id "LASF_Spec", the record id of the descriptor
+/// of index i (from 1 to 255) is 99 + i.
+/// LAZRS_OTHER is returned if the data is not valid.
+///
+/// @data: the VLR data
+/// @size: size of the data
+/// @descriptor: will receive the descriptor
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_wave_packet_descriptor_from_vlr_data(
+    data: *const u8,
+    size: usize,
+    descriptor: *mut Lazrs_WavePacketDescriptor,
+) -> Lazrs_Result {
+    debug_assert!(!data.is_null());
+    debug_assert!(!descriptor.is_null());
+    match Lazrs_WavePacketDescriptor::from_vlr_data(std::slice::from_raw_parts(data, size)) {
+        Some(parsed) => {
+            *descriptor = parsed;
+            Lazrs_Result::LAZRS_OK
+        }
+        None => Lazrs_Result::LAZRS_OTHER,
+    }
+}
+
+/// Opens the waveforms of a LAS file
+///
+/// The Wave Packet Descriptor VLRs are read, the samples are only read
+/// by `lazrs_waveform_reader_read`.
+/// When the waveform data packets are in the file, they are read from the source,
+/// which must then support seeking and stay valid until the reader is freed.
+/// When they are in an external file, the source must be a filename,
+/// and the file with the same name but the `.wdp` extension is opened.
+///
+/// The file is read from the current position of the source.
+/// LAZRS_OTHER is returned if the header does not say where the
+/// waveform data packets are.
+///
+/// @source_type: type of the source
+/// @source: where the LAS file is read from
+/// @reader: will receive the reader, or NULL if an error occurred,
+///          to be freed with `lazrs_waveform_reader_delete`
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_waveform_reader_open(
+    source_type: Lazrs_SourceType,
+    source: Lazrs_Source,
+    reader: *mut *mut Lazrs_WaveformReader,
+) -> Lazrs_Result {
+    debug_assert!(!reader.is_null());
+    *reader = std::ptr::null_mut();
+    match open_reader(source_type, source, None) {
+        Ok(waveforms) => {
+            *reader = Box::into_raw(Box::new(waveforms));
+            Lazrs_Result::LAZRS_OK
+        }
+        Err(result) => result,
+    }
+}
+
+/// Opens the waveforms of a LAS file, with the waveform data packets read from another source
+///
+/// Like `lazrs_waveform_reader_open`, but the packets are always read from
+/// `wdp_source`, from its current position, whatever the header of the file says.
+/// `wdp_source` must support seeking and stay valid until the reader is freed.
+///
+/// @source_type: type of the source
+/// @source: where the LAS file is read from
+/// @wdp_source_type: type of the source of the packets
+/// @wdp_source: where the waveform data packets (usually a `.wdp` file) are read from
+/// @reader: will receive the reader, or NULL if an error occurred,
+///          to be freed with `lazrs_waveform_reader_delete`
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_waveform_reader_open_external(
+    source_type: Lazrs_SourceType,
+    source: Lazrs_Source,
+    wdp_source_type: Lazrs_SourceType,
+    wdp_source: Lazrs_Source,
+    reader: *mut *mut Lazrs_WaveformReader,
+) -> Lazrs_Result {
+    debug_assert!(!reader.is_null());
+    *reader = std::ptr::null_mut();
+    match open_reader(source_type, source, Some((wdp_source_type, wdp_source))) {
+        Ok(waveforms) => {
+            *reader = Box::into_raw(Box::new(waveforms));
+            Lazrs_Result::LAZRS_OK
+        }
+        Err(result) => result,
+    }
+}
+
+/// Frees the reader
+///
+/// @reader can be NULL (no-op)
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_waveform_reader_delete(reader: *mut Lazrs_WaveformReader) {
+    if !reader.is_null() {
+        let _ = Box::from_raw(reader);
+    }
+}
+
+/// Gets a wave packet descriptor of the file
+///
+/// @reader: must not be NULL
+/// @index: index of the descriptor, as in the wave packets of the points,
+///         LAZRS_OTHER is returned if the file has no such descriptor
+/// @descriptor: will receive the descriptor
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_waveform_reader_descriptor(
+    reader: *const Lazrs_WaveformReader,
+    index: u8,
+    descriptor: *mut Lazrs_WavePacketDescriptor,
+) -> Lazrs_Result {
+    debug_assert!(!reader.is_null());
+    debug_assert!(!descriptor.is_null());
+    match (*reader).descriptors[usize::from(index)] {
+        Some(found) => {
+            *descriptor = found;
+            Lazrs_Result::LAZRS_OK
+        }
+        None => Lazrs_Result::LAZRS_OTHER,
+    }
+}
+
+/// Reads the waveform samples of a point
+///
+/// The samples are given as stored, the descriptor
+/// has the gain and offset converting them to volts.
+/// LAZRS_OTHER is returned if the samples are compressed or do not have 8, 16 or 32 bits,
+/// if the file has no descriptor for the packet, if the packet is smaller than its samples
+/// or if its offset is out of range.
+///
+/// @reader: must not be NULL
+/// @packet: the wave packet of the point (see `lazrs_point_view_get`), must not be NULL
+/// @samples: will receive the samples, must not be NULL
+/// @len: number of samples the buffer can hold, LAZRS_OTHER is returned
+///       if it is less than the number of samples of the descriptor
+#[no_mangle]
+pub unsafe extern "C" fn lazrs_waveform_reader_read(
+    reader: *mut Lazrs_WaveformReader,
+    packet: *const Lazrs_WavePacket,
+    samples: *mut u32,
+    len: usize,
+) -> Lazrs_Result {
+    debug_assert!(!reader.is_null());
+    debug_assert!(!packet.is_null());
+    debug_assert!(!samples.is_null());
+    let samples = std::slice::from_raw_parts_mut(samples, len);
+    match (*reader).read_samples(&*packet, samples) {
+        Ok(()) => Lazrs_Result::LAZRS_OK,
+        Err(result) => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::las::Vlr;
+    use crate::Lazrs_Buffer;
+
+    /// Size of the LAS 1.4 header
+    const HEADER_SIZE: usize = 375;
+    /// Size of the header of an EVLR, the packets start after it
+    const EVLR_HEADER_SIZE: u64 = 60;
+
+    /// 16 bits samples, 4 of them
+    fn descriptor_data() -> Vec<u8> {
+        let mut data = vec![16, 0];
+        data.extend_from_slice(&4u32.to_le_bytes());
+        data.extend_from_slice(&1000u32.to_le_bytes());
+        data.extend_from_slice(&0.5f64.to_le_bytes());
+        data.extend_from_slice(&(-1.0f64).to_le_bytes());
+        data
+    }
+
+    /// The samples of the waveform data packets, 8 samples of 16 bits
+    fn packets() -> Vec<u8> {
+        (0..8u16).flat_map(|i| (1000 + i).to_le_bytes()).collect()
+    }
+
+    /// A LAS 1.4 file of format 4 without points, with the descriptor of index 1
+    /// and, if `internal`, the waveform data packets in an EVLR
+    fn las_file(internal: bool) -> Vec<u8> {
+        let vlr = Vlr::new("LASF_Spec", 100, "descriptor", descriptor_data());
+        let mut evlr = Vlr::new("LASF_Spec", 65535, "packets", packets());
+        evlr.extended = true;
+        let offset_to_point_data = HEADER_SIZE as u64 + vlr.size();
+
+        let mut data = vec![0u8; HEADER_SIZE];
+        data[..4].copy_from_slice(b"LASF");
+        let global_encoding: u16 = if internal { 1 << 1 } else { 1 << 2 };
+        data[6..8].copy_from_slice(&global_encoding.to_le_bytes());
+        data[24..26].copy_from_slice(&[1, 4]);
+        data[94..96].copy_from_slice(&(HEADER_SIZE as u16).to_le_bytes());
+        data[96..100].copy_from_slice(&(offset_to_point_data as u32).to_le_bytes());
+        data[100..104].copy_from_slice(&1u32.to_le_bytes());
+        data[104] = 4;
+        data[105..107].copy_from_slice(&57u16.to_le_bytes());
+        if internal {
+            data[227..235].copy_from_slice(&offset_to_point_data.to_le_bytes());
+            data[235..243].copy_from_slice(&offset_to_point_data.to_le_bytes());
+            data[243..247].copy_from_slice(&1u32.to_le_bytes());
+        }
+        vlr.write_to(&mut data).unwrap();
+        if internal {
+            evlr.write_to(&mut data).unwrap();
+        }
+        data
+    }
+
+    fn buffer_source(data: &[u8]) -> Lazrs_Source {
+        Lazrs_Source {
+            buffer: Lazrs_Buffer {
+                data: data.as_ptr(),
+                len: data.len(),
+            },
+        }
+    }
+
+    fn packet(descriptor_index: u8, byte_offset: u64, packet_size: u32) -> Lazrs_WavePacket {
+        Lazrs_WavePacket {
+            descriptor_index,
+            byte_offset,
+            packet_size,
+            ..Default::default()
+        }
+    }
+
+    /// Reads the samples of the packet, which must have 4 of them
+    fn read(
+        reader: *mut Lazrs_WaveformReader,
+        packet: Lazrs_WavePacket,
+    ) -> Result<[u32; 4], Lazrs_Result> {
+        let mut samples = [0u32; 4];
+        match unsafe { lazrs_waveform_reader_read(reader, &packet, samples.as_mut_ptr(), 4) } {
+            Lazrs_Result::LAZRS_OK => Ok(samples),
+            result => Err(result),
+        }
+    }
+
+    #[test]
+    fn samples_are_read_from_the_internal_packets() {
+        let las = las_file(true);
+        let mut reader = std::ptr::null_mut();
+        unsafe {
+            let result = lazrs_waveform_reader_open(
+                Lazrs_SourceType::LAZRS_SOURCE_BUFFER,
+                buffer_source(&las),
+                &mut reader,
+            );
+            assert_eq!(result, Lazrs_Result::LAZRS_OK);
+
+            let mut descriptor = Lazrs_WavePacketDescriptor::from_vlr_data(&[0; 26]).unwrap();
+            assert_eq!(
+                lazrs_waveform_reader_descriptor(reader, 1, &mut descriptor),
+                Lazrs_Result::LAZRS_OK
+            );
+            assert_eq!(descriptor.number_of_samples, 4);
+            assert_eq!(descriptor.digitizer_gain, 0.5);
+            assert_eq!(
+                lazrs_waveform_reader_descriptor(reader, 2, &mut descriptor),
+                Lazrs_Result::LAZRS_OTHER
+            );
+
+            // The byte offsets count the header of the EVLR
+            assert_eq!(
+                read(reader, packet(1, EVLR_HEADER_SIZE, 8)),
+                Ok([1000, 1001, 1002, 1003])
+            );
+            assert_eq!(
+                read(reader, packet(1, EVLR_HEADER_SIZE + 6, 8)),
+                Ok([1003, 1004, 1005, 1006])
+            );
+
+            // No such descriptor
+            assert_eq!(
+                read(reader, packet(2, EVLR_HEADER_SIZE, 8)),
+                Err(Lazrs_Result::LAZRS_OTHER)
+            );
+            // Packet smaller than the samples
+            assert_eq!(
+                read(reader, packet(1, EVLR_HEADER_SIZE, 7)),
+                Err(Lazrs_Result::LAZRS_OTHER)
+            );
+            assert_eq!(
+                read(reader, packet(1, u64::MAX, 8)),
+                Err(Lazrs_Result::LAZRS_OTHER)
+            );
+            // Past the end of the file
+            assert_eq!(
+                read(reader, packet(1, EVLR_HEADER_SIZE + 12, 8)),
+                Err(Lazrs_Result::LAZRS_IO_ERROR)
+            );
+            let mut samples = [0u32; 3];
+            let result = lazrs_waveform_reader_read(
+                reader,
+                &packet(1, EVLR_HEADER_SIZE, 8),
+                samples.as_mut_ptr(),
+                samples.len(),
+            );
+            assert_eq!(result, Lazrs_Result::LAZRS_OTHER);
+
+            lazrs_waveform_reader_delete(reader);
+        }
+    }
+
+    #[test]
+    fn samples_are_read_from_an_external_source() {
+        let las = las_file(false);
+        let wdp = packets();
+        let mut reader = std::ptr::null_mut();
+        unsafe {
+            // External packets are found next to a file, not a buffer
+            let result = lazrs_waveform_reader_open(
+                Lazrs_SourceType::LAZRS_SOURCE_BUFFER,
+                buffer_source(&las),
+                &mut reader,
+            );
+            assert_eq!(result, Lazrs_Result::LAZRS_OTHER);
+            assert!(reader.is_null());
+
+            let result = lazrs_waveform_reader_open_external(
+                Lazrs_SourceType::LAZRS_SOURCE_BUFFER,
+                buffer_source(&las),
+                Lazrs_SourceType::LAZRS_SOURCE_BUFFER,
+                buffer_source(&wdp),
+                &mut reader,
+            );
+            assert_eq!(result, Lazrs_Result::LAZRS_OK);
+            assert_eq!(read(reader, packet(1, 2, 8)), Ok([1001, 1002, 1003, 1004]));
+            lazrs_waveform_reader_delete(reader);
+        }
+    }
+
+    #[test]
+    fn unsupported_descriptors() {
+        let mut data = descriptor_data();
+        let descriptor = Lazrs_WavePacketDescriptor::from_vlr_data(&data).unwrap();
+        assert_eq!(descriptor.sample_size(), Some(2));
+        data[0] = 12;
+        let descriptor = Lazrs_WavePacketDescriptor::from_vlr_data(&data).unwrap();
+        assert_eq!(descriptor.sample_size(), None);
+        data[0] = 8;
+        data[1] = 1;
+        let descriptor = Lazrs_WavePacketDescriptor::from_vlr_data(&data).unwrap();
+        assert_eq!(descriptor.sample_size(), None);
+        assert!(Lazrs_WavePacketDescriptor::from_vlr_data(&data[..DESCRIPTOR_SIZE - 1]).is_none());
+    }
+}